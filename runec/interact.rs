@@ -16,11 +16,14 @@ Branch Follow Commands:
   T     Follow `True` branch
   F     Follow `False` branch
 
+Symbolic jumps prompt for the index of the target to follow.
+
 Interpretter Commands:
   C     Continue Execution
   S     Single Step Instruction
   D     Print Debug information
   ?     Add Assertion
+  E     Evaluate ESIL expression
   Q     Query Constraint Solver
   X     Add safety assertions
   H     Print Help Menu
@@ -37,6 +40,7 @@ pub enum Command {
     Query,
     Help,
     Safety,
+    Esil,
     Invalid,
 }
 
@@ -62,6 +66,7 @@ impl From<char> for Command {
             'Q' => Command::Query,
             'H' => Command::Help,
             'X' => Command::Safety,
+            'E' => Command::Esil,
             _ => Command::Invalid,
         }
     }
@@ -72,8 +77,8 @@ pub struct InteractiveExplorer {
     console: Console,
     cmd_q: Vec<Command>,
     single_step: bool,
-    // TODO: Remove this breakpointing feature once BPs are implemented.
-    pub bp: Vec<u64>,
+    /// ESIL expression entered at the prompt, to be evaluated by the driver.
+    esil: Option<String>,
 }
 
 impl InteractiveExplorer {
@@ -128,6 +133,13 @@ impl InteractiveExplorer {
                 _ => panic!("Invalid"),
             };
 
+            // Constants take the width of the first operand.
+            let width = if &tokens[1][0..1] == "[" {
+                64
+            } else {
+                ctx.reg_width(tokens[1]).expect("Unknown register")
+            };
+
             let op_1 = {
                 if &tokens[1][0..1] == "[" {
                     let addr = {
@@ -145,7 +157,7 @@ impl InteractiveExplorer {
                 if tokens[2].len() > 2 && &tokens[2][0..2] == "0x" {
                     let const_v = u64::from_str_radix(&tokens[2][2..], 16)
                                       .expect("Invalid base16 Integer");
-                    ctx.define_const(const_v, 64)
+                    ctx.define_const(const_v, width)
                 } else {
                     ctx.reg_read(tokens[2])
                 }
@@ -154,6 +166,89 @@ impl InteractiveExplorer {
             self.console.print_success("Constraint Added!");
         }
     }
+
+    // Ask for the index of the jump target to follow, out of `count`.
+    fn choose_target(&self, count: usize) -> usize {
+        loop {
+            self.console.print_info(&format!("Target to follow (0-{}):", count - 1));
+            match self.console.readline().ok().and_then(|line| line.trim().parse::<usize>().ok()) {
+                Some(i) if i < count => return i,
+                _ => self.console.print_error("Invalid target"),
+            }
+        }
+    }
+
+    /// ESIL expression entered at the prompt since the last call, if any.
+    pub fn take_esil(&mut self) -> Option<String> {
+        self.esil.take()
+    }
+
+    /// Print a possible value of each result of an evaluated ESIL expression.
+    pub fn print_esil_results(&self,
+                              ctx: &RuneContext,
+                              results: &[(<RuneContext as RegisterRead>::VarRef, usize)]) {
+        if results.is_empty() {
+            self.console.print_success("Evaluated!");
+        }
+        for (i, &(val, width)) in results.iter().enumerate() {
+            let mut ctx = ctx.clone();
+            let var = ctx.define_var(&format!("esil_result_{}", i), width);
+            ctx.eval(core::OpCodes::Cmp, vec![var, val]);
+            let mut z3: z3::Z3 = Default::default();
+            match ctx.try_solve(&mut z3).ok().and_then(|m| m.get(&var).cloned()) {
+                Some(v) => {
+                    self.console.print_success(&format!("{}: {:#x} ({} bits)", i, v, width))
+                }
+                None => self.console.print_error(&format!("{}: unsatisfiable", i)),
+            }
+        }
+    }
+
+    /// Drop into the interactive prompt at the current instruction. Returns when the user asks
+    /// to continue, to single step or to evaluate an ESIL expression.
+    pub fn halt(&mut self, ctx: &mut RuneContext) {
+        self.console.print_info(&format!("Halted at {:#x}", ctx.ip()));
+        loop {
+            self.single_step = match self.console.read_command()[0] {
+                Command::Step => true,
+                Command::Continue => false,
+                Command::Debug => {
+                    self.print_debug(ctx);
+                    continue;
+                }
+                Command::Assertion => {
+                    self.add_assertion(ctx);
+                    continue;
+                }
+                Command::Query => {
+                    self.query_constraints(ctx);
+                    continue;
+                }
+                Command::Help => {
+                    self.help();
+                    continue;
+                }
+                Command::Safety => {
+                    self.safety(ctx);
+                    continue;
+                }
+                Command::Esil => {
+                    self.console.print_info("ESIL expression, for example: rax,rbx,+,rcx,=");
+                    match self.console.readline() {
+                        Ok(ref line) if !line.trim().is_empty() => {
+                            self.esil = Some(line.trim().to_owned());
+                            return;
+                        }
+                        _ => continue,
+                    }
+                }
+                _ => {
+                    continue;
+                }
+            };
+            break;
+        }
+    }
 }
 
 impl PathExplorer for InteractiveExplorer {
@@ -165,42 +260,16 @@ impl PathExplorer for InteractiveExplorer {
             cmd_q: Vec::new(),
             console: Default::default(),
             single_step: false,
-            bp: Vec::new(),
+            esil: None,
         }
     }
 
     fn next(&mut self, ctx: &mut Self::Ctx) -> RuneControl {
-        if self.single_step || self.bp.contains(&ctx.ip()) {
-            self.console.print_info(&format!("Halted at {:#x}", ctx.ip()));
-            loop {
-                self.single_step = match self.console.read_command()[0] {
-                    Command::Step => true,
-                    Command::Continue => false,
-                    Command::Debug => {
-                        self.print_debug(ctx);
-                        continue;
-                    }
-                    Command::Assertion => {
-                        self.add_assertion(ctx);
-                        continue;
-                    }
-                    Command::Query => {
-                        self.query_constraints(ctx);
-                        continue;
-                    }
-                    Command::Help => {
-                        self.help();
-                        continue;
-                    }
-                    Command::Safety => {
-                        self.safety(ctx);
-                        continue;
-                    }
-                    _ => {
-                        continue;
-                    }
-                };
-                break;
+        if self.single_step {
+            self.halt(ctx);
+            // Let the driver evaluate the expression, it halts again afterwards.
+            if self.esil.is_some() {
+                return RuneControl::Break;
             }
         }
         RuneControl::Continue
@@ -222,12 +291,12 @@ impl PathExplorer for InteractiveExplorer {
         if let Some(cmd) = self.cmd_q.pop() {
             match cmd {
                 Command::FollowTrue => {
-                    let one = ctx.define_const(1, 64);
+                    let one = ctx.define_const(1, 1);
                     ctx.eval(core::OpCodes::Cmp, &[condition, one]);
                     RuneControl::ExploreTrue
                 }
                 Command::FollowFalse => {
-                    let zero = ctx.define_const(0, 64);
+                    let zero = ctx.define_const(0, 1);
                     ctx.eval(core::OpCodes::Cmp, &[condition, zero]);
                     RuneControl::ExploreFalse
                 }
@@ -237,4 +306,21 @@ impl PathExplorer for InteractiveExplorer {
             unreachable!()
         }
     }
+
+    fn register_targets(&mut self,
+                        ctx: &mut Self::Ctx,
+                        mut targets: Vec<Self::Ctx>)
+                        -> RuneControl {
+        if targets.is_empty() {
+            return RuneControl::TerminatePath;
+        }
+        self.console.print_info(&format!("Symbolic jump with {} feasible targets:", targets.len()));
+        for (i, target) in targets.iter().enumerate() {
+            self.console.print_info(&format!("  {}: {:#x}", i, target.ip()));
+        }
+        let choice = self.choose_target(targets.len());
+        *ctx = targets.swap_remove(choice);
+        self.console.print_info(&format!("Following {:#x}", ctx.ip()));
+        RuneControl::Continue
+    }
 }
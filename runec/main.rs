@@ -15,12 +15,22 @@ mod console;
 use std::process::exit;
 use docopt::Docopt;
 use std::collections::HashMap;
-use rune::context::utils;
+use rune::context::rune_ctx::RuneContext;
+use rune::context::arch::ArchInfo;
+use rune::context::callconv::CallConv;
+use rune::context::context::{Context, ContextAPI, Evaluate};
+use rune::context::frame::StackFrame;
+use rune::context::image::MemImage;
+use rune::context::region::MemRegion;
 use rune::explorer::explorer::PathExplorer;
-use rune::engine::rune::Rune;
+use rune::engine::rune::{Rune, RuneControl};
+use rune::engine::summary::imports_r2;
 use rune::engine::engine::Engine;
 use interact::InteractiveExplorer;
 use r2pipe::r2::R2;
+use r2pipe::structs::LOpInfo;
+use libsmt::backends::z3;
+use libsmt::theories::core;
 use rune::stream::InstructionStream;
 
 static USAGE: &'static str = "
@@ -31,10 +41,19 @@ Usage:
 Options:
   -e --end=<end_addr>                    Address to end emulation at.
   -s --start=<start_addr>                Address to start emulation at.
+  -a --arch=<arch>                       Architecture of the code. One of x86, x86-64, arm,
+                                         thumb, aarch64, mips, mipsel. Defaults to x86-64.
   --const=<const_vars>                   Key:Value pairs.
                                          Example: --const=rbp:0x1000,rsp:0x1100
   --sym=<sym_vars>                       Registers/Memory address to be set as symbolic.
                                          Example: --sym=rsi,rdi,0x1000
+  --args=<args>                          Arguments of the function at the start address, in
+                                         order. Each is a constant, `sym` for a symbolic word
+                                         or `sym:<bits>`. Example: --args=sym,0x1000,sym:8
+  --frame                                Set up a stack frame for the function at the start
+                                         address. Paths end when the function returns.
+  --callconv=<cc>                        Calling convention. One of sysv, cdecl, stdcall,
+                                         fastcall, aapcs. Defaults to that of the architecture.
   -b --break=<bp_list>                   Set breakpoints at addresses.
   --reset                                Set all unset registers (symbolic / constant) to 0
   --save                                 Save current configuration to a r2 project
@@ -48,14 +67,73 @@ struct Args {
     flag_break: Option<String>,
     flag_sym: Option<String>,
     flag_const: Option<String>,
+    flag_args: Option<String>,
+    flag_callconv: Option<String>,
+    flag_frame: bool,
     flag_start: Option<u64>,
     flag_end: Option<u64>,
+    flag_arch: Option<String>,
     flag_reset: bool,
     flag_save: bool,
     flag_project: Option<String>,
     arg_file: Option<String>,
 }
 
+// Drop into the prompt until the user resumes execution. ESIL expressions entered at the prompt
+// are evaluated on the current context.
+fn halt<S>(rune: &mut Rune<RuneContext, InteractiveExplorer, S>)
+    where S: InstructionStream<Output = LOpInfo, Index = u64>
+{
+    // An expression may already be pending if the explorer halted while single stepping.
+    let mut esil = rune.with_explorer(|explorer, _| explorer.take_esil());
+    if esil.is_none() {
+        esil = rune.with_explorer(|explorer, ctx| {
+            explorer.halt(ctx);
+            explorer.take_esil()
+        });
+    }
+    while let Some(expr) = esil {
+        match rune.eval_esil(&expr) {
+            Ok(results) => {
+                rune.with_explorer(|explorer, ctx| explorer.print_esil_results(ctx, &results))
+            }
+            Err(e) => println!("{}", e),
+        }
+        esil = rune.with_explorer(|explorer, ctx| {
+            explorer.halt(ctx);
+            explorer.take_esil()
+        });
+    }
+}
+
+// Print the outcome of a path on which the function at the start address returned: the return
+// value and the values of the symbols that lead to it.
+fn report_return(ctx: &RuneContext) {
+    let mut ctx = ctx.clone();
+    let ret = match ctx.return_value() {
+        Ok(ret) => ret,
+        Err(e) => {
+            println!("Function returned: {}", e);
+            return;
+        }
+    };
+    let width = ctx.call_conv().map_or(64, |cc| cc.word_size());
+    let var = ctx.define_var("return_value", width);
+    ctx.eval(core::OpCodes::Cmp, vec![var, ret]);
+    let mut z3: z3::Z3 = Default::default();
+    match ctx.try_solve(&mut z3) {
+        Ok(model) => {
+            println!("Function returned {:#x}", model.get(&var).cloned().unwrap_or(0));
+            for (name, sym) in ctx.symbols() {
+                if let Some(value) = model.get(&sym) {
+                    println!("  {} = {:#x}", name, value);
+                }
+            }
+        }
+        Err(e) => println!("Function returned: {}", e),
+    }
+}
+
 fn main() {
     let args: Args = Docopt::new(USAGE).and_then(|d| d.decode()).unwrap_or_else(|e| e.exit());
 
@@ -64,18 +142,29 @@ fn main() {
         exit(0);
     }
 
+    let arch = args.flag_arch
+                   .as_ref()
+                   .map(|a| a.parse::<ArchInfo>().unwrap_or_else(|e| panic!("{}", e)))
+                   .unwrap_or_default();
+
+    let has_file = args.arg_file.is_some();
     let mut stream = R2::new(args.arg_file).expect("Unable to spawn r2");
     stream.init();
+    if args.flag_arch.is_some() {
+        arch.configure(&mut stream);
+    }
 
     let sym_vars = args.flag_sym
                        .unwrap_or_default()
                        .split(',')
+                       .filter(|x| !x.is_empty())
                        .map(|x| x.to_owned())
                        .collect::<Vec<String>>();
 
     let const_vars = args.flag_const
                          .unwrap_or_default()
                          .split(',')
+                         .filter(|x| !x.is_empty())
                          .map(|x_| {
                              let x = x_.to_owned();
                              let mut substr = x.split(':').take(2);
@@ -93,9 +182,17 @@ fn main() {
                          })
                          .collect::<HashMap<_, _>>();
 
+    let fn_args = args.flag_args
+                      .unwrap_or_default()
+                      .split(',')
+                      .filter(|x| !x.is_empty())
+                      .map(|x| x.to_owned())
+                      .collect::<Vec<String>>();
+
     let mut breakpoints = args.flag_break
                               .unwrap_or_default()
                               .split(',')
+                              .filter(|x| !x.is_empty())
                               .map(|x| {
                                   let b = x.to_owned();
                                   if b.starts_with("0x") {
@@ -121,10 +218,88 @@ fn main() {
     // args.flag_start.unwrap()
     // };
 
-    let ctx = utils::new_ctx(args.flag_start, Some(sym_vars), Some(const_vars));
-    let mut explorer = InteractiveExplorer::new();
-    explorer.bp = breakpoints;
+    let mut builder = RuneContext::builder().arch(arch).zero_registers(args.flag_reset);
+    // Accesses outside of the sections of the binary are reported. Memory initially holds the
+    // contents of the sections.
+    if has_file {
+        match MemRegion::from_r2(&mut stream) {
+            Ok(regions) => {
+                for region in regions {
+                    builder = builder.region(region);
+                }
+            }
+            Err(e) => println!("Unable to load sections: {}", e),
+        }
+        match MemImage::from_r2(&mut stream) {
+            Ok(image) => builder = builder.image(image),
+            Err(e) => println!("Unable to load section contents: {}", e),
+        }
+    }
+    // Calls to imported functions run their summaries, where there is one.
+    let imports = if has_file {
+        imports_r2(&mut stream).unwrap_or_else(|e| {
+            println!("Unable to load imports: {}", e);
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    if let Some(start) = args.flag_start {
+        builder = builder.ip(start);
+    }
+    for var in sym_vars {
+        builder = builder.sym_key(var);
+    }
+    for (var, value) in const_vars {
+        builder = builder.const_key(var, value);
+    }
+    if args.flag_frame {
+        builder = builder.frame(StackFrame::for_arch(&arch));
+    }
+    if let Some(ref cc) = args.flag_callconv {
+        builder = builder.call_conv(cc.parse::<CallConv>().unwrap_or_else(|e| panic!("{}", e)));
+    }
+    for (i, arg) in fn_args.iter().enumerate() {
+        builder = if arg == "sym" {
+            builder.sym_arg(i, arch.addr_width())
+        } else if arg.starts_with("sym:") {
+            builder.sym_arg(i, arg[4..].parse().expect("Invalid argument width"))
+        } else if arg.starts_with("0x") {
+            builder.const_arg(i,
+                              u64::from_str_radix(&arg[2..], 16).expect("Invalid base16 integer"))
+        } else {
+            builder.const_arg(i, u64::from_str_radix(arg, 10).expect("Invalid base10 integer"))
+        };
+    }
+    let ctx = builder.build().unwrap_or_else(|e| {
+        println!("{}", e);
+        exit(1)
+    });
+    let explorer = InteractiveExplorer::new();
 
     let mut rune = Rune::new(ctx, explorer, stream);
-    rune.run().expect("Rune Error:");
+    for addr in breakpoints {
+        rune.breakpoints_mut().add(addr);
+    }
+    for (addr, name) in imports {
+        rune.summaries_mut().add_target(addr, name);
+    }
+
+    loop {
+        match rune.run() {
+            Ok(RuneControl::Break) => halt(&mut rune),
+            Ok(_) => break,
+            Err(e) => {
+                println!("{}", e);
+                exit(1)
+            }
+        }
+    }
+
+    for e in rune.errors() {
+        println!("Path terminated: {}", e);
+    }
+    for ctx in rune.returned() {
+        report_return(ctx);
+    }
 }
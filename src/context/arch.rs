@@ -0,0 +1,132 @@
+//! Architectures supported by rune.
+//!
+//! `ArchInfo` describes the code that is being executed the same way radare2 does, through
+//! `asm.arch`, `asm.bits` and `cfg.bigendian`. It determines the register profile, the width of
+//! addresses and the byte order of memory.
+
+use std::str::FromStr;
+
+use r2pipe::r2::R2;
+
+use context::rune_ctx::Endianness;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arch {
+    X86,
+    Arm,
+    Mips,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArchInfo {
+    pub arch: Arch,
+    /// Value of `asm.bits`. On ARM, 16 selects Thumb and 64 selects AArch64.
+    pub bits: usize,
+    pub endian: Endianness,
+}
+
+impl Default for ArchInfo {
+    fn default() -> ArchInfo {
+        ArchInfo::x86_64()
+    }
+}
+
+impl ArchInfo {
+    pub fn new(arch: Arch, bits: usize, endian: Endianness) -> ArchInfo {
+        ArchInfo {
+            arch: arch,
+            bits: bits,
+            endian: endian,
+        }
+    }
+
+    pub fn x86() -> ArchInfo {
+        ArchInfo::new(Arch::X86, 32, Endianness::Little)
+    }
+
+    pub fn x86_64() -> ArchInfo {
+        ArchInfo::new(Arch::X86, 64, Endianness::Little)
+    }
+
+    pub fn arm() -> ArchInfo {
+        ArchInfo::new(Arch::Arm, 32, Endianness::Little)
+    }
+
+    pub fn thumb() -> ArchInfo {
+        ArchInfo::new(Arch::Arm, 16, Endianness::Little)
+    }
+
+    pub fn aarch64() -> ArchInfo {
+        ArchInfo::new(Arch::Arm, 64, Endianness::Little)
+    }
+
+    pub fn mips() -> ArchInfo {
+        ArchInfo::new(Arch::Mips, 32, Endianness::Big)
+    }
+
+    pub fn mipsel() -> ArchInfo {
+        ArchInfo::new(Arch::Mips, 32, Endianness::Little)
+    }
+
+    /// Width of addresses and of the program counter in bits.
+    pub fn addr_width(&self) -> usize {
+        match (self.arch, self.bits) {
+            // Thumb instructions are 16 bits wide, but addresses are not.
+            (Arch::Arm, 16) => 32,
+            (_, bits) => bits,
+        }
+    }
+
+    /// Mask of the bits of an address.
+    pub fn addr_mask(&self) -> u64 {
+        if self.addr_width() >= 64 {
+            u64::max_value()
+        } else {
+            (1 << self.addr_width()) - 1
+        }
+    }
+
+    /// True if writes to the lower 32 bits of a 64-bit register clear the upper 32 bits, as
+    /// they do on x86-64 and AArch64.
+    pub fn zero_extends_32(&self) -> bool {
+        self.bits == 64 && (self.arch == Arch::X86 || self.arch == Arch::Arm)
+    }
+
+    /// Name of the architecture in radare2 (`asm.arch`).
+    pub fn r2_arch(&self) -> &'static str {
+        match self.arch {
+            Arch::X86 => "x86",
+            Arch::Arm => "arm",
+            Arch::Mips => "mips",
+        }
+    }
+
+    /// Configure an instance of radare2 for this architecture.
+    pub fn configure(&self, r2: &mut R2) {
+        r2.send(&format!("e asm.arch = {}", self.r2_arch()));
+        r2.flush();
+        r2.send(&format!("e asm.bits = {}", self.bits));
+        r2.flush();
+        r2.send(&format!("e cfg.bigendian = {}", self.endian == Endianness::Big));
+        r2.flush();
+    }
+}
+
+impl FromStr for ArchInfo {
+    type Err = String;
+
+    /// Parse names such as `x86`, `x86-64`, `arm`, `thumb`, `aarch64`, `mips` and `mipsel`.
+    fn from_str(s: &str) -> Result<ArchInfo, String> {
+        let arch = match &s.to_lowercase()[..] {
+            "x86" | "x86-32" | "i386" => ArchInfo::x86(),
+            "x86-64" | "x86_64" | "x64" | "amd64" => ArchInfo::x86_64(),
+            "arm" | "arm32" => ArchInfo::arm(),
+            "thumb" => ArchInfo::thumb(),
+            "aarch64" | "arm64" => ArchInfo::aarch64(),
+            "mips" | "mipsbe" => ArchInfo::mips(),
+            "mipsel" => ArchInfo::mipsel(),
+            _ => return Err(format!("Unsupported architecture: {}", s)),
+        };
+        Ok(arch)
+    }
+}
@@ -0,0 +1,642 @@
+//! Builder for `RuneContext`.
+//!
+//! ```ignore
+//! let ctx = RuneContext::builder()
+//!               .arch(ArchInfo::x86())
+//!               .ip(0x8048000)
+//!               .sym_reg("eax")
+//!               .sym_buffer("input", 0x3000, 16)
+//!               .frame(StackFrame::for_arch(&ArchInfo::x86()))
+//!               .const_arg(0, 0x3000)
+//!               .build()
+//!               .expect("Invalid context");
+//! ```
+//!
+//! Nothing is checked until `build` is called, which reports the first invalid setting as a
+//! `BuildError`.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use libsmt::backends::smtlib2::SMTLib2;
+use libsmt::logics::qf_abv;
+
+use context::arch::ArchInfo;
+use context::callconv::CallConv;
+use context::context::{AddrStrategy, Context, ContextAPI, ContextError, MemoryWrite};
+use context::frame::StackFrame;
+use context::image::MemImage;
+use context::reg_profile::{ProfileError, RegProfile};
+use context::region::MemRegion;
+use context::rune_ctx::{RuneContext, RuneMemory, RuneRegFile, UninitPolicy};
+use context::utils::{self, Key};
+
+#[derive(Debug)]
+pub enum BuildError {
+    /// There is no bundled register profile for the architecture and none was given.
+    NoRegProfile(ArchInfo),
+    /// The register profile could not be loaded.
+    Profile(ProfileError),
+    UnknownRegister(String),
+    /// The register, or a register overlapping it, was set more than once.
+    RegisterSetTwice(String),
+    /// The value does not fit in the register or memory access.
+    ValueTooWide { target: String, value: u64, width: usize },
+    /// Memory accesses must be a whole number of bytes, and values at most 64 bits wide.
+    InvalidSize(usize),
+    /// A string that is neither a register name nor a memory address.
+    InvalidKey(String),
+    /// A memory image could not be read.
+    Io(PathBuf, io::Error),
+    /// A symbolic argument must be between 1 bit and a word wide.
+    InvalidArgWidth(usize, usize),
+    /// The argument could not be set, for example because the stack pointer is not set.
+    Arg(usize, ContextError),
+    /// The stack frame could not be set up.
+    Frame(ContextError),
+    /// The context rejected an initial value.
+    Context(ContextError),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildError::NoRegProfile(ref arch) => {
+                write!(f, "No register profile available for {:?}", arch)
+            }
+            BuildError::Profile(ref e) => write!(f, "Unable to load register profile: {:?}", e),
+            BuildError::UnknownRegister(ref reg) => write!(f, "Unknown register `{}`", reg),
+            BuildError::RegisterSetTwice(ref reg) => {
+                write!(f, "Register `{}` (or a register overlapping it) is set twice", reg)
+            }
+            BuildError::ValueTooWide { ref target, value, width } => {
+                write!(f, "{:#x} does not fit in {} ({} bits)", value, target, width)
+            }
+            BuildError::InvalidSize(size) => {
+                write!(f, "Invalid size of {} bits for a memory access", size)
+            }
+            BuildError::InvalidKey(ref key) => {
+                write!(f, "`{}` is neither a register nor a memory address", key)
+            }
+            BuildError::Io(ref path, ref e) => {
+                write!(f, "Unable to read {}: {}", path.display(), e)
+            }
+            BuildError::InvalidArgWidth(i, width) => {
+                write!(f, "Invalid width of {} bits for argument {}", width, i)
+            }
+            BuildError::Arg(i, ref e) => write!(f, "Unable to set argument {}: {}", i, e),
+            BuildError::Frame(ref e) => write!(f, "Unable to set up the stack frame: {}", e),
+            BuildError::Context(ref e) => write!(f, "Unable to set an initial value: {}", e),
+        }
+    }
+}
+
+impl From<ProfileError> for BuildError {
+    fn from(e: ProfileError) -> BuildError {
+        BuildError::Profile(e)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum RegInit {
+    Const(u64),
+    Sym,
+}
+
+#[derive(Clone, Debug)]
+enum MemInit {
+    /// Value and size in bits.
+    Const(u64, usize),
+    /// Size in bits.
+    Sym(usize),
+    /// Named symbolic buffer of `len` bytes.
+    Buffer(String, usize),
+    Image(Vec<u8>),
+    ImageFile(PathBuf),
+}
+
+#[derive(Clone, Debug)]
+enum Init {
+    Reg(String, RegInit),
+    Mem(u64, MemInit),
+    /// Register or memory address given as a string, see `utils::to_key`.
+    Key(String, RegInit),
+}
+
+#[derive(Clone, Debug)]
+enum ArgInit {
+    Const(u64),
+    /// Width in bits.
+    Sym(usize),
+}
+
+#[derive(Clone, Debug)]
+pub struct ContextBuilder {
+    arch: ArchInfo,
+    ip: Option<u64>,
+    profile: Option<RegProfile>,
+    inits: Vec<Init>,
+    zero_registers: bool,
+    uninit: UninitPolicy,
+    addr_strategy: AddrStrategy,
+    regions: Vec<MemRegion>,
+    image: MemImage,
+    /// Raw files to add to the image, and the addresses they are mapped at.
+    image_files: Vec<(u64, PathBuf)>,
+    call_conv: Option<CallConv>,
+    frame: Option<StackFrame>,
+    /// Arguments of the function at the start address. They are set after everything else, so
+    /// that stack arguments are written relative to the stack pointer that has been set.
+    args: Vec<(usize, ArgInit)>,
+}
+
+impl Default for ContextBuilder {
+    fn default() -> ContextBuilder {
+        ContextBuilder::new()
+    }
+}
+
+impl ContextBuilder {
+    /// A builder for an x86-64 context, starting at address 0.
+    pub fn new() -> ContextBuilder {
+        ContextBuilder {
+            arch: ArchInfo::default(),
+            ip: None,
+            profile: None,
+            inits: Vec::new(),
+            zero_registers: false,
+            uninit: UninitPolicy::default(),
+            addr_strategy: AddrStrategy::default(),
+            regions: Vec::new(),
+            image: MemImage::new(),
+            image_files: Vec::new(),
+            call_conv: None,
+            frame: None,
+            args: Vec::new(),
+        }
+    }
+
+    pub fn arch(mut self, arch: ArchInfo) -> ContextBuilder {
+        self.arch = arch;
+        self
+    }
+
+    /// Change the bitness (`asm.bits`) of the architecture.
+    pub fn bits(mut self, bits: usize) -> ContextBuilder {
+        self.arch.bits = bits;
+        self
+    }
+
+    pub fn ip(mut self, ip: u64) -> ContextBuilder {
+        self.ip = Some(ip);
+        self
+    }
+
+    /// Use `profile` instead of the register profile bundled for the architecture.
+    pub fn reg_profile(mut self, profile: RegProfile) -> ContextBuilder {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Set a register to a constant. Setting the program counter sets the start address.
+    pub fn const_reg<T: AsRef<str>>(mut self, reg: T, value: u64) -> ContextBuilder {
+        self.inits.push(Init::Reg(reg.as_ref().to_owned(), RegInit::Const(value)));
+        self
+    }
+
+    /// Make a register symbolic. The symbol is named after the register.
+    pub fn sym_reg<T: AsRef<str>>(mut self, reg: T) -> ContextBuilder {
+        self.inits.push(Init::Reg(reg.as_ref().to_owned(), RegInit::Sym));
+        self
+    }
+
+    /// Write a constant of `size` bits to memory.
+    pub fn const_mem(mut self, addr: u64, value: u64, size: usize) -> ContextBuilder {
+        self.inits.push(Init::Mem(addr, MemInit::Const(value, size)));
+        self
+    }
+
+    /// Make `size` bits of memory a single symbol, named `mem_<addr in hex>`.
+    pub fn sym_mem(mut self, addr: u64, size: usize) -> ContextBuilder {
+        self.inits.push(Init::Mem(addr, MemInit::Sym(size)));
+        self
+    }
+
+    /// Make `len` bytes of memory symbolic. Every byte is a symbol of its own, named
+    /// `<name>[<i>]`.
+    pub fn sym_buffer<T: AsRef<str>>(mut self, name: T, addr: u64, len: usize) -> ContextBuilder {
+        self.inits.push(Init::Mem(addr, MemInit::Buffer(name.as_ref().to_owned(), len)));
+        self
+    }
+
+    /// Initialize memory at `addr` with `bytes`.
+    pub fn mem_image(mut self, addr: u64, bytes: &[u8]) -> ContextBuilder {
+        self.inits.push(Init::Mem(addr, MemInit::Image(bytes.to_vec())));
+        self
+    }
+
+    /// Initialize memory at `addr` with the contents of the file at `path`.
+    pub fn mem_image_file<P: AsRef<Path>>(mut self, addr: u64, path: P) -> ContextBuilder {
+        self.inits.push(Init::Mem(addr, MemInit::ImageFile(path.as_ref().to_path_buf())));
+        self
+    }
+
+    /// Add the segments of `image` to the initial contents of memory. Unlike `mem_image`, the
+    /// bytes are only loaded into the context a page at a time, as they are read.
+    pub fn image(mut self, image: MemImage) -> ContextBuilder {
+        self.image.extend(image);
+        self
+    }
+
+    /// Add the contents of the file at `path`, mapped at `addr`, to the image. See `image`.
+    pub fn image_file<P: AsRef<Path>>(mut self, addr: u64, path: P) -> ContextBuilder {
+        self.image_files.push((addr, path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Set a register (`rax`) or a word of memory (`0x1000`, `4096`) to a constant.
+    pub fn const_key<T: AsRef<str>>(mut self, key: T, value: u64) -> ContextBuilder {
+        self.inits.push(Init::Key(key.as_ref().to_owned(), RegInit::Const(value)));
+        self
+    }
+
+    /// Make a register (`rax`) or a word of memory (`0x1000`, `4096`) symbolic.
+    pub fn sym_key<T: AsRef<str>>(mut self, key: T) -> ContextBuilder {
+        self.inits.push(Init::Key(key.as_ref().to_owned(), RegInit::Sym));
+        self
+    }
+
+    /// Calling convention of the functions executed, instead of the usual one of the
+    /// architecture.
+    pub fn call_conv(mut self, cc: CallConv) -> ContextBuilder {
+        self.call_conv = Some(cc);
+        self
+    }
+
+    /// Set up a stack frame for the function at the start address: the stack pointer, the return
+    /// address and a zero frame pointer, unless it is set. The stack is added to the regions if
+    /// there are any. The engine ends paths on which the function returns.
+    pub fn frame(mut self, frame: StackFrame) -> ContextBuilder {
+        self.frame = Some(frame);
+        self
+    }
+
+    /// Set the `i`th argument of the function at the start address to a constant, according to
+    /// the calling convention.
+    pub fn const_arg(mut self, i: usize, value: u64) -> ContextBuilder {
+        self.args.push((i, ArgInit::Const(value)));
+        self
+    }
+
+    /// Make the `i`th argument of the function at the start address a symbol of `width` bits,
+    /// named `arg<i>`. See `ContextAPI::set_arg_sym`.
+    pub fn sym_arg(mut self, i: usize, width: usize) -> ContextBuilder {
+        self.args.push((i, ArgInit::Sym(width)));
+        self
+    }
+
+    /// Set registers that have not been set to zero.
+    pub fn zero_registers(mut self, zero: bool) -> ContextBuilder {
+        self.zero_registers = zero;
+        self
+    }
+
+    /// Value of registers and memory that are read before being written.
+    pub fn uninit(mut self, policy: UninitPolicy) -> ContextBuilder {
+        self.uninit = policy;
+        self
+    }
+
+    /// How memory accesses at symbolic addresses are handled.
+    pub fn addr_strategy(mut self, strategy: AddrStrategy) -> ContextBuilder {
+        self.addr_strategy = strategy;
+        self
+    }
+
+    /// Declare a mapped region of memory, see `RuneContext::add_region`.
+    pub fn region(mut self, region: MemRegion) -> ContextBuilder {
+        self.regions.push(region);
+        self
+    }
+
+    pub fn build(self) -> Result<RuneContext, BuildError> {
+        let arch = self.arch;
+        let profile = match self.profile {
+            Some(profile) => profile,
+            None => try!(RegProfile::builtin(&arch).ok_or(BuildError::NoRegProfile(arch))),
+        };
+        let mut regfile = RuneRegFile::from_profile(&profile);
+        regfile.set_zero_extend_32(arch.zero_extends_32());
+
+        let mem = RuneMemory::with_arch(&arch);
+        let smt = SMTLib2::new(Some(qf_abv::QF_ABV));
+        let mut ctx = RuneContext::new(self.ip, mem, regfile, smt, arch);
+        ctx.set_uninit_policy(self.uninit);
+        ctx.set_addr_strategy(self.addr_strategy);
+        if let Some(cc) = self.call_conv {
+            ctx.set_call_conv(cc);
+        }
+        for region in self.regions {
+            ctx.add_region(region);
+        }
+        let mut image = self.image;
+        for (addr, path) in self.image_files {
+            match MemImage::from_file(addr, &path) {
+                Ok(file) => image.extend(file),
+                Err(e) => return Err(BuildError::Io(path, e)),
+            }
+        }
+        ctx.set_image(image);
+
+        let word = arch.addr_width();
+        for init in self.inits {
+            match init {
+                Init::Reg(reg, init) => try!(init_reg(&mut ctx, &reg, init)),
+                Init::Mem(addr, init) => try!(init_mem(&mut ctx, addr, init)),
+                Init::Key(key, init) => {
+                    match utils::to_key(&key) {
+                        Some(Key::Reg(reg)) => try!(init_reg(&mut ctx, &reg, init)),
+                        Some(Key::Mem(addr)) => {
+                            let init = match init {
+                                RegInit::Const(v) => MemInit::Const(v, word),
+                                RegInit::Sym => MemInit::Sym(word),
+                            };
+                            try!(init_mem(&mut ctx, addr as u64, init));
+                        }
+                        None => return Err(BuildError::InvalidKey(key)),
+                    }
+                }
+            }
+        }
+
+        if let Some(frame) = self.frame {
+            try!(init_frame(&mut ctx, frame));
+        }
+        for (i, init) in self.args {
+            try!(init_arg(&mut ctx, i, init));
+        }
+
+        if self.zero_registers {
+            ctx.zero_registers();
+        }
+        Ok(ctx)
+    }
+}
+
+fn check_fits(target: &str, value: u64, width: usize) -> Result<(), BuildError> {
+    if width < 64 && value >> width != 0 {
+        Err(BuildError::ValueTooWide {
+            target: target.to_owned(),
+            value: value,
+            width: width,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+fn check_size(size: usize) -> Result<(), BuildError> {
+    if size == 0 || size % 8 != 0 || size > 64 {
+        Err(BuildError::InvalidSize(size))
+    } else {
+        Ok(())
+    }
+}
+
+fn init_reg(ctx: &mut RuneContext, reg: &str, init: RegInit) -> Result<(), BuildError> {
+    let width = try!(ctx.reg_width(reg)
+                        .ok_or_else(|| BuildError::UnknownRegister(reg.to_owned())));
+    if ctx.alias_of(reg.to_owned()) == Some("PC".to_owned()) {
+        if let RegInit::Const(value) = init {
+            try!(check_fits(reg, value, width));
+            ctx.set_ip(value);
+            return Ok(());
+        }
+    }
+    let result = match init {
+        RegInit::Const(value) => {
+            try!(check_fits(reg, value, width));
+            ctx.set_reg_as_const(reg, value)
+        }
+        RegInit::Sym => ctx.set_reg_as_sym(reg),
+    };
+    match result {
+        Ok(_) => Ok(()),
+        Err(ContextError::RegisterAlreadySet(_)) => {
+            Err(BuildError::RegisterSetTwice(reg.to_owned()))
+        }
+        Err(e) => Err(BuildError::Context(e)),
+    }
+}
+
+fn init_frame(ctx: &mut RuneContext, frame: StackFrame) -> Result<(), BuildError> {
+    let cc = try!(ctx.call_conv().ok_or(BuildError::Frame(ContextError::NoCallConv)));
+    let sp = cc.stack_pointer();
+    if ctx.is_reg_set(sp) {
+        return Err(BuildError::RegisterSetTwice(sp.to_owned()));
+    }
+    try!(ctx.set_reg_as_const(sp, frame.sp).map_err(BuildError::Frame));
+    match cc.link_reg() {
+        Some(lr) => {
+            if ctx.is_reg_set(lr) {
+                return Err(BuildError::RegisterSetTwice(lr.to_owned()));
+            }
+            try!(ctx.set_reg_as_const(lr, frame.ret_addr).map_err(BuildError::Frame));
+        }
+        None => {
+            let size = cc.word_size() as u64;
+            try!(ctx.set_mem_as_const(frame.sp as usize, frame.ret_addr, size)
+                    .map_err(BuildError::Frame));
+        }
+    }
+    if !ctx.is_reg_set(cc.frame_pointer()) {
+        try!(ctx.set_reg_as_const(cc.frame_pointer(), 0).map_err(BuildError::Frame));
+    }
+    if !ctx.regions().is_empty() {
+        ctx.add_region(frame.region());
+    }
+    ctx.set_entry_frame(frame);
+    Ok(())
+}
+
+fn init_arg(ctx: &mut RuneContext, i: usize, init: ArgInit) -> Result<(), BuildError> {
+    let cc = try!(ctx.call_conv().ok_or(BuildError::Arg(i, ContextError::NoCallConv)));
+    let word = cc.word_size();
+    let result = match init {
+        ArgInit::Const(value) => {
+            try!(check_fits(&format!("argument {}", i), value, word));
+            ctx.set_arg_const(i, value)
+        }
+        ArgInit::Sym(width) => {
+            if width == 0 || width > word {
+                return Err(BuildError::InvalidArgWidth(i, width));
+            }
+            ctx.set_arg_sym(i, width)
+        }
+    };
+    result.map(|_| ()).map_err(|e| BuildError::Arg(i, e))
+}
+
+fn init_mem(ctx: &mut RuneContext, addr: u64, init: MemInit) -> Result<(), BuildError> {
+    let word = ctx.arch().addr_width();
+    match init {
+        MemInit::Const(value, size) => {
+            try!(check_size(size));
+            try!(check_fits(&format!("memory at {:#x}", addr), value, size));
+            try!(ctx.set_mem_as_const(addr as usize, value, size as u64)
+                    .map_err(BuildError::Context));
+        }
+        MemInit::Sym(size) => {
+            try!(check_size(size));
+            try!(ctx.set_mem_as_sym(addr as usize, size as u64).map_err(BuildError::Context));
+        }
+        MemInit::Buffer(name, len) => {
+            for i in 0..len as u64 {
+                let key = format!("{}[{}]", name, i);
+                let byte = ctx.define_var(&key, 8);
+                let addr_ = ctx.define_const(addr + i, word);
+                try!(ctx.try_mem_write(addr_, byte, 8).map_err(BuildError::Context));
+                ctx.syms.insert(key, byte);
+            }
+        }
+        MemInit::Image(bytes) => try!(write_bytes(ctx, addr, &bytes)),
+        MemInit::ImageFile(path) => {
+            let mut bytes = Vec::new();
+            let read = File::open(&path).and_then(|mut f| f.read_to_end(&mut bytes));
+            if let Err(e) = read {
+                return Err(BuildError::Io(path, e));
+            }
+            try!(write_bytes(ctx, addr, &bytes));
+        }
+    }
+    Ok(())
+}
+
+fn write_bytes(ctx: &mut RuneContext, addr: u64, bytes: &[u8]) -> Result<(), BuildError> {
+    let word = ctx.arch().addr_width();
+    for (i, byte) in bytes.iter().enumerate() {
+        let addr_ = ctx.define_const(addr + i as u64, word);
+        let byte_ = ctx.define_const(*byte as u64, 8);
+        try!(ctx.try_mem_write(addr_, byte_, 8).map_err(BuildError::Context));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use context::arch::ArchInfo;
+    use context::context::{Context, Evaluate, MemoryRead, RegisterRead};
+    use context::rune_ctx::RuneContext;
+    use libsmt::backends::z3;
+    use libsmt::theories::{bitvec, core};
+
+    #[test]
+    fn build_context() {
+        let mut ctx = RuneContext::builder()
+                          .arch(ArchInfo::x86())
+                          .const_reg("eip", 0x8048000)
+                          .const_reg("ax", 0x1234)
+                          .sym_reg("ebx")
+                          .mem_image(0x1000, &[0x11, 0x22])
+                          .sym_buffer("input", 0x2000, 4)
+                          .build()
+                          .unwrap();
+        assert_eq!(ctx.ip(), 0x8048000);
+        assert!(ctx.syms.contains_key("ebx"));
+        assert!(ctx.syms.contains_key("input[3]"));
+
+        let addr = ctx.define_const(0x1000, 32);
+        let word = ctx.mem_read(addr, 16);
+        let expected = ctx.define_const(0x2211, 16);
+        let eq = ctx.eval(core::OpCodes::Cmp, vec![word, expected]);
+        ctx.eval(core::OpCodes::Not, vec![eq]);
+        let mut z3: z3::Z3 = Default::default();
+        assert!(!ctx.check_sat(&mut z3));
+    }
+
+    #[test]
+    fn build_args() {
+        let mut ctx = RuneContext::builder()
+                          .const_reg("rsp", 0x8000)
+                          .const_arg(0, 0x1000)
+                          .sym_arg(7, 8)
+                          .build()
+                          .unwrap();
+        let rdi = ctx.reg_read("rdi");
+        assert_eq!(ctx.const_value(&rdi), Some(0x1000));
+        // The seventh and eighth arguments follow the return address.
+        let addr = ctx.define_const(0x8010, 64);
+        let arg = ctx.mem_read(addr, 64);
+        let zeros = ctx.define_const(0, 56);
+        let sym = ctx.syms["arg7"];
+        let expected = ctx.eval(bitvec::OpCodes::Concat, vec![zeros, sym]);
+        let eq = ctx.eval(core::OpCodes::Cmp, vec![arg, expected]);
+        ctx.eval(core::OpCodes::Not, vec![eq]);
+        let mut z3: z3::Z3 = Default::default();
+        assert!(!ctx.check_sat(&mut z3));
+
+        // Arguments of stdcall functions are all on the stack.
+        let mut ctx = RuneContext::builder()
+                          .arch(ArchInfo::x86())
+                          .call_conv(CallConv::Stdcall)
+                          .const_reg("esp", 0x8000)
+                          .const_arg(1, 0x1234)
+                          .build()
+                          .unwrap();
+        let addr = ctx.define_const(0x8008, 32);
+        let arg = ctx.mem_read(addr, 32);
+        let expected = ctx.define_const(0x1234, 32);
+        let eq = ctx.eval(core::OpCodes::Cmp, vec![arg, expected]);
+        ctx.eval(core::OpCodes::Not, vec![eq]);
+        let mut z3: z3::Z3 = Default::default();
+        assert!(!ctx.check_sat(&mut z3));
+
+        let eax = ctx.set_reg_as_const("eax", 7).unwrap();
+        assert_eq!(ctx.return_value(), Ok(eax));
+    }
+
+    #[test]
+    fn build_errors() {
+        let unknown = RuneContext::builder().const_reg("eax", 0).arch(ArchInfo::mips()).build();
+        match unknown {
+            Err(BuildError::UnknownRegister(ref reg)) if reg == "eax" => {}
+            r => panic!("Expected an unknown register, got {:?}", r.map(|_| ())),
+        }
+
+        let wide = RuneContext::builder().const_reg("al", 0x100).build();
+        match wide {
+            Err(BuildError::ValueTooWide { width: 8, .. }) => {}
+            r => panic!("Expected a value that is too wide, got {:?}", r.map(|_| ())),
+        }
+
+        let twice = RuneContext::builder().sym_reg("rax").const_reg("eax", 1).build();
+        match twice {
+            Err(BuildError::RegisterSetTwice(_)) => {}
+            r => panic!("Expected a register set twice, got {:?}", r.map(|_| ())),
+        }
+
+        match RuneContext::builder().sym_key("").build() {
+            Err(BuildError::InvalidKey(_)) => {}
+            r => panic!("Expected an invalid key, got {:?}", r.map(|_| ())),
+        }
+
+        match RuneContext::builder().const_mem(0x1000, 0, 12).build() {
+            Err(BuildError::InvalidSize(12)) => {}
+            r => panic!("Expected an invalid size, got {:?}", r.map(|_| ())),
+        }
+
+        // Stack arguments need the stack pointer.
+        match RuneContext::builder().sym_arg(6, 64).build() {
+            Err(BuildError::Arg(6, ContextError::UnsetRegister(_))) => {}
+            r => panic!("Expected an unset stack pointer, got {:?}", r.map(|_| ())),
+        }
+
+        match RuneContext::builder().sym_arg(0, 65).build() {
+            Err(BuildError::InvalidArgWidth(0, 65)) => {}
+            r => panic!("Expected an invalid argument width, got {:?}", r.map(|_| ())),
+        }
+    }
+}
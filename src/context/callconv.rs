@@ -0,0 +1,293 @@
+//! Calling conventions.
+//!
+//! A `CallConv` tells where the arguments of a function are found and where its return value
+//! goes. Arguments are numbered from 0 and are one word wide. They can only be read at the entry
+//! of the function, before it has touched the stack.
+//!
+//! Arguments that are not passed in registers are on the stack, the first one closest to the
+//! stack pointer. Where the call instruction pushes the return address, it is at the stack
+//! pointer and the arguments follow it.
+
+use std::str::FromStr;
+
+use libsmt::logics::qf_abv;
+use libsmt::theories::bitvec;
+
+use context::arch::{Arch, ArchInfo};
+use context::context::{Context, ContextResult, MemoryRead, MemoryWrite, RegisterRead,
+                       RegisterWrite};
+
+type VarRef<Ctx> = <Ctx as RegisterRead>::VarRef;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallConv {
+    /// System V AMD64 ABI. The first six arguments are passed in registers.
+    SysV,
+    /// 32-bit x86. All arguments are passed on the stack, the caller removes them.
+    Cdecl,
+    /// 32-bit x86. All arguments are passed on the stack, the callee removes them.
+    Stdcall,
+    /// 32-bit x86 (Microsoft). The first two arguments are passed in `ecx` and `edx`, the callee
+    /// removes the others from the stack.
+    Fastcall,
+    /// 32-bit ARM. The first four arguments are passed in registers, the return address in
+    /// `lr`.
+    Aapcs,
+}
+
+impl CallConv {
+    /// The usual calling convention on `arch`, if rune supports it.
+    pub fn for_arch(arch: &ArchInfo) -> Option<CallConv> {
+        match (arch.arch, arch.bits) {
+            (Arch::X86, 64) => Some(CallConv::SysV),
+            (Arch::X86, 32) => Some(CallConv::Cdecl),
+            (Arch::Arm, 32) | (Arch::Arm, 16) => Some(CallConv::Aapcs),
+            _ => None,
+        }
+    }
+
+    /// Registers holding the first arguments.
+    pub fn arg_regs(&self) -> &'static [&'static str] {
+        match *self {
+            CallConv::SysV => &["rdi", "rsi", "rdx", "rcx", "r8", "r9"],
+            CallConv::Cdecl | CallConv::Stdcall => &[],
+            CallConv::Fastcall => &["ecx", "edx"],
+            CallConv::Aapcs => &["r0", "r1", "r2", "r3"],
+        }
+    }
+
+    pub fn ret_reg(&self) -> &'static str {
+        match *self {
+            CallConv::SysV => "rax",
+            CallConv::Cdecl | CallConv::Stdcall | CallConv::Fastcall => "eax",
+            CallConv::Aapcs => "r0",
+        }
+    }
+
+    pub fn stack_pointer(&self) -> &'static str {
+        match *self {
+            CallConv::SysV => "rsp",
+            CallConv::Cdecl | CallConv::Stdcall | CallConv::Fastcall => "esp",
+            CallConv::Aapcs => "sp",
+        }
+    }
+
+    pub fn frame_pointer(&self) -> &'static str {
+        match *self {
+            CallConv::SysV => "rbp",
+            CallConv::Cdecl | CallConv::Stdcall | CallConv::Fastcall => "ebp",
+            CallConv::Aapcs => "fp",
+        }
+    }
+
+    /// Register holding the return address, if it is not pushed on the stack.
+    pub fn link_reg(&self) -> Option<&'static str> {
+        match *self {
+            CallConv::Aapcs => Some("lr"),
+            _ => None,
+        }
+    }
+
+    /// True if the called function removes its stack arguments when it returns.
+    pub fn callee_cleans(&self) -> bool {
+        match *self {
+            CallConv::Stdcall | CallConv::Fastcall => true,
+            _ => false,
+        }
+    }
+
+    /// Width of arguments and of the return value in bits.
+    pub fn word_size(&self) -> usize {
+        match *self {
+            CallConv::SysV => 64,
+            _ => 32,
+        }
+    }
+
+    /// Offset from the stack pointer, at the entry of a function, of the `i`th argument if it is
+    /// passed on the stack.
+    pub fn stack_offset(&self, i: usize) -> Option<u64> {
+        let nregs = self.arg_regs().len();
+        // Skip the return address.
+        let first = if self.link_reg().is_some() { 0 } else { 1 };
+        if i < nregs {
+            None
+        } else {
+            Some((i - nregs + first) as u64 * (self.word_size() / 8) as u64)
+        }
+    }
+
+    /// Read the `i`th argument.
+    pub fn arg<Ctx>(&self, ctx: &mut Ctx, i: usize) -> ContextResult<VarRef<Ctx>>
+        where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+    {
+        match self.stack_offset(i) {
+            None => ctx.try_reg_read(self.arg_regs()[i]),
+            Some(offset) => {
+                let sp = try!(ctx.try_reg_read(self.stack_pointer()));
+                let addr = offset_addr(ctx, sp, offset, self.word_size());
+                let size = self.word_size() as u64;
+                ctx.try_mem_read(addr, size)
+            }
+        }
+    }
+
+    /// Set the `i`th argument to `value`, a value of `width` bits that is zero extended to a
+    /// word. Stack arguments are written relative to the current stack pointer.
+    pub fn set_arg<Ctx>(&self,
+                        ctx: &mut Ctx,
+                        i: usize,
+                        value: VarRef<Ctx>,
+                        width: usize)
+                        -> ContextResult<()>
+        where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+    {
+        let word = self.word_size();
+        let value = if width < word {
+            let zeros = ctx.define_const(0, word - width);
+            ctx.eval(bitvec::OpCodes::Concat, vec![zeros, value])
+        } else {
+            value
+        };
+        match self.stack_offset(i) {
+            None => ctx.try_reg_write(self.arg_regs()[i], value),
+            Some(offset) => {
+                let sp = try!(ctx.try_reg_read(self.stack_pointer()));
+                let addr = offset_addr(ctx, sp, offset, word);
+                ctx.try_mem_write(addr, value, word as u64)
+            }
+        }
+    }
+
+    /// The return value.
+    pub fn return_value<Ctx: Context>(&self, ctx: &mut Ctx) -> ContextResult<VarRef<Ctx>> {
+        ctx.try_reg_read(self.ret_reg())
+    }
+
+    /// Set the return value. `value` must be one word wide.
+    pub fn set_return<Ctx>(&self, ctx: &mut Ctx, value: VarRef<Ctx>) -> ContextResult<()>
+        where Ctx: Context
+    {
+        ctx.try_reg_write(self.ret_reg(), value)
+    }
+
+    /// Return from a function taking `nargs` arguments at its entry, by popping the return
+    /// address off the stack (or reading the link register). Returns the return address, leaving
+    /// the program counter to the caller. Stack arguments are popped as well if the callee
+    /// removes them.
+    pub fn pop_return<Ctx>(&self, ctx: &mut Ctx, nargs: usize) -> ContextResult<VarRef<Ctx>>
+        where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+    {
+        if let Some(lr) = self.link_reg() {
+            return ctx.try_reg_read(lr);
+        }
+        let word = self.word_size();
+        let mut words = 1;
+        if self.callee_cleans() {
+            words += nargs.saturating_sub(self.arg_regs().len());
+        }
+        let sp = try!(ctx.try_reg_read(self.stack_pointer()));
+        let target = try!(ctx.try_mem_read(sp.clone(), word as u64));
+        let sp = offset_addr(ctx, sp, (words * word / 8) as u64, word);
+        try!(ctx.try_reg_write(self.stack_pointer(), sp));
+        Ok(target)
+    }
+}
+
+impl FromStr for CallConv {
+    type Err = String;
+
+    /// Parse `sysv`, `cdecl`, `stdcall`, `fastcall` and `aapcs`.
+    fn from_str(s: &str) -> Result<CallConv, String> {
+        let cc = match &s.to_lowercase()[..] {
+            "sysv" | "amd64" => CallConv::SysV,
+            "cdecl" => CallConv::Cdecl,
+            "stdcall" => CallConv::Stdcall,
+            "fastcall" => CallConv::Fastcall,
+            "aapcs" | "arm" => CallConv::Aapcs,
+            _ => return Err(format!("Unsupported calling convention: {}", s)),
+        };
+        Ok(cc)
+    }
+}
+
+/// `base + offset`, for addresses of `width` bits. The result is a constant if `base` is, so
+/// that the context knows the address.
+pub fn offset_addr<Ctx>(ctx: &mut Ctx,
+                        base: VarRef<Ctx>,
+                        offset: u64,
+                        width: usize)
+                        -> VarRef<Ctx>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    if offset == 0 {
+        return base;
+    }
+    if let Some(base) = ctx.const_value(&base) {
+        let mask = if width >= 64 { u64::max_value() } else { (1 << width) - 1 };
+        return ctx.define_const(base.wrapping_add(offset) & mask, width);
+    }
+    let offset = ctx.define_const(offset, width);
+    ctx.eval(bitvec::OpCodes::BvAdd, vec![base, offset])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use context::builder::ContextBuilder;
+    use context::rune_ctx::RuneContext;
+    use context::rune_ctx::test::value_is;
+
+    // Context at the entry of a function following `cc`, called from 0x1234 with the arguments
+    // 1, 2 and 3.
+    fn entry(arch: ArchInfo, cc: CallConv) -> RuneContext {
+        let mut builder = RuneContext::builder()
+                              .arch(arch)
+                              .call_conv(cc)
+                              .const_reg(cc.stack_pointer(), 0x8000);
+        builder = match cc.link_reg() {
+            Some(lr) => builder.const_reg(lr, 0x1234),
+            None => builder.const_mem(0x8000, 0x1234, 32),
+        };
+        (0..3).fold(builder, |b: ContextBuilder, i| b.const_arg(i, i as u64 + 1)).build().unwrap()
+    }
+
+    #[test]
+    fn call_and_return() {
+        // (convention, architecture, stack pointer in the caller after the return)
+        let cases: &[(CallConv, fn() -> ArchInfo, u64)] =
+            &[(CallConv::Cdecl, ArchInfo::x86, 0x8004),
+              (CallConv::Stdcall, ArchInfo::x86, 0x8010),
+              (CallConv::Fastcall, ArchInfo::x86, 0x8008),
+              (CallConv::Aapcs, ArchInfo::arm, 0x8000)];
+        for &(cc, arch, sp) in cases {
+            let mut ctx = entry(arch(), cc);
+            for i in 0..3 {
+                let arg = cc.arg(&mut ctx, i).unwrap();
+                assert!(value_is(&ctx, arg, 32, i as u64 + 1), "{:?}: argument {}", cc, i);
+            }
+            let target = cc.pop_return(&mut ctx, 3).unwrap();
+            assert!(value_is(&ctx, target, 32, 0x1234), "{:?}: return address", cc);
+            let sp_ = ctx.reg_read(cc.stack_pointer());
+            assert!(value_is(&ctx, sp_, 32, sp), "{:?}: stack pointer", cc);
+
+            let value = ctx.define_const(42, 32);
+            cc.set_return(&mut ctx, value).unwrap();
+            let value = cc.return_value(&mut ctx).unwrap();
+            assert!(value_is(&ctx, value, 32, 42), "{:?}: return value", cc);
+        }
+    }
+
+    #[test]
+    fn stack_layout() {
+        assert_eq!(CallConv::SysV.stack_offset(5), None);
+        assert_eq!(CallConv::SysV.stack_offset(6), Some(8));
+        assert_eq!(CallConv::Cdecl.stack_offset(0), Some(4));
+        assert_eq!(CallConv::Fastcall.stack_offset(2), Some(4));
+        assert_eq!(CallConv::Aapcs.stack_offset(4), Some(0));
+        assert_eq!(CallConv::Aapcs.stack_offset(5), Some(4));
+        assert_eq!("STDCALL".parse::<CallConv>(), Ok(CallConv::Stdcall));
+        assert!("pascal".parse::<CallConv>().is_err());
+    }
+}
@@ -1,12 +1,82 @@
 //!  Defines `Context` trait to be used by symbolic emulator
 
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 use std::hash::Hash;
 
 use std::collections::HashMap;
 
 use libsmt::backends::smtlib2::SMTProc;
 
+use context::arch::ArchInfo;
+use context::callconv::CallConv;
+use context::event::PathEvent;
+use context::heap::Heap;
+use context::region::MemRegion;
+use context::sys::SysState;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ContextError {
+    UnknownRegister(String),
+    /// The register was read before it was given a value.
+    UnsetRegister(String),
+    /// The register, or a register overlapping it, already has an initial value.
+    RegisterAlreadySet(String),
+    /// Memory accesses must be a whole number of bytes.
+    InvalidAccessSize(u64),
+    /// An internal variable (`$z`, `$c`, ...) was read before any operation set it.
+    UnsetInternalVar,
+    /// The constraints of the context cannot be satisfied.
+    Unsat,
+    /// There is no calling convention for the architecture.
+    NoCallConv,
+}
+
+impl fmt::Display for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ContextError::UnknownRegister(ref reg) => write!(f, "Unknown register `{}`", reg),
+            ContextError::UnsetRegister(ref reg) => {
+                write!(f, "Register `{}` read before being set", reg)
+            }
+            ContextError::RegisterAlreadySet(ref reg) => {
+                write!(f, "Register `{}` already has an initial value", reg)
+            }
+            ContextError::InvalidAccessSize(size) => {
+                write!(f, "Memory access of {} bits is not a whole number of bytes", size)
+            }
+            ContextError::UnsetInternalVar => {
+                write!(f, "Internal variable read before any operation set it")
+            }
+            ContextError::Unsat => write!(f, "No satisfying solution"),
+            ContextError::NoCallConv => write!(f, "No calling convention for the architecture"),
+        }
+    }
+}
+
+pub type ContextResult<T> = Result<T, ContextError>;
+
+/// Handling of memory accesses at addresses that can take more than one value. Strategies other
+/// than `Symbolic` are applied by the engine, which reports every such access as a
+/// `PathEvent::SymbolicAccess`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddrStrategy {
+    /// Use the address as it is, relying on the theory of arrays.
+    Symbolic,
+    /// Constrain the address to a single value chosen by the solver.
+    Concretize,
+    /// Fork a path for each of up to `n` feasible values of the address. Only the first such
+    /// access of an instruction forks, later ones are concretized.
+    Fork(usize),
+    /// Constrain the address to lie within `start..end`.
+    Region { start: u64, end: u64 },
+}
+
+impl Default for AddrStrategy {
+    fn default() -> AddrStrategy {
+        AddrStrategy::Symbolic
+    }
+}
+
 pub trait Context: Clone + Debug
                    + RegisterRead
                    + RegisterWrite<VarRef=<Self as RegisterRead>::VarRef>
@@ -23,36 +93,111 @@ pub trait Context: Clone + Debug
     }
     fn increment_ip(&mut self, u64);
     fn set_ip(&mut self, u64);
+    /// Architecture of the code executed in this context.
+    fn arch(&self) -> ArchInfo;
+    /// How memory accesses at symbolic addresses are handled.
+    fn addr_strategy(&self) -> AddrStrategy {
+        AddrStrategy::Symbolic
+    }
+    /// Calling convention of the functions executed in this context.
+    fn call_conv(&self) -> Option<CallConv> {
+        CallConv::for_arch(&self.arch())
+    }
+    /// Return address of the function execution started in, if a stack frame was set up for it.
+    fn entry_return(&self) -> Option<u64> {
+        None
+    }
     fn define_const(&mut self, u64, usize) -> <Self as RegisterRead>::VarRef;
+    fn define_var<T: AsRef<str>>(&mut self, T, usize) -> <Self as RegisterRead>::VarRef;
+    /// Define a named symbolic variable, which becomes part of `symbols`.
+    fn define_sym<T: AsRef<str>>(&mut self, T, usize) -> <Self as RegisterRead>::VarRef;
+    /// Value of `v`, if it is known to be a constant without consulting the solver.
+    fn const_value(&self, _: &<Self as RegisterRead>::VarRef) -> Option<u64> {
+        None
+    }
     fn alias_of(&self, String) -> Option<String>;
-    fn e_old(&self) -> <Self as RegisterRead>::VarRef;
-    fn e_cur(&self) -> <Self as RegisterRead>::VarRef;
-
-    fn solve<S: SMTProc>(&mut self, &mut S) -> HashMap<<Self as RegisterRead>::VarRef, u64>;
+    /// Width of the register in bits.
+    fn reg_width<T: AsRef<str>>(&self, T) -> Option<usize>;
+    /// Names of the general purpose registers and flags, leaving out sub-registers.
+    fn general_registers(&self) -> Vec<String>;
+    fn try_e_old(&self) -> ContextResult<<Self as RegisterRead>::VarRef>;
+    fn try_e_cur(&self) -> ContextResult<<Self as RegisterRead>::VarRef>;
+    fn e_old(&self) -> <Self as RegisterRead>::VarRef {
+        self.try_e_old().expect("e_old accessed before being set!")
+    }
+    fn e_cur(&self) -> <Self as RegisterRead>::VarRef {
+        self.try_e_cur().expect("e_cur accessed before being set!")
+    }
+    /// Width in bits of the destination of the last operation.
+    fn e_lastsz(&self) -> usize;
+
+    fn try_solve<S: SMTProc>(&mut self,
+                             &mut S)
+                             -> ContextResult<HashMap<<Self as RegisterRead>::VarRef, u64>>;
+    fn solve<S: SMTProc>(&mut self, p: &mut S) -> HashMap<<Self as RegisterRead>::VarRef, u64> {
+        self.try_solve(p).expect("No satisfying solution.")
+    }
+    fn check_sat<S: SMTProc>(&mut self, &mut S) -> bool;
 
     fn var_named<T: AsRef<str>>(& self, T) -> Option<<Self as RegisterRead>::VarRef>;
     fn set_e_old(&mut self, <Self as RegisterRead>::VarRef);
     fn set_e_cur(&mut self, <Self as RegisterRead>::VarRef);
+    fn set_e_lastsz(&mut self, usize);
+
+    /// Record an event in the log of the current path.
+    fn record_event(&mut self, PathEvent);
+    fn events(&self) -> &[PathEvent];
+
+    /// Mapped regions of memory. Accesses are not checked if there are none.
+    fn regions(&self) -> &[MemRegion] {
+        &[]
+    }
+    fn add_region(&mut self, MemRegion);
+    /// Named symbolic variables, whose values make up a model of the path.
+    fn symbols(&self) -> Vec<(String, <Self as RegisterRead>::VarRef)>;
+
+    /// State of the operating system, kept by system calls.
+    fn sys_state(&self) -> &SysState;
+    fn sys_state_mut(&mut self) -> &mut SysState;
+
+    /// Allocations made on the heap of the path.
+    fn heap(&self) -> &Heap<<Self as RegisterRead>::VarRef>;
+    fn heap_mut(&mut self) -> &mut Heap<<Self as RegisterRead>::VarRef>;
 }
 
+// The `try_` methods report errors, the others panic on them.
+
 pub trait RegisterRead: Sized {
     type VarRef: Clone + Debug + Hash + Eq;
-    fn reg_read<T: AsRef<str>>(&mut self, T) -> Self::VarRef;
+    fn try_reg_read<T: AsRef<str>>(&mut self, T) -> ContextResult<Self::VarRef>;
+    fn reg_read<T: AsRef<str>>(&mut self, reg: T) -> Self::VarRef {
+        self.try_reg_read(reg).unwrap_or_else(|e| panic!("{}", e))
+    }
 }
 
 pub trait RegisterWrite: Sized {
     type VarRef: Clone + Debug + Hash + Eq;
-    fn reg_write<T: AsRef<str>>(&mut self, T, Self::VarRef);
+    /// Write `source` to the register. `source` must be as wide as the register.
+    fn try_reg_write<T: AsRef<str>>(&mut self, T, Self::VarRef) -> ContextResult<()>;
+    fn reg_write<T: AsRef<str>>(&mut self, reg: T, source: Self::VarRef) {
+        self.try_reg_write(reg, source).unwrap_or_else(|e| panic!("{}", e))
+    }
 }
 
 pub trait MemoryRead: Sized {
     type VarRef: Clone + Debug + Hash + Eq;
-    fn mem_read(&mut self, Self::VarRef, u64) -> Self::VarRef;
+    fn try_mem_read(&mut self, Self::VarRef, u64) -> ContextResult<Self::VarRef>;
+    fn mem_read(&mut self, addr: Self::VarRef, size: u64) -> Self::VarRef {
+        self.try_mem_read(addr, size).unwrap_or_else(|e| panic!("{}", e))
+    }
 }
 
 pub trait MemoryWrite: Sized {
     type VarRef: Clone + Debug + Hash + Eq;
-    fn mem_write(&mut self, Self::VarRef, Self::VarRef, u64);
+    fn try_mem_write(&mut self, Self::VarRef, Self::VarRef, u64) -> ContextResult<()>;
+    fn mem_write(&mut self, addr: Self::VarRef, data: Self::VarRef, size: u64) {
+        self.try_mem_write(addr, data, size).unwrap_or_else(|e| panic!("{}", e))
+    }
 }
 
 pub trait Evaluate {
@@ -69,13 +214,29 @@ pub trait Evaluate {
 /// from `Context`. That is, there must be no loss of functionality by not implementing
 /// `ContextAPI`.
 pub trait ContextAPI: Context {
-    /// Set register to hold either symbolic or concrete values.
-    fn set_reg_as_const<T: AsRef<str>>(&mut self, T, u64) -> <Self as RegisterRead>::VarRef;
-    fn set_reg_as_sym<T: AsRef<str>>(&mut self, T) -> <Self as RegisterRead>::VarRef;
-
-    /// Set memory to hold either symbolic or concrete values.
-    fn set_mem_as_const(&mut self, usize, u64, u64) -> <Self as RegisterRead>::VarRef;
-    fn set_mem_as_sym(&mut self, usize, u64) -> <Self as RegisterRead>::VarRef;
+    /// Set register to hold either symbolic or concrete values. The register, and registers
+    /// overlapping it, must not have been set before.
+    fn set_reg_as_const<T: AsRef<str>>(&mut self,
+                                       T,
+                                       u64)
+                                       -> ContextResult<<Self as RegisterRead>::VarRef>;
+    fn set_reg_as_sym<T: AsRef<str>>(&mut self, T) -> ContextResult<<Self as RegisterRead>::VarRef>;
+
+    /// Set memory to hold either symbolic or concrete values. Sizes are in bits.
+    fn set_mem_as_const(&mut self,
+                        usize,
+                        u64,
+                        u64)
+                        -> ContextResult<<Self as RegisterRead>::VarRef>;
+    fn set_mem_as_sym(&mut self, usize, u64) -> ContextResult<<Self as RegisterRead>::VarRef>;
+
+    /// Set the `i`th argument of the function at the current address, according to the calling
+    /// convention, to a new symbol `arg<i>` of `width` bits. The symbol is zero extended to a
+    /// word. Stack arguments are only written correctly once the stack pointer is set.
+    fn set_arg_sym(&mut self, usize, usize) -> ContextResult<<Self as RegisterRead>::VarRef>;
+    fn set_arg_const(&mut self, usize, u64) -> ContextResult<<Self as RegisterRead>::VarRef>;
+    /// The return value of a function that has returned, according to the calling convention.
+    fn return_value(&mut self) -> ContextResult<<Self as RegisterRead>::VarRef>;
 
     /// Set registers that are not set to be a constant zero.
     fn zero_registers(&mut self);
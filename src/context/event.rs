@@ -0,0 +1,97 @@
+//! Events of interest that occur while exploring a path.
+//!
+//! Events are recorded in the context of the path on which they occur, so that every path
+//! carries its own log.
+
+use std::collections::HashMap;
+
+use context::context::{AddrStrategy, Context, RegisterRead};
+use context::region::Access;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathEvent {
+    /// The program counter was written with a value that could take more values than the
+    /// configured limit, i.e. the target of the jump is (close to) unconstrained. Only the
+    /// `targets` were explored.
+    ControlFlowHijack { addr: u64, targets: Vec<u64> },
+    /// The instruction at `addr` has no esil, or esil that rune does not support. The registers
+    /// in `havoc` were given fresh symbolic values.
+    UnsupportedInstruction {
+        addr: u64,
+        opcode: Option<String>,
+        esil: Option<String>,
+        havoc: Vec<String>,
+    },
+    /// The instruction at `addr` accessed memory at an address that can take more than one value,
+    /// which was handled with `strategy`. `targets` are the addresses the access was constrained
+    /// to on this path, if any.
+    SymbolicAccess {
+        addr: u64,
+        write: bool,
+        strategy: AddrStrategy,
+        targets: Vec<u64>,
+    },
+    /// The instruction at `addr` may access the `size` bytes at `target` (or fetch itself from
+    /// there) outside of the regions that permit the access. `model` holds the values of the
+    /// named symbols that lead to the access.
+    InvalidAccess {
+        addr: u64,
+        access: Access,
+        target: u64,
+        size: u64,
+        model: Vec<(String, u64)>,
+    },
+    /// The instruction at `addr` may access the `size` bytes at `target`, beyond the end of the
+    /// heap allocation at `base`. `model` holds the values of the named symbols that lead to the
+    /// access.
+    HeapOutOfBounds {
+        addr: u64,
+        write: bool,
+        target: u64,
+        size: u64,
+        base: u64,
+        model: Vec<(String, u64)>,
+    },
+    /// The instruction at `addr` may access the `size` bytes at `target`, in the heap allocation
+    /// at `base`, which has been freed.
+    UseAfterFree {
+        addr: u64,
+        write: bool,
+        target: u64,
+        size: u64,
+        base: u64,
+        model: Vec<(String, u64)>,
+    },
+    /// `free` (at `addr`) may be called with `ptr`, an allocation that has already been freed.
+    DoubleFree {
+        addr: u64,
+        ptr: u64,
+        model: Vec<(String, u64)>,
+    },
+    /// `free` (at `addr`) may be called with `ptr`, which is not the address of an allocation.
+    InvalidFree {
+        addr: u64,
+        ptr: u64,
+        model: Vec<(String, u64)>,
+    },
+    /// The path ended with a call to `exit` at `addr`. `status` is the exit status, if it is a
+    /// constant.
+    Exit { addr: u64, status: Option<u64> },
+    /// The function execution started in returned, ending the path. `value` is the return value,
+    /// if it is a constant.
+    Returned { value: Option<u64> },
+    /// The instruction at `addr` made a system call that the syscall handler does not know.
+    UnknownSyscall { addr: u64, number: u64 },
+}
+
+/// Values of the named symbols of `ctx` in a solver model, sorted by name.
+pub fn named_values<Ctx: Context>(ctx: &Ctx,
+                                  values: &HashMap<<Ctx as RegisterRead>::VarRef, u64>)
+                                  -> Vec<(String, u64)> {
+    let mut named = ctx.symbols()
+                       .into_iter()
+                       .filter_map(|(name, var)| values.get(&var).map(|v| (name, *v)))
+                       .collect::<Vec<_>>();
+    named.sort();
+    named
+}
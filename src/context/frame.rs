@@ -0,0 +1,117 @@
+//! Stack frame of the function execution starts in.
+//!
+//! When execution starts at the entry of a function, the context builder can set up a stack for
+//! it: a region of memory, a stack pointer into it and a return address. The return address is a
+//! sentinel that no code is expected at, so that the engine can tell when the function returns.
+
+use std::fmt;
+
+use context::arch::ArchInfo;
+use context::region::{MemRegion, Perms};
+
+/// Return address of the function execution starts in.
+pub const RETURN_SENTINEL: u64 = 0xffff_ff00;
+
+/// Space between the stack pointer and the top of the stack, for arguments passed on the stack.
+const ARGS_SIZE: u64 = 0x1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameError {
+    /// The stack does not fit above address 0.
+    TooLarge { top: u64, size: u64 },
+    /// The stack has no room for the arguments passed on it.
+    TooSmall(u64),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FrameError::TooLarge { top, size } => {
+                write!(f, "Stack of {:#x} bytes does not fit above 0 below {:#x}", size, top)
+            }
+            FrameError::TooSmall(size) => {
+                write!(f, "Stack of {:#x} bytes is smaller than {:#x}", size, ARGS_SIZE)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackFrame {
+    /// End of the stack, which grows down from it.
+    top: u64,
+    /// Size of the stack in bytes.
+    size: u64,
+    /// Stack pointer at the entry of the function, pointing at the return address if the calling
+    /// convention pushes it.
+    pub sp: u64,
+    pub ret_addr: u64,
+}
+
+impl StackFrame {
+    /// A stack of `size` bytes below `top`. The stack must lie above address 0 and leave room
+    /// for the arguments passed on the stack.
+    pub fn new(top: u64, size: u64) -> Result<StackFrame, FrameError> {
+        if size >= top {
+            return Err(FrameError::TooLarge {
+                top: top,
+                size: size,
+            });
+        }
+        if size < ARGS_SIZE {
+            return Err(FrameError::TooSmall(size));
+        }
+        Ok(StackFrame {
+            top: top,
+            size: size,
+            sp: top - ARGS_SIZE,
+            ret_addr: RETURN_SENTINEL,
+        })
+    }
+
+    /// A 1 MiB stack where Linux usually puts the stack on `arch`.
+    pub fn for_arch(arch: &ArchInfo) -> StackFrame {
+        let top = if arch.addr_width() == 64 { 0x7fff_ffff_f000 } else { 0xbfff_f000 };
+        StackFrame::new(top, 0x10_0000).unwrap()
+    }
+
+    pub fn top(&self) -> u64 {
+        self.top
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn region(&self) -> MemRegion {
+        MemRegion::new("[stack]",
+                       self.top - self.size,
+                       self.size,
+                       Perms::new(true, true, false))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frame_bounds() {
+        let frame = StackFrame::new(0x10000, 0x2000).unwrap();
+        assert_eq!(frame.sp, 0xf000);
+        assert_eq!(frame.region(), MemRegion::new("[stack]", 0xe000, 0x2000, frame.region().perms));
+        assert!(StackFrame::new(0x10001, 0x10000).is_ok());
+        assert_eq!(StackFrame::new(0x10000, 0x10000),
+                   Err(FrameError::TooLarge {
+                       top: 0x10000,
+                       size: 0x10000,
+                   }));
+        assert_eq!(StackFrame::new(0x1000, 0x2000),
+                   Err(FrameError::TooLarge {
+                       top: 0x1000,
+                       size: 0x2000,
+                   }));
+        assert_eq!(StackFrame::new(0x10000, 0x100), Err(FrameError::TooSmall(0x100)));
+        assert!(StackFrame::new(0x100, 0x100).is_err());
+    }
+}
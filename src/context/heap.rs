@@ -0,0 +1,113 @@
+//! Model of the heap.
+//!
+//! Allocations are made by the `malloc` summary from an area of memory set aside for the heap.
+//! Every allocation is given a span of its own, so that allocations never overlap, with a guard
+//! after its last byte that catches accesses just beyond the end. Sizes may be symbolic, in which
+//! case the span covers the largest size allowed. Freed allocations are kept, so that later
+//! accesses to them can be told apart from other accesses.
+
+/// Start of the heap.
+pub const HEAP_BASE: u64 = 0x0a00_0000;
+
+/// Bytes between the end of an allocation and the next allocation, at least.
+pub const GUARD_SIZE: u64 = 0x10;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Allocation<V> {
+    pub base: u64,
+    /// Size in bytes, one word wide.
+    pub size: V,
+    /// Bytes set aside for the allocation, including the guard.
+    pub span: u64,
+    /// False once the allocation has been freed.
+    pub live: bool,
+}
+
+impl<V> Allocation<V> {
+    /// True if `addr` lies within the span of the allocation.
+    pub fn contains(&self, addr: u64) -> bool {
+        addr >= self.base && addr - self.base < self.span
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heap<V> {
+    base: u64,
+    next: u64,
+    allocs: Vec<Allocation<V>>,
+}
+
+impl<V: Clone> Default for Heap<V> {
+    fn default() -> Heap<V> {
+        Heap::new(HEAP_BASE)
+    }
+}
+
+impl<V: Clone> Heap<V> {
+    /// An empty heap starting at `base`.
+    pub fn new(base: u64) -> Heap<V> {
+        Heap {
+            base: base,
+            next: base,
+            allocs: Vec::new(),
+        }
+    }
+
+    /// Set aside `max_size` bytes for an allocation of `size` bytes. Returns the address of the
+    /// allocation, which is 16 byte aligned.
+    pub fn alloc(&mut self, size: V, max_size: u64) -> u64 {
+        let base = self.next;
+        let span = (max_size + GUARD_SIZE + 15) & !15;
+        self.next += span;
+        self.allocs.push(Allocation {
+            base: base,
+            size: size,
+            span: span,
+            live: true,
+        });
+        base
+    }
+
+    /// Mark the allocation at `base` as freed. Returns false if there is no live allocation at
+    /// `base`.
+    pub fn free(&mut self, base: u64) -> bool {
+        match self.allocs.iter_mut().find(|a| a.base == base && a.live) {
+            Some(alloc) => {
+                alloc.live = false;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The allocation whose span contains `addr`.
+    pub fn find(&self, addr: u64) -> Option<&Allocation<V>> {
+        if addr < self.base || addr >= self.next {
+            return None;
+        }
+        self.allocs.iter().find(|a| a.contains(addr))
+    }
+
+    /// Allocations in the order they were made, including the freed ones.
+    pub fn allocations(&self) -> &[Allocation<V>] {
+        &self.allocs
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn heap_allocations() {
+        let mut heap: Heap<u64> = Heap::new(0x1000);
+        assert_eq!(heap.alloc(4, 4), 0x1000);
+        assert_eq!(heap.alloc(0x20, 0x20), 0x1020);
+        assert_eq!(heap.find(0x1013).map(|a| a.base), Some(0x1000));
+        assert_eq!(heap.find(0x1050).map(|a| a.base), None);
+        assert!(heap.free(0x1020));
+        assert!(!heap.free(0x1020));
+        assert!(!heap.free(0x1004));
+        assert_eq!(heap.find(0x1020).map(|a| a.live), Some(false));
+    }
+}
@@ -0,0 +1,138 @@
+//! Concrete initial contents of memory.
+//!
+//! An image holds the bytes of a binary's sections, or of any raw file, at the addresses they are
+//! mapped at. The bytes are only added to the constraints of a context a page at a time, the
+//! first time the page is read.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use r2pipe::r2::R2;
+
+use context::region;
+
+/// Granularity at which the image is loaded into memory.
+pub const PAGE_SIZE: u64 = 0x1000;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Segment {
+    pub base: u64,
+    pub bytes: Vec<u8>,
+}
+
+impl Segment {
+    fn end(&self) -> u64 {
+        self.base + self.bytes.len() as u64
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MemImage {
+    segments: Vec<Segment>,
+}
+
+impl MemImage {
+    pub fn new() -> MemImage {
+        MemImage::default()
+    }
+
+    /// Map `bytes` at `base`. Later segments take precedence where segments overlap.
+    pub fn add_segment(&mut self, base: u64, bytes: Vec<u8>) {
+        if !bytes.is_empty() {
+            self.segments.push(Segment {
+                base: base,
+                bytes: bytes,
+            });
+        }
+    }
+
+    /// Add the segments of `other` to the image.
+    pub fn extend(&mut self, other: MemImage) {
+        self.segments.extend(other.segments);
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// An image of the contents of the file at `path`, mapped at `base`.
+    pub fn from_file<P: AsRef<Path>>(base: u64, path: P) -> io::Result<MemImage> {
+        let mut bytes = Vec::new();
+        try!(File::open(path).and_then(|mut f| f.read_to_end(&mut bytes)));
+        let mut image = MemImage::new();
+        image.add_segment(base, bytes);
+        Ok(image)
+    }
+
+    /// An image of the sections of the binary loaded in radare2 (`iSj`), read with `p8`.
+    /// Sections without any bytes in the file, such as `.bss`, are left out.
+    pub fn from_r2(r2: &mut R2) -> Result<MemImage, String> {
+        let mut image = MemImage::new();
+        for section in try!(region::sections(r2)) {
+            let size = ::std::cmp::min(section.size, section.vsize);
+            if size == 0 {
+                continue;
+            }
+            r2.send(&format!("p8 {} @ {:#x}", size, section.vaddr));
+            let bytes = try!(from_hex(r2.recv().trim()));
+            image.add_segment(section.vaddr, bytes);
+        }
+        Ok(image)
+    }
+
+    /// The byte at `addr`, if it is part of the image.
+    pub fn byte(&self, addr: u64) -> Option<u8> {
+        self.segments
+            .iter()
+            .rev()
+            .find(|s| addr >= s.base && addr < s.end())
+            .map(|s| s.bytes[(addr - s.base) as usize])
+    }
+
+    /// The bytes of the image in page `page` (the page at `page * PAGE_SIZE`), along with their
+    /// addresses.
+    pub fn page(&self, page: u64) -> Vec<(u64, u8)> {
+        let start = page * PAGE_SIZE;
+        let end = start.saturating_add(PAGE_SIZE);
+        if !self.segments.iter().any(|s| s.base < end && s.end() > start) {
+            return Vec::new();
+        }
+        (start..end).filter_map(|addr| self.byte(addr).map(|b| (addr, b))).collect()
+    }
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>, String> {
+    if hex.len() % 2 != 0 {
+        return Err(format!("Invalid hex string: {}", hex));
+    }
+    (0..hex.len() / 2)
+        .map(|i| {
+            u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)
+                .map_err(|_| format!("Invalid hex string: {}", hex))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn image_pages() {
+        let mut image = MemImage::new();
+        image.add_segment(0x1ffe, vec![1, 2, 3, 4]);
+        image.add_segment(0x2001, vec![5]);
+        assert_eq!(image.byte(0x1ffd), None);
+        assert_eq!(image.byte(0x1fff), Some(2));
+        assert_eq!(image.byte(0x2001), Some(5));
+        assert_eq!(image.page(1), vec![(0x1ffe, 1), (0x1fff, 2)]);
+        assert_eq!(image.page(2), vec![(0x2000, 3), (0x2001, 5)]);
+        assert!(image.page(3).is_empty());
+        assert_eq!(from_hex("00ff10"), Ok(vec![0, 0xff, 0x10]));
+    }
+}
@@ -0,0 +1,221 @@
+//! Register profiles describe the registers of an architecture: their names, widths, where they
+//! live in the register file, and which registers play special roles (program counter, stack
+//! pointer, ...).
+//!
+//! A profile can come from one of the profiles bundled with rune, from a JSON file holding the
+//! output of radare2's `drj` / `LRegInfo`, from radare2's textual register profile format (`drp`),
+//! or by asking a running instance of radare2.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use r2pipe::r2::R2;
+use r2pipe::structs::LRegInfo;
+use rustc_serialize::json;
+
+use context::arch::{Arch, ArchInfo};
+
+const X86: &'static str = include_str!("profiles/x86.txt");
+const X86_64: &'static str = include_str!("profiles/x86_64.txt");
+const ARM: &'static str = include_str!("profiles/arm.txt");
+const AARCH64: &'static str = include_str!("profiles/aarch64.txt");
+const MIPS: &'static str = include_str!("profiles/mips.txt");
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegDef {
+    pub name: String,
+    /// Register arena, such as `gpr`, `flg` or `seg`.
+    pub type_str: String,
+    /// Width in bits.
+    pub size: usize,
+    /// Offset in bits from the start of the arena.
+    pub offset: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RegProfile {
+    pub regs: Vec<RegDef>,
+    /// (role, register) pairs, such as `("PC", "rip")`.
+    pub aliases: Vec<(String, String)>,
+}
+
+#[derive(Debug)]
+pub enum ProfileError {
+    Io(io::Error),
+    Json(json::DecoderError),
+    /// A line of a textual profile could not be parsed.
+    Parse { line: usize, msg: String },
+    /// radare2 could not provide the register information.
+    R2(String),
+}
+
+impl From<io::Error> for ProfileError {
+    fn from(e: io::Error) -> ProfileError {
+        ProfileError::Io(e)
+    }
+}
+
+impl From<json::DecoderError> for ProfileError {
+    fn from(e: json::DecoderError) -> ProfileError {
+        ProfileError::Json(e)
+    }
+}
+
+// Offsets and sizes are in bytes, unless prefixed with a `.`, in which case they are in bits.
+fn parse_bits(s: &str) -> Option<usize> {
+    if s.starts_with('.') {
+        s[1..].parse::<usize>().ok()
+    } else {
+        s.parse::<usize>().ok().map(|v| v * 8)
+    }
+}
+
+impl RegProfile {
+    /// The profile bundled with rune for `arch`, if there is one.
+    pub fn builtin(arch: &ArchInfo) -> Option<RegProfile> {
+        let profile = match (arch.arch, arch.bits) {
+            (Arch::X86, 32) => X86,
+            (Arch::X86, 64) => X86_64,
+            (Arch::Arm, 16) | (Arch::Arm, 32) => ARM,
+            (Arch::Arm, 64) => AARCH64,
+            (Arch::Mips, 32) => MIPS,
+            _ => return None,
+        };
+        Some(RegProfile::parse(profile).expect("Invalid builtin register profile"))
+    }
+
+    /// Parse a register profile in the textual format used by radare2, for example:
+    ///
+    /// ```text
+    /// =PC    rip
+    /// gpr    rax    .64    80    0
+    /// gpr    zf     .1     .1158 0
+    /// ```
+    pub fn parse(profile: &str) -> Result<RegProfile, ProfileError> {
+        let mut regs = Vec::new();
+        let mut aliases = Vec::new();
+        for (i, line) in profile.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let err = |msg: &str| {
+                ProfileError::Parse {
+                    line: i + 1,
+                    msg: msg.to_owned(),
+                }
+            };
+            if words[0].starts_with('=') {
+                if words.len() < 2 {
+                    return Err(err("Alias without a register"));
+                }
+                aliases.push((words[0][1..].to_owned(), words[1].to_owned()));
+                continue;
+            }
+            if words.len() < 4 {
+                return Err(err("Expected `type name size offset`"));
+            }
+            let size = try!(parse_bits(words[2]).ok_or_else(|| err("Invalid size")));
+            let offset = try!(parse_bits(words[3]).ok_or_else(|| err("Invalid offset")));
+            regs.push(RegDef {
+                name: words[1].to_owned(),
+                type_str: words[0].to_owned(),
+                size: size,
+                offset: offset,
+            });
+        }
+        Ok(RegProfile {
+            regs: regs,
+            aliases: aliases,
+        })
+    }
+
+    pub fn from_reginfo(reginfo: &LRegInfo) -> RegProfile {
+        let regs = reginfo.reg_info
+                          .iter()
+                          .map(|r| {
+                              RegDef {
+                                  name: r.name.clone(),
+                                  type_str: r.type_str.clone(),
+                                  size: r.size,
+                                  offset: r.offset,
+                              }
+                          })
+                          .collect();
+        let aliases = reginfo.alias_info
+                             .iter()
+                             .map(|a| (a.role_str.clone(), a.reg.clone()))
+                             .collect();
+        RegProfile {
+            regs: regs,
+            aliases: aliases,
+        }
+    }
+
+    /// Load a profile from a file holding the register information reported by radare2 (`drj`)
+    /// as JSON.
+    pub fn from_json_file<P: AsRef<Path>>(path: P) -> Result<RegProfile, ProfileError> {
+        let mut s = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut s));
+        let reginfo: LRegInfo = try!(json::decode(&s));
+        Ok(RegProfile::from_reginfo(&reginfo))
+    }
+
+    /// Ask radare2 for the register profile of the architecture it is configured for.
+    pub fn from_r2(r2: &mut R2) -> Result<RegProfile, ProfileError> {
+        let reginfo = try!(r2.reg_info().map_err(|e| ProfileError::R2(format!("{:?}", e))));
+        Ok(RegProfile::from_reginfo(&reginfo))
+    }
+
+    /// Width of the register in bits.
+    pub fn width_of(&self, reg: &str) -> Option<usize> {
+        self.regs.iter().find(|r| r.name == reg).map(|r| r.size)
+    }
+
+    /// Register that plays `role` (`PC`, `SP`, `A0`, ...).
+    pub fn alias(&self, role: &str) -> Option<&str> {
+        self.aliases.iter().find(|a| a.0 == role).map(|a| &a.1[..])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use context::arch::ArchInfo;
+
+    #[test]
+    fn builtin_profiles() {
+        let archs = [(ArchInfo::x86(), "eip", "eax", 32),
+                     (ArchInfo::x86_64(), "rip", "r8d", 32),
+                     (ArchInfo::arm(), "pc", "lr", 32),
+                     (ArchInfo::thumb(), "pc", "r7", 32),
+                     (ArchInfo::aarch64(), "pc", "w30", 32),
+                     (ArchInfo::mips(), "pc", "t9", 32)];
+        for &(ref arch, pc, reg, width) in &archs {
+            let profile = RegProfile::builtin(arch).unwrap();
+            assert_eq!(profile.alias("PC"), Some(pc));
+            assert_eq!(profile.width_of(pc), Some(arch.addr_width()));
+            assert_eq!(profile.width_of(reg), Some(width));
+        }
+    }
+
+    #[test]
+    fn parse_profile() {
+        let profile = RegProfile::parse("=PC\trip\n\
+                                         # comment\n\
+                                         gpr\trax\t.64\t80\t0\n\
+                                         gpr\tzf\t.1\t.1158\t0\n")
+                          .unwrap();
+        assert_eq!(profile.aliases, vec![("PC".to_owned(), "rip".to_owned())]);
+        assert_eq!(profile.regs[0].offset, 640);
+        assert_eq!(profile.regs[1].offset, 1158);
+        assert_eq!(profile.regs[1].size, 1);
+
+        match RegProfile::parse("gpr\trax\t.64\n") {
+            Err(ProfileError::Parse { line: 1, .. }) => {}
+            r => panic!("Expected a parse error, got {:?}", r),
+        }
+    }
+}
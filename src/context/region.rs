@@ -0,0 +1,195 @@
+//! Mapped regions of memory and their permissions.
+//!
+//! Regions can be declared by hand or read from the sections radare2 reports for a binary. When a
+//! context has regions, the engine checks every memory access and instruction fetch against them.
+
+use std::str::FromStr;
+
+use r2pipe::r2::R2;
+use rustc_serialize::json::Json;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Exec,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Perms {
+    pub read: bool,
+    pub write: bool,
+    pub exec: bool,
+}
+
+impl Perms {
+    pub fn new(read: bool, write: bool, exec: bool) -> Perms {
+        Perms {
+            read: read,
+            write: write,
+            exec: exec,
+        }
+    }
+
+    pub fn allows(&self, access: Access) -> bool {
+        match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Exec => self.exec,
+        }
+    }
+}
+
+impl FromStr for Perms {
+    type Err = String;
+
+    /// Parse permissions in the format used by radare2, such as `-r-x` or `rw-`.
+    fn from_str(s: &str) -> Result<Perms, String> {
+        let mut perms = Perms::default();
+        for c in s.chars() {
+            match c {
+                'r' => perms.read = true,
+                'w' => perms.write = true,
+                'x' => perms.exec = true,
+                '-' | 'm' | 's' => {}
+                _ => return Err(format!("Invalid permissions: {}", s)),
+            }
+        }
+        Ok(perms)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemRegion {
+    pub name: String,
+    pub base: u64,
+    /// Size in bytes.
+    pub size: u64,
+    pub perms: Perms,
+}
+
+impl MemRegion {
+    pub fn new<T: AsRef<str>>(name: T, base: u64, size: u64, perms: Perms) -> MemRegion {
+        MemRegion {
+            name: name.as_ref().to_owned(),
+            base: base,
+            size: size,
+            perms: perms,
+        }
+    }
+
+    /// True if the `n` bytes at `addr` lie within the region.
+    pub fn contains(&self, addr: u64, n: u64) -> bool {
+        addr >= self.base && n <= self.size && addr - self.base <= self.size - n
+    }
+
+    /// True if any of the `n` bytes at `addr` lie within the region.
+    pub fn overlaps(&self, addr: u64, n: u64) -> bool {
+        if addr <= self.base {
+            self.base - addr < n && self.size != 0
+        } else {
+            addr - self.base < self.size
+        }
+    }
+
+    /// Regions for the sections of the binary loaded in radare2 (`iSj`).
+    pub fn from_r2(r2: &mut R2) -> Result<Vec<MemRegion>, String> {
+        let mut regions = Vec::new();
+        for section in try!(sections(r2)) {
+            if section.vsize == 0 {
+                continue;
+            }
+            let perms = try!(section.perms.parse());
+            regions.push(MemRegion::new(&section.name, section.vaddr, section.vsize, perms));
+        }
+        Ok(regions)
+    }
+}
+
+/// A section of a binary, as reported by radare2.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Section {
+    pub name: String,
+    pub vaddr: u64,
+    /// Size of the section in the file.
+    pub size: u64,
+    /// Size of the section in memory.
+    pub vsize: u64,
+    pub perms: String,
+}
+
+/// The sections of the binary loaded in radare2 (`iSj`).
+pub fn sections(r2: &mut R2) -> Result<Vec<Section>, String> {
+    r2.send("iSj");
+    parse_sections(&r2.recv())
+}
+
+fn parse_sections(json: &str) -> Result<Vec<Section>, String> {
+    let sections = try!(Json::from_str(json).map_err(|e| e.to_string()));
+    // Newer versions of radare2 wrap the sections in an object.
+    let sections = match sections.find("sections").and_then(|s| s.as_array()) {
+        Some(sections) => sections.clone(),
+        None => try!(sections.as_array().cloned().ok_or("Unexpected section info")),
+    };
+
+    let mut result = Vec::new();
+    for section in sections {
+        let field = |name: &str| section.find(name).and_then(|v| v.as_u64());
+        let vaddr = try!(field("vaddr").ok_or("Section without an address"));
+        let size = try!(field("size").or_else(|| field("vsize")).ok_or("Section without a size"));
+        let string = |names: &[&str]| {
+            names.iter()
+                 .filter_map(|name| section.find(name).and_then(|v| v.as_string()))
+                 .next()
+                 .unwrap_or("")
+                 .to_owned()
+        };
+        result.push(Section {
+            name: string(&["name"]),
+            vaddr: vaddr,
+            size: size,
+            vsize: field("vsize").unwrap_or(size),
+            perms: string(&["perm", "flags"]),
+        });
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn region_sections() {
+        let section = Section {
+            name: ".bss".to_owned(),
+            vaddr: 0x2000,
+            size: 0,
+            vsize: 0x10,
+            perms: "-rw-".to_owned(),
+        };
+        let old = r#"[{"name": ".bss", "size": 0, "vsize": 16, "flags": "-rw-", "vaddr": 8192}]"#;
+        assert_eq!(parse_sections(old), Ok(vec![section.clone()]));
+        let new = r#"{"sections": [{"name": ".bss", "vsize": 16, "perm": "-rw-", "vaddr": 8192,
+                                    "size": 0}]}"#;
+        assert_eq!(parse_sections(new), Ok(vec![section]));
+        assert!(parse_sections(r#"[{"name": ".bss", "vaddr": 8192}]"#).is_err());
+    }
+
+    #[test]
+    fn region_contains() {
+        let text = MemRegion::new(".text", 0x1000, 0x100, "-r-x".parse().unwrap());
+        assert!(text.perms.allows(Access::Exec));
+        assert!(!text.perms.allows(Access::Write));
+        assert!(text.contains(0x1000, 8));
+        assert!(text.contains(0x10f8, 8));
+        assert!(!text.contains(0x10f9, 8));
+        assert!(!text.contains(0xfff, 1));
+        assert!(!MemRegion::new("tiny", 0, 2, Perms::default()).contains(0, 4));
+        assert!(text.overlaps(0xf00, 0x101));
+        assert!(text.overlaps(0x10ff, 0x1000));
+        assert!(!text.overlaps(0xf00, 0x100));
+        assert!(!text.overlaps(0x1100, 8));
+        assert!(!text.overlaps(0x1000, 0));
+    }
+}
@@ -1,6 +1,7 @@
 //! Defines `RuneContext`
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 
 use r2pipe::structs::LRegInfo;
 use petgraph::graph::NodeIndex;
@@ -9,26 +10,103 @@ use libsmt::backends::backend::SMTBackend;
 use libsmt::logics::qf_abv;
 use libsmt::theories::{array_ex, bitvec, core};
 
-use context::context::{Context, ContextAPI, Evaluate, MemoryRead, MemoryWrite, RegisterRead,
-                       RegisterWrite};
-
-// TODO: Handle symbolic jumps
+use context::context::{AddrStrategy, Context, ContextAPI, ContextError, ContextResult, Evaluate,
+                       MemoryRead, MemoryWrite, RegisterRead, RegisterWrite};
+use context::arch::ArchInfo;
+use context::builder::ContextBuilder;
+use context::callconv::CallConv;
+use context::event::PathEvent;
+use context::frame::StackFrame;
+use context::heap::Heap;
+use context::image::{self, MemImage};
+use context::reg_profile::RegProfile;
+use context::region::MemRegion;
+use context::sys::SysState;
 
 #[derive(Clone, Debug)]
 pub struct RuneContext {
     ip: u64,
+    arch: ArchInfo,
     pub solver: SMTLib2<qf_abv::QF_ABV>,
     regfile: RuneRegFile,
     mem: RuneMemory,
     e_old: Option<NodeIndex>,
     e_cur: Option<NodeIndex>,
+    e_lastsz: usize,
     /// FIXME
     pub syms: HashMap<String, NodeIndex>,
+    events: Vec<PathEvent>,
+    uninit: UninitPolicy,
+    /// Values of the constants created by `define_const`.
+    consts: HashMap<NodeIndex, u64>,
+    regions: Vec<MemRegion>,
+    sys: SysState,
+    call_conv: Option<CallConv>,
+    entry_frame: Option<StackFrame>,
+    heap: Heap<NodeIndex>,
 }
 
-#[derive(Clone, Debug, Default)]
+/// Value of registers and memory that are read before being written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UninitPolicy {
+    /// Reading an unset register is an error. Memory is zero, as reads of unwritten memory
+    /// cannot be told apart when addresses are symbolic.
+    Panic,
+    Zero,
+    /// A fresh symbol is created on the first read, and recorded in `RuneContext::syms`. A
+    /// register is named after the widest register containing it, a byte of memory read at a
+    /// constant address `mem_<addr in hex>`. Bytes read through other addresses are
+    /// unconstrained, but not named.
+    Symbolic,
+}
+
+impl Default for UninitPolicy {
+    fn default() -> UninitPolicy {
+        UninitPolicy::Panic
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl Default for Endianness {
+    fn default() -> Endianness {
+        Endianness::Little
+    }
+}
+
+/// Byte addressable memory, modelled as an `(Array (_ BitVec n) (_ BitVec 8))` where `n` is the
+/// width of an address.
+///
+/// Accesses wider than a byte are decomposed into selects / stores of the individual bytes, so
+/// overlapping and unaligned accesses of any size are bit-precise.
+#[derive(Clone, Debug)]
 pub struct RuneMemory {
     map: Option<NodeIndex>,
+    /// Contents of memory before any write.
+    base: Option<NodeIndex>,
+    endian: Endianness,
+    addr_width: usize,
+    /// Memory that has not been written is unconstrained, rather than zero.
+    symbolic: bool,
+    /// Bytes written through constant addresses.
+    written: HashSet<u64>,
+    strategy: AddrStrategy,
+    /// Concrete initial contents, shared between the states of all paths.
+    image: Rc<MemImage>,
+    /// Pages of the image that have been loaded.
+    loaded: HashSet<u64>,
+    /// Bytes outside of the image that have been constrained to zero.
+    zeroed: HashSet<u64>,
+}
+
+impl Default for RuneMemory {
+    fn default() -> RuneMemory {
+        RuneMemory::new()
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -64,119 +142,442 @@ impl RegEntry {
 #[derive(Clone, Debug, Default)]
 pub struct RuneRegFile {
     current_regs: Vec<Option<NodeIndex>>,
+    /// Width (in bits) of every register in `current_regs`.
+    widths: Vec<usize>,
     regfile: HashMap<String, RegEntry>,
     alias_info: HashMap<String, String>,
+    /// Set for the registers in `current_regs` that belong to the general purpose arena.
+    general: Vec<bool>,
+    /// Writes to the lower 32 bits of a 64-bit register clear the upper 32 bits (x86-64).
+    zero_extend_32: bool,
 }
 
 impl RuneRegFile {
     pub fn new(reginfo: &mut LRegInfo) -> RuneRegFile {
+        RuneRegFile::from_profile(&RegProfile::from_reginfo(reginfo))
+    }
+
+    pub fn from_profile(profile: &RegProfile) -> RuneRegFile {
         let mut cur_regs = Vec::new();
+        let mut widths = Vec::new();
         let mut regfile = HashMap::new();
-        let mut seen_offsets = Vec::new();
+        // (register arena, bit offset, width) of the registers in `cur_regs`.
+        let mut wholes: Vec<(&str, usize, usize)> = Vec::new();
         let mut alias_info = HashMap::new();
-        reginfo.reg_info.sort_by(|x, y| (y.offset + y.size).cmp(&(x.offset + x.size)));
-        for register in &reginfo.reg_info {
-            let (idx, s_bit, e_bit, is_whole) = if !seen_offsets.contains(&register.offset) &&
-                                                   (register.type_str == "gpr" || register.type_str == "flg") {
-                cur_regs.push(None);
-                seen_offsets.push(register.offset);
-                (cur_regs.len() - 1, 0, register.size - 1, true)
+        // Visit wider registers first, so that a register is always seen after the registers
+        // that contain it.
+        let mut regs = profile.regs.iter().collect::<Vec<_>>();
+        regs.sort_by(|x, y| y.size.cmp(&x.size));
+        for register in regs {
+            if register.size == 0 {
+                continue;
+            }
+            // radare2 places flags in the same arena as the general purpose registers. Offsets
+            // of registers in other arenas are independent of each other.
+            let arena = if register.type_str == "flg" {
+                "gpr"
             } else {
-                let mut found = 0;
-                for (i, offset) in seen_offsets.iter().enumerate() {
-                    if register.offset == *offset {
-                        found = i;
-                        break;
-                    }
-                }
-                (found, 0, register.size - 1, false)
+                &register.type_str[..]
+            };
+            let parent = wholes.iter().position(|&(arena_, offset, width)| {
+                arena_ == arena && register.offset >= offset &&
+                register.offset + register.size <= offset + width
+            });
+
+            let (idx, s_bit, is_whole) = if let Some(i) = parent {
+                (i, register.offset - wholes[i].1, false)
+            } else {
+                cur_regs.push(None);
+                widths.push(register.size);
+                wholes.push((arena, register.offset, register.size));
+                (cur_regs.len() - 1, 0, true)
             };
 
             regfile.insert(register.name.clone(),
-                           RegEntry::new(register.name.clone(), idx, s_bit, e_bit, is_whole, None));
+                           RegEntry::new(register.name.clone(),
+                                         idx,
+                                         s_bit,
+                                         s_bit + register.size - 1,
+                                         is_whole,
+                                         None));
         }
 
-        for alias in &reginfo.alias_info {
-            alias_info.insert(alias.role_str.clone(), alias.reg.clone());
+        for &(ref role, ref reg) in &profile.aliases {
+            alias_info.insert(role.clone(), reg.clone());
             // Add this alias info in the corresponding RegEntry too.
-            if let Some(info) = regfile.get_mut(&alias.reg) {
-                info.alias = Some(alias.role_str.clone());
+            if let Some(info) = regfile.get_mut(reg) {
+                info.alias = Some(role.clone());
             }
         }
 
+        // x86-64 is recognized by its program counter.
+        let zero_extend_32 = alias_info.get("PC").map(|pc| pc == "rip").unwrap_or(false);
+        let general = wholes.iter().map(|&(arena, _, _)| arena == "gpr").collect();
+
         RuneRegFile {
             current_regs: cur_regs,
+            widths: widths,
             regfile: regfile,
             alias_info: alias_info,
+            general: general,
+            zero_extend_32: zero_extend_32,
         }
     }
 
-    fn read(&mut self, reg_name: &str, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> NodeIndex {
-        let rentry = &self.regfile.get(reg_name).expect("Unknown Register");
-        let idx = self.current_regs[rentry.idx].expect("Unset register - Undefined Behavior. \
-                                                        Consider setting an initial value before use!");
-        if rentry.is_whole {
+    /// Set if writes to the lower 32 bits of a 64-bit register clear the upper 32 bits.
+    pub fn set_zero_extend_32(&mut self, zext: bool) {
+        self.zero_extend_32 = zext;
+    }
+
+    /// Name of the widest register containing `reg_name`.
+    pub fn whole_of(&self, reg_name: &str) -> Option<String> {
+        let idx = match self.regfile.get(reg_name) {
+            Some(r) => r.idx,
+            None => return None,
+        };
+        self.regfile
+            .values()
+            .find(|r| r.is_whole && r.idx == idx)
+            .map(|r| r.name.clone())
+    }
+
+    /// Names of the widest general purpose registers and flags, sorted.
+    pub fn general_registers(&self) -> Vec<String> {
+        let mut regs = self.regfile
+                           .values()
+                           .filter(|r| r.is_whole && self.general[r.idx])
+                           .map(|r| r.name.clone())
+                           .collect::<Vec<_>>();
+        regs.sort();
+        regs
+    }
+
+    /// Width of the register in bits.
+    pub fn width_of(&self, reg_name: &str) -> Option<usize> {
+        self.regfile.get(reg_name).map(|r| r.end_bit - r.start_bit + 1)
+    }
+
+    fn read(&mut self,
+            reg_name: &str,
+            solver: &mut SMTLib2<qf_abv::QF_ABV>)
+            -> ContextResult<NodeIndex> {
+        let rentry = try!(self.regfile
+                              .get(reg_name)
+                              .ok_or_else(|| ContextError::UnknownRegister(reg_name.to_owned())));
+        // Reading an unset register is undefined behavior. Consider setting an initial value
+        // before use!
+        let idx = try!(self.current_regs[rentry.idx]
+                           .ok_or_else(|| ContextError::UnsetRegister(reg_name.to_owned())));
+        Ok(if rentry.is_whole {
             idx
         } else {
-            solver.assert(bitvec::OpCodes::Extract((rentry.end_bit) as u64, 0), &[idx])
-        }
+            solver.assert(bitvec::OpCodes::Extract(rentry.end_bit as u64, rentry.start_bit as u64),
+                          &[idx])
+        })
     }
 
-    // TODO: This is not totally correct as the sizes of registers may not match.
-    fn write(&mut self, dest: &str, source: NodeIndex) -> Option<NodeIndex> {
-        let rentry = &self.regfile[dest];
-        let e_old = self.current_regs[rentry.idx];
-        self.current_regs[rentry.idx] = Some(source);
-        e_old
+    // Writes to a sub-register are merged into the register containing it. Bits of the
+    // containing register that have not been set before are taken to be zero.
+    //
+    // Returns the previous value of the register, if any.
+    fn write(&mut self,
+             dest: &str,
+             source: NodeIndex,
+             solver: &mut SMTLib2<qf_abv::QF_ABV>)
+             -> ContextResult<Option<NodeIndex>> {
+        let rentry = try!(self.regfile
+                              .get(dest)
+                              .cloned()
+                              .ok_or_else(|| ContextError::UnknownRegister(dest.to_owned())));
+        let parent = self.current_regs[rentry.idx];
+        if rentry.is_whole {
+            self.current_regs[rentry.idx] = Some(source);
+            return Ok(parent);
+        }
+
+        let e_old = if parent.is_some() {
+            Some(try!(self.read(dest, solver)))
+        } else {
+            None
+        };
+        let width = self.widths[rentry.idx];
+        let (s_bit, e_bit) = (rentry.start_bit, rentry.end_bit);
+        let value = if self.zero_extend_32 && width == 64 && s_bit == 0 && e_bit == 31 {
+            let zero = solver.new_const(bitvec::OpCodes::Const(0, 32));
+            solver.assert(bitvec::OpCodes::Concat, &[zero, source])
+        } else {
+            let (hi, lo) = if let Some(parent) = parent {
+                let hi = if e_bit + 1 < width {
+                    Some(solver.assert(bitvec::OpCodes::Extract(width as u64 - 1,
+                                                                e_bit as u64 + 1),
+                                       &[parent]))
+                } else {
+                    None
+                };
+                let lo = if s_bit > 0 {
+                    Some(solver.assert(bitvec::OpCodes::Extract(s_bit as u64 - 1, 0), &[parent]))
+                } else {
+                    None
+                };
+                (hi, lo)
+            } else {
+                let hi = if e_bit + 1 < width {
+                    Some(solver.new_const(bitvec::OpCodes::Const(0, width - e_bit - 1)))
+                } else {
+                    None
+                };
+                let lo = if s_bit > 0 {
+                    Some(solver.new_const(bitvec::OpCodes::Const(0, s_bit)))
+                } else {
+                    None
+                };
+                (hi, lo)
+            };
+
+            let mut value = source;
+            if let Some(hi) = hi {
+                value = solver.assert(bitvec::OpCodes::Concat, &[hi, value]);
+            }
+            if let Some(lo) = lo {
+                value = solver.assert(bitvec::OpCodes::Concat, &[value, lo]);
+            }
+            value
+        };
+
+        self.current_regs[rentry.idx] = Some(value);
+        Ok(e_old)
     }
 }
 
 impl RuneMemory {
     pub fn new() -> RuneMemory {
-        RuneMemory { map: None }
+        RuneMemory::with_endianness(Endianness::Little)
+    }
+
+    pub fn with_endianness(endian: Endianness) -> RuneMemory {
+        RuneMemory {
+            map: None,
+            base: None,
+            endian: endian,
+            addr_width: 64,
+            symbolic: false,
+            written: HashSet::new(),
+            strategy: AddrStrategy::default(),
+            image: Rc::new(MemImage::new()),
+            loaded: HashSet::new(),
+            zeroed: HashSet::new(),
+        }
+    }
+
+    /// Memory with the address width and byte order of `arch`.
+    pub fn with_arch(arch: &ArchInfo) -> RuneMemory {
+        RuneMemory {
+            addr_width: arch.addr_width(),
+            ..RuneMemory::with_endianness(arch.endian)
+        }
+    }
+
+    pub fn endianness(&self) -> Endianness {
+        self.endian
+    }
+
+    pub fn addr_width(&self) -> usize {
+        self.addr_width
+    }
+
+    pub fn strategy(&self) -> AddrStrategy {
+        self.strategy
+    }
+
+    /// Set how accesses at symbolic addresses are handled.
+    pub fn set_strategy(&mut self, strategy: AddrStrategy) {
+        self.strategy = strategy;
+    }
+
+    /// Set if memory that has not been written is unconstrained rather than zero. Only has an
+    /// effect before memory is first accessed.
+    pub fn set_symbolic(&mut self, symbolic: bool) {
+        self.symbolic = symbolic;
+    }
+
+    /// Set the concrete initial contents of memory. See `RuneMemory::load`.
+    pub fn set_image(&mut self, image: MemImage) {
+        self.image = Rc::new(image);
+    }
+
+    pub fn image(&self) -> &MemImage {
+        &self.image
+    }
+
+    /// True if the page of the image at `page * image::PAGE_SIZE` has been loaded.
+    pub fn is_loaded(&self, page: u64) -> bool {
+        self.loaded.contains(&page)
     }
 
     pub fn init_memory(&mut self, solver: &mut SMTLib2<qf_abv::QF_ABV>) {
-        let bv_array = qf_abv::array_sort(qf_abv::bv_sort(64), qf_abv::bv_sort(64));
+        let bv_array = qf_abv::array_sort(qf_abv::bv_sort(self.addr_width), qf_abv::bv_sort(8));
         let idx_ = solver.new_var(Some("mem"), bv_array);
-        // Set memory to all 0s
-        let arr_const_ty = qf_abv::array_const(qf_abv::bv_sort(64),
-                                               qf_abv::bv_sort(64),
-                                               bitvec::OpCodes::Const(0, 64));
-        let const_0 = solver.new_const(arr_const_ty);
-        solver.assert(core::OpCodes::Cmp, &[idx_, const_0]);
+        // With an image, zero memory is set up a byte at a time by `load`.
+        if !self.symbolic && self.image.is_empty() {
+            // Set memory to all 0s
+            let arr_const_ty = qf_abv::array_const(qf_abv::bv_sort(self.addr_width),
+                                                   qf_abv::bv_sort(8),
+                                                   bitvec::OpCodes::Const(0, 8));
+            let const_0 = solver.new_const(arr_const_ty);
+            solver.assert(core::OpCodes::Cmp, &[idx_, const_0]);
+        }
         self.map = Some(idx_);
+        self.base = Some(idx_);
+    }
+
+    /// The array holding the contents of memory before any write.
+    pub fn base(&mut self, solver: &mut SMTLib2<qf_abv::QF_ABV>) -> NodeIndex {
+        if self.base.is_none() {
+            self.init_memory(solver);
+        }
+        self.base.unwrap()
+    }
+
+    /// True if the byte at `addr` has been written through a constant address.
+    pub fn is_written(&self, addr: u64) -> bool {
+        self.written.contains(&addr)
+    }
+
+    fn addr_mask(&self) -> u64 {
+        if self.addr_width >= 64 {
+            u64::max_value()
+        } else {
+            (1 << self.addr_width) - 1
+        }
+    }
+
+    // Remember the bytes of a write of `n` bytes at the constant address `addr`.
+    fn mark_written(&mut self, addr: u64, n: u64) {
+        let mask = self.addr_mask();
+        for i in 0..n {
+            self.written.insert(addr.wrapping_add(i) & mask);
+        }
+    }
+
+    /// Constrain the initial contents of the `n` bytes at the constant address `addr`, before
+    /// they are read. The first read of a page of the image loads the whole page. Bytes outside
+    /// of the image are zero, unless memory is symbolic.
+    ///
+    /// Memory read at symbolic addresses only holds the image where it has already been loaded,
+    /// and is unconstrained elsewhere.
+    pub fn load(&mut self, addr: u64, n: u64, solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        if self.image.is_empty() {
+            return;
+        }
+        let base = self.base(solver);
+        let mask = self.addr_mask();
+        for i in 0..n {
+            let addr_ = addr.wrapping_add(i) & mask;
+            let page = addr_ / image::PAGE_SIZE;
+            if self.image.byte(addr_).is_some() {
+                if self.loaded.insert(page) {
+                    for (a, byte) in self.image.page(page) {
+                        self.init_byte(base, a, byte, solver);
+                    }
+                }
+            } else if !self.symbolic && !self.written.contains(&addr_) &&
+                      self.zeroed.insert(addr_) {
+                self.init_byte(base, addr_, 0, solver);
+            }
+        }
+    }
+
+    fn init_byte(&self,
+                 base: NodeIndex,
+                 addr: u64,
+                 byte: u8,
+                 solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+        let addr = solver.new_const(bitvec::OpCodes::Const(addr, self.addr_width));
+        let byte = solver.new_const(bitvec::OpCodes::Const(byte as u64, 8));
+        let initial = solver.assert(array_ex::OpCodes::Select, &[base, addr]);
+        solver.assert(core::OpCodes::Cmp, &[initial, byte]);
+    }
+
+    // Address of the `i`th byte of an access at `addr`.
+    fn byte_addr(&self,
+                 addr: NodeIndex,
+                 i: u64,
+                 solver: &mut SMTLib2<qf_abv::QF_ABV>)
+                 -> NodeIndex {
+        if i == 0 {
+            addr
+        } else {
+            let offset = solver.new_const(bitvec::OpCodes::Const(i, self.addr_width));
+            solver.assert(bitvec::OpCodes::BvAdd, &[addr, offset])
+        }
+    }
+
+    // Position of the `i`th byte in memory within a value of `n` bytes, counting from the least
+    // significant byte.
+    fn byte_pos(&self, i: u64, n: u64) -> u64 {
+        match self.endian {
+            Endianness::Little => i,
+            Endianness::Big => n - 1 - i,
+        }
     }
 
     pub fn read(&mut self,
                 addr: NodeIndex,
                 read_size: u64,
                 solver: &mut SMTLib2<qf_abv::QF_ABV>)
-                -> NodeIndex {
+                -> ContextResult<NodeIndex> {
+        if read_size == 0 || read_size % 8 != 0 {
+            return Err(ContextError::InvalidAccessSize(read_size));
+        }
         if self.map.is_none() {
             self.init_memory(solver);
         }
         let mem = self.map.unwrap();
-        let idx = solver.assert(array_ex::OpCodes::Select, &[mem, addr]);
-        if read_size < 64 {
-            solver.assert(bitvec::OpCodes::Extract(read_size - 1, 1), &[idx])
-        } else {
-            idx
+        let n = read_size / 8;
+
+        // bytes[k] is the k-th least significant byte of the result.
+        let mut bytes = vec![None; n as usize];
+        for i in 0..n {
+            let baddr = self.byte_addr(addr, i, solver);
+            let byte = solver.assert(array_ex::OpCodes::Select, &[mem, baddr]);
+            bytes[self.byte_pos(i, n) as usize] = Some(byte);
         }
+
+        // Concat places its first operand in the most significant bits.
+        let mut result = bytes[0].unwrap();
+        for byte in bytes.into_iter().skip(1) {
+            result = solver.assert(bitvec::OpCodes::Concat, &[byte.unwrap(), result]);
+        }
+        Ok(result)
     }
 
-    // TODO: Need to handle the case where write_size is not 64.
     pub fn write(&mut self,
                  addr: NodeIndex,
                  data: NodeIndex,
-                 _write_size: u64,
-                 solver: &mut SMTLib2<qf_abv::QF_ABV>) {
+                 write_size: u64,
+                 solver: &mut SMTLib2<qf_abv::QF_ABV>)
+                 -> ContextResult<()> {
+        if write_size == 0 || write_size % 8 != 0 {
+            return Err(ContextError::InvalidAccessSize(write_size));
+        }
         if self.map.is_none() {
             self.init_memory(solver);
         }
-        let mem = self.map.unwrap();
-        let new_mem = solver.assert(array_ex::OpCodes::Store, &[mem, addr, data]);
-        self.map = Some(new_mem);
+        let mut mem = self.map.unwrap();
+        let n = write_size / 8;
+
+        for i in 0..n {
+            let baddr = self.byte_addr(addr, i, solver);
+            let byte = if n == 1 {
+                data
+            } else {
+                let low = self.byte_pos(i, n) * 8;
+                solver.assert(bitvec::OpCodes::Extract(low + 7, low), &[data])
+            };
+            mem = solver.assert(array_ex::OpCodes::Store, &[mem, baddr, byte]);
+        }
+        self.map = Some(mem);
+        Ok(())
     }
 }
 
@@ -189,14 +590,20 @@ impl Context for RuneContext {
         self.e_cur = Some(i);
     }
 
-    fn e_old(&self) -> NodeIndex {
-        assert!(self.e_old.is_some(), "e_old accessed before being set!");
-        self.e_old.unwrap()
+    fn set_e_lastsz(&mut self, size: usize) {
+        self.e_lastsz = size;
     }
 
-    fn e_cur(&self) -> NodeIndex {
-        assert!(self.e_cur.is_some(), "e_cur accessed before being set!");
-        self.e_cur.unwrap()
+    fn e_lastsz(&self) -> usize {
+        self.e_lastsz
+    }
+
+    fn try_e_old(&self) -> ContextResult<NodeIndex> {
+        self.e_old.ok_or(ContextError::UnsetInternalVar)
+    }
+
+    fn try_e_cur(&self) -> ContextResult<NodeIndex> {
+        self.e_cur.ok_or(ContextError::UnsetInternalVar)
     }
 
     fn ip(&self) -> u64 {
@@ -208,34 +615,130 @@ impl Context for RuneContext {
     }
 
     fn increment_ip(&mut self, by: u64) {
-        self.ip += by;
+        self.ip = self.ip.wrapping_add(by) & self.arch.addr_mask();
     }
 
     fn set_ip(&mut self, to: u64) {
-        self.ip = to;
+        self.ip = to & self.arch.addr_mask();
+    }
+
+    fn arch(&self) -> ArchInfo {
+        self.arch
+    }
+
+    fn addr_strategy(&self) -> AddrStrategy {
+        self.mem.strategy()
+    }
+
+    fn call_conv(&self) -> Option<CallConv> {
+        self.call_conv
+    }
+
+    fn entry_return(&self) -> Option<u64> {
+        self.entry_frame.map(|frame| frame.ret_addr)
     }
 
     fn define_const(&mut self, c: u64, size: usize) -> NodeIndex {
-        self.solver.new_const(bitvec::OpCodes::Const(c, size))
+        let idx = self.solver.new_const(bitvec::OpCodes::Const(c, size));
+        self.consts.insert(idx, c);
+        idx
+    }
+
+    fn define_var<T: AsRef<str>>(&mut self, name: T, size: usize) -> NodeIndex {
+        self.solver.new_var(Some(name.as_ref()), qf_abv::bv_sort(size))
+    }
+
+    fn define_sym<T: AsRef<str>>(&mut self, name: T, size: usize) -> NodeIndex {
+        let sym = self.define_var(name.as_ref(), size);
+        self.syms.insert(name.as_ref().to_owned(), sym);
+        sym
+    }
+
+    fn const_value(&self, v: &NodeIndex) -> Option<u64> {
+        self.consts.get(v).cloned()
     }
 
     fn alias_of(&self, reg: String) -> Option<String> {
-        self.regfile.regfile[&reg].alias.clone()
+        self.regfile.regfile.get(&reg).and_then(|r| r.alias.clone())
+    }
+
+    fn reg_width<T: AsRef<str>>(&self, reg: T) -> Option<usize> {
+        self.regfile.width_of(reg.as_ref())
+    }
+
+    fn general_registers(&self) -> Vec<String> {
+        self.regfile.general_registers()
     }
 
-    fn solve<S: SMTProc>(&mut self, p: &mut S) -> HashMap<NodeIndex, u64> {
-        self.solver.solve(p).expect("No satisfying solution.")
+    fn try_solve<S: SMTProc>(&mut self, p: &mut S) -> ContextResult<HashMap<NodeIndex, u64>> {
+        self.solver.solve(p).map_err(|_| ContextError::Unsat)
+    }
+
+    fn check_sat<S: SMTProc>(&mut self, p: &mut S) -> bool {
+        self.solver.check_sat(p)
     }
 
     fn var_named<T: AsRef<str>>(&self, var: T) -> Option<NodeIndex> {
         self.syms.get(var.as_ref()).cloned()
     }
+
+    fn regions(&self) -> &[MemRegion] {
+        &self.regions
+    }
+
+    /// Declare a mapped region of memory. Once there are regions, accesses outside of them are
+    /// reported by the engine.
+    fn add_region(&mut self, region: MemRegion) {
+        self.regions.push(region);
+    }
+
+    fn symbols(&self) -> Vec<(String, NodeIndex)> {
+        self.syms.iter().map(|(k, v)| (k.clone(), *v)).collect()
+    }
+
+    fn record_event(&mut self, event: PathEvent) {
+        self.events.push(event);
+    }
+
+    fn events(&self) -> &[PathEvent] {
+        &self.events
+    }
+
+    fn sys_state(&self) -> &SysState {
+        &self.sys
+    }
+
+    fn sys_state_mut(&mut self) -> &mut SysState {
+        &mut self.sys
+    }
+
+    fn heap(&self) -> &Heap<NodeIndex> {
+        &self.heap
+    }
+
+    fn heap_mut(&mut self) -> &mut Heap<NodeIndex> {
+        &mut self.heap
+    }
+}
+
+// Name of the symbol for initial memory at `addr`.
+fn mem_sym_name(addr: u64) -> String {
+    format!("mem_{:#x}", addr)
 }
 
 impl RegisterRead for RuneContext {
     type VarRef = NodeIndex;
 
-    fn reg_read<T: AsRef<str>>(&mut self, reg: T) -> NodeIndex {
+    fn try_reg_read<T: AsRef<str>>(&mut self, reg: T) -> ContextResult<NodeIndex> {
+        if self.uninit != UninitPolicy::Panic && !self.is_reg_set(reg.as_ref()) {
+            if let Some(whole) = self.regfile.whole_of(reg.as_ref()) {
+                if self.uninit == UninitPolicy::Zero {
+                    try!(self.set_reg_as_const(whole, 0));
+                } else {
+                    try!(self.set_reg_as_sym(whole));
+                }
+            }
+        }
         self.regfile.read(reg.as_ref(), &mut self.solver)
     }
 }
@@ -243,20 +746,34 @@ impl RegisterRead for RuneContext {
 impl RegisterWrite for RuneContext {
     type VarRef = NodeIndex;
 
-    fn reg_write<T: AsRef<str>>(&mut self, reg: T, source: NodeIndex) {
-        let e_old = self.regfile.write(reg.as_ref(), source);
-        // XXX: THIS IS A HACK!
-        if !reg.as_ref().to_owned().ends_with('f') {
-            self.e_old = e_old;
-            self.e_cur = Some(source);
+    fn try_reg_write<T: AsRef<str>>(&mut self, reg: T, source: NodeIndex) -> ContextResult<()> {
+        let e_old = try!(self.regfile.write(reg.as_ref(), source, &mut self.solver));
+        // Flags are single bit registers that are set from the internal variables of the last
+        // operation. Writing them must not replace the last operation.
+        let width = self.regfile.width_of(reg.as_ref()).unwrap();
+        if width > 1 {
+            // Internal variables are evaluated on 64 bit values.
+            self.e_old = match e_old {
+                Some(old) => Some(self.zero_extend_64(old, width)),
+                None => None,
+            };
+            self.e_cur = Some(self.zero_extend_64(source, width));
+            self.e_lastsz = width;
         }
+        Ok(())
     }
 }
 
 impl MemoryRead for RuneContext {
     type VarRef = NodeIndex;
 
-    fn mem_read(&mut self, addr: NodeIndex, size: u64) -> NodeIndex {
+    fn try_mem_read(&mut self, addr: NodeIndex, size: u64) -> ContextResult<NodeIndex> {
+        if let Some(&addr_) = self.consts.get(&addr) {
+            self.mem.load(addr_, size / 8, &mut self.solver);
+            if self.uninit == UninitPolicy::Symbolic {
+                self.name_mem(addr_, size / 8);
+            }
+        }
         self.mem.read(addr, size, &mut self.solver)
     }
 }
@@ -264,8 +781,16 @@ impl MemoryRead for RuneContext {
 impl  MemoryWrite for RuneContext {
     type VarRef = NodeIndex;
 
-    fn mem_write(&mut self, addr: NodeIndex, data: NodeIndex, write_size: u64) {
-        self.mem.write(addr, data, write_size, &mut self.solver);
+    fn try_mem_write(&mut self,
+                     addr: NodeIndex,
+                     data: NodeIndex,
+                     write_size: u64)
+                     -> ContextResult<()> {
+        try!(self.mem.write(addr, data, write_size, &mut self.solver));
+        if let Some(&addr_) = self.consts.get(&addr) {
+            self.mem.mark_written(addr_, write_size / 8);
+        }
+        Ok(())
     }
 }
 
@@ -277,61 +802,75 @@ impl Evaluate for RuneContext {
         where T: Into<Self::IFn>,
               Q: AsRef<[Self::VarRef]>
     {
-        // TODO: Add extract / concat to ensure that the registers are of compatible
-        // sizes for
-        // operations.
+        // Operands are expected to be of compatible sizes. The engine extends or extracts them
+        // as needed before evaluating an operation.
         self.solver.assert(smt_fn, &operands.as_ref())
     }
 }
 
 impl ContextAPI for RuneContext {
-    fn set_reg_as_const<T: AsRef<str>>(&mut self, reg: T, val: u64) -> NodeIndex {
-        let rentry = self.regfile.regfile[reg.as_ref()].clone();
-        // Assert that the register is not currently set/defined.
-        assert!(self.regfile.current_regs[rentry.idx].is_none());
-        let cval = self.define_const(val, 64);
-        self.regfile.current_regs[rentry.idx] = Some(cval);
-        cval
-    }
-
-    fn set_reg_as_sym<T: AsRef<str>>(&mut self, reg: T) -> NodeIndex {
-        let rentry = self.regfile.regfile[reg.as_ref()].clone();
-        // Assert that the register is not currently set/defined.
-        assert!(self.regfile.current_regs[rentry.idx].is_none());
-        let sym = self.solver.new_var(Some(reg.as_ref()), qf_abv::bv_sort(64));
-        self.regfile.current_regs[rentry.idx] = Some(sym);
+    fn set_reg_as_const<T: AsRef<str>>(&mut self, reg: T, val: u64) -> ContextResult<NodeIndex> {
+        let width = try!(self.unset_reg_width(reg.as_ref()));
+        let cval = self.define_const(val, width);
+        try!(self.regfile.write(reg.as_ref(), cval, &mut self.solver));
+        Ok(cval)
+    }
+
+    fn set_reg_as_sym<T: AsRef<str>>(&mut self, reg: T) -> ContextResult<NodeIndex> {
+        let width = try!(self.unset_reg_width(reg.as_ref()));
+        let sym = self.solver.new_var(Some(reg.as_ref()), qf_abv::bv_sort(width));
+        try!(self.regfile.write(reg.as_ref(), sym, &mut self.solver));
         self.syms.insert(reg.as_ref().to_owned(), sym);
-        sym
+        Ok(sym)
     }
 
-    fn set_mem_as_const(&mut self, addr: usize, val: u64, write_size: u64) -> NodeIndex {
+    fn set_mem_as_const(&mut self,
+                        addr: usize,
+                        val: u64,
+                        write_size: u64)
+                        -> ContextResult<NodeIndex> {
         let cval = self.define_const(val, write_size as usize);
-        let addr = self.define_const(addr as u64, 64);
-        // TODO
-        if write_size < 64 {
-            unimplemented!();
-        } else {
-            self.mem_write(addr, cval, 64);
-        }
-        cval
+        let addr_width = self.mem.addr_width();
+        let addr = self.define_const(addr as u64, addr_width);
+        try!(self.try_mem_write(addr, cval, write_size));
+        Ok(cval)
     }
 
-    fn set_mem_as_sym(&mut self, addr: usize, write_size: u64) -> NodeIndex {
-        assert!(write_size == 64,
-                "TODO: Unimplemented set_mem for size < 64!");
-        let key = format!("mem_{}", addr);
-        let sym = self.solver.new_var(Some(&key), qf_abv::bv_sort(64));
-        let addr = self.define_const(addr as u64, 64);
-        self.mem_write(addr, sym, write_size);
+    fn set_mem_as_sym(&mut self, addr: usize, write_size: u64) -> ContextResult<NodeIndex> {
+        let key = mem_sym_name(addr as u64);
+        let sym = self.solver.new_var(Some(&key), qf_abv::bv_sort(write_size as usize));
+        let addr_width = self.mem.addr_width();
+        let addr = self.define_const(addr as u64, addr_width);
+        try!(self.try_mem_write(addr, sym, write_size));
         self.syms.insert(key, sym);
-        sym
+        Ok(sym)
+    }
+
+    fn set_arg_sym(&mut self, i: usize, width: usize) -> ContextResult<NodeIndex> {
+        let cc = try!(self.call_conv.ok_or(ContextError::NoCallConv));
+        let sym = self.define_sym(format!("arg{}", i), width);
+        try!(cc.set_arg(self, i, sym, width));
+        Ok(sym)
+    }
+
+    fn set_arg_const(&mut self, i: usize, val: u64) -> ContextResult<NodeIndex> {
+        let cc = try!(self.call_conv.ok_or(ContextError::NoCallConv));
+        let width = cc.word_size();
+        let cval = self.define_const(val, width);
+        try!(cc.set_arg(self, i, cval, width));
+        Ok(cval)
+    }
+
+    fn return_value(&mut self) -> ContextResult<NodeIndex> {
+        let cc = try!(self.call_conv.ok_or(ContextError::NoCallConv));
+        cc.return_value(self)
     }
 
     fn zero_registers(&mut self) {
-        let cval = Some(self.define_const(0, 64));
-        for reg in &mut self.regfile.current_regs {
-            if reg.is_none() {
-                *reg = cval;
+        for i in 0..self.regfile.current_regs.len() {
+            if self.regfile.current_regs[i].is_none() {
+                let width = self.regfile.widths[i];
+                self.regfile.current_regs[i] = Some(self.define_const(0, width));
             }
         }
     }
@@ -342,29 +881,133 @@ impl ContextAPI for RuneContext {
 }
 
 impl RuneContext {
+    /// Start building a context. See `ContextBuilder`.
+    pub fn builder() -> ContextBuilder {
+        ContextBuilder::new()
+    }
+
     pub fn new(ip: Option<u64>,
                mem: RuneMemory,
                regfile: RuneRegFile,
-               solver: SMTLib2<qf_abv::QF_ABV>)
+               solver: SMTLib2<qf_abv::QF_ABV>,
+               arch: ArchInfo)
                -> RuneContext {
         RuneContext {
             ip: ip.unwrap_or(0),
+            arch: arch,
             mem: mem,
             regfile: regfile,
             solver: solver,
             e_old: None,
             e_cur: None,
+            e_lastsz: 64,
             syms: HashMap::new(),
+            events: Vec::new(),
+            uninit: UninitPolicy::default(),
+            consts: HashMap::new(),
+            regions: Vec::new(),
+            sys: SysState::default(),
+            call_conv: CallConv::for_arch(&arch),
+            entry_frame: None,
+            heap: Heap::default(),
+        }
+    }
+
+    pub fn uninit_policy(&self) -> UninitPolicy {
+        self.uninit
+    }
+
+    /// Set the value of registers and memory that are read before being written. Memory is
+    /// only affected if it has not been accessed yet.
+    pub fn set_uninit_policy(&mut self, policy: UninitPolicy) {
+        self.uninit = policy;
+        self.mem.set_symbolic(policy == UninitPolicy::Symbolic);
+    }
+
+    pub fn set_addr_strategy(&mut self, strategy: AddrStrategy) {
+        self.mem.set_strategy(strategy);
+    }
+
+    /// Override the calling convention, which defaults to the usual one of the architecture.
+    pub fn set_call_conv(&mut self, cc: CallConv) {
+        self.call_conv = Some(cc);
+    }
+
+    /// Stack frame set up for the function execution started in. See `ContextBuilder::frame`.
+    pub fn entry_frame(&self) -> Option<&StackFrame> {
+        self.entry_frame.as_ref()
+    }
+
+    pub fn set_entry_frame(&mut self, frame: StackFrame) {
+        self.entry_frame = Some(frame);
+    }
+
+    /// Set the concrete initial contents of memory, loaded a page at a time as it is read. Only
+    /// has an effect if memory has not been accessed yet.
+    pub fn set_image(&mut self, image: MemImage) {
+        self.mem.set_image(image);
+    }
+
+    pub fn memory(&self) -> &RuneMemory {
+        &self.mem
+    }
+
+    // Name the bytes of initial memory at `addr..addr + n` that are read before being written.
+    fn name_mem(&mut self, addr: u64, n: u64) {
+        let base = self.mem.base(&mut self.solver);
+        let addr_width = self.mem.addr_width();
+        for i in 0..n {
+            let addr_ = addr.wrapping_add(i) & self.arch.addr_mask();
+            let key = mem_sym_name(addr_);
+            if self.mem.is_written(addr_) || self.mem.image().byte(addr_).is_some() ||
+               self.syms.contains_key(&key) {
+                continue;
+            }
+            let byte = self.define_var(&key, 8);
+            let addr_ = self.define_const(addr_, addr_width);
+            let initial = self.solver.assert(array_ex::OpCodes::Select, &[base, addr_]);
+            self.solver.assert(core::OpCodes::Cmp, &[initial, byte]);
+            self.syms.insert(key, byte);
+        }
+    }
+
+    /// True if the register, or a register overlapping it, has been set.
+    pub fn is_reg_set(&self, reg: &str) -> bool {
+        self.regfile
+            .regfile
+            .get(reg)
+            .map(|r| self.regfile.current_regs[r.idx].is_some())
+            .unwrap_or(false)
+    }
+
+    // Width of `reg`, which must not have been given a value yet.
+    fn unset_reg_width(&self, reg: &str) -> ContextResult<usize> {
+        let width = try!(self.regfile
+                             .width_of(reg)
+                             .ok_or_else(|| ContextError::UnknownRegister(reg.to_owned())));
+        if self.is_reg_set(reg) {
+            return Err(ContextError::RegisterAlreadySet(reg.to_owned()));
+        }
+        Ok(width)
+    }
+
+    // Zero extend a value of `width` bits to 64 bits.
+    fn zero_extend_64(&mut self, v: NodeIndex, width: usize) -> NodeIndex {
+        if width < 64 {
+            let zero = self.solver.new_const(bitvec::OpCodes::Const(0, 64 - width));
+            self.solver.assert(bitvec::OpCodes::Concat, &[zero, v])
+        } else {
+            v
         }
     }
 }
 
 #[cfg(test)]
-mod test {
+pub mod test {
     use super::*;
     use context::context::{Context, ContextAPI, Evaluate, MemoryRead, MemoryWrite, RegisterRead,
                            RegisterWrite};
-    use context::utils;
+    use context::image::MemImage;
 
     use libsmt::logics::qf_abv;
     use libsmt::backends::smtlib2::SMTLib2;
@@ -374,7 +1017,7 @@ mod test {
 
     #[test]
     fn ctx_reg_write() {
-        let mut ctx = utils::new_ctx(None, None, None);
+        let mut ctx = RuneContext::builder().build().unwrap();
         let const_8 = ctx.define_const(8, 64);
 
         // Test setting rax to 8
@@ -388,9 +1031,9 @@ mod test {
 
     #[test]
     fn ctx_reg_read() {
-        let mut ctx = utils::new_ctx(None, None, None);
+        let mut ctx = RuneContext::builder().build().unwrap();
 
-        ctx.set_reg_as_sym("rax");
+        ctx.set_reg_as_sym("rax").unwrap();
 
         // We set rax to be some value, and add constraints on sub-registers of rax. if
         // all is
@@ -417,10 +1060,10 @@ mod test {
 
     #[test]
     fn ctx_reg_solve_simple() {
-        let mut ctx = utils::new_ctx(None, None, None);
+        let mut ctx = RuneContext::builder().build().unwrap();
         // Set rdi and rsi as symbolic
-        ctx.set_reg_as_sym("rdi");
-        ctx.set_reg_as_sym("rsi");
+        ctx.set_reg_as_sym("rdi").unwrap();
+        ctx.set_reg_as_sym("rsi").unwrap();
 
         let const_deadbeef = ctx.define_const(0x0000dead0000beef, 64);
         let const_0_32 = ctx.define_const(0, 32);
@@ -449,9 +1092,9 @@ mod test {
 
     #[test]
     fn ctx_mem_read_write() {
-        let mut ctx = utils::new_ctx(None, None, None);
+        let mut ctx = RuneContext::builder().build().unwrap();
 
-        ctx.set_reg_as_sym("rax");
+        ctx.set_reg_as_sym("rax").unwrap();
 
         let rax = ctx.reg_read("rax");
         let addr = ctx.define_const(0xbadcafe, 64);
@@ -468,10 +1111,12 @@ mod test {
 
     #[test]
     fn ctx_mem_sym_read_write() {
-        let mut ctx = utils::new_ctx(None, None, None);
+        let mut ctx = RuneContext::builder().build().unwrap();
 
-        ctx.set_mem_as_sym(0xff41, 64);
-        ctx.set_mem_as_sym(0xfe41, 64);
+        ctx.set_mem_as_sym(0xff41, 64).unwrap();
+        ctx.set_mem_as_sym(0xfe41, 64).unwrap();
+        assert!(ctx.syms.contains_key("mem_0xff41"));
+        assert!(ctx.syms.contains_key("mem_0xfe41"));
 
         let addr = ctx.define_const(0xff41, 64);
         let addr_ = ctx.define_const(0xfe41, 64);
@@ -493,9 +1138,93 @@ mod test {
         // TODO: Test does not assert correctness yet.
     }
 
+    #[test]
+    fn ctx_mem_unaligned_read_write() {
+        let mut ctx = RuneContext::builder().build().unwrap();
+
+        ctx.set_mem_as_const(0x1000, 0x1122334455667788, 64).unwrap();
+        // Overwrite the third byte.
+        ctx.set_mem_as_const(0x1002, 0xaa, 8).unwrap();
+
+        let byte = ctx.define_var("byte", 8);
+        let word = ctx.define_var("word", 16);
+        let dword = ctx.define_var("dword", 32);
+
+        let addr_1 = ctx.define_const(0x1001, 64);
+        let addr_6 = ctx.define_const(0x1006, 64);
+        let byte_ = ctx.mem_read(addr_1, 8);
+        let word_ = ctx.mem_read(addr_6, 16);
+        let dword_ = ctx.mem_read(addr_1, 32);
+
+        ctx.eval(core::OpCodes::Cmp, &[byte, byte_]);
+        ctx.eval(core::OpCodes::Cmp, &[word, word_]);
+        ctx.eval(core::OpCodes::Cmp, &[dword, dword_]);
+
+        let result = ctx.solve(&mut solver());
+        assert_eq!(result[&byte], 0x77);
+        assert_eq!(result[&word], 0x1122);
+        assert_eq!(result[&dword], 0x4455aa77);
+    }
+
+    #[test]
+    fn ctx_set_reg_errors() {
+        let mut ctx = RuneContext::builder().build().unwrap();
+        assert_eq!(ctx.set_reg_as_const("foo", 0),
+                   Err(ContextError::UnknownRegister("foo".to_owned())));
+        assert_eq!(ctx.set_reg_as_sym("foo"),
+                   Err(ContextError::UnknownRegister("foo".to_owned())));
+
+        ctx.set_reg_as_const("rax", 1).unwrap();
+        assert_eq!(ctx.set_reg_as_sym("rax"),
+                   Err(ContextError::RegisterAlreadySet("rax".to_owned())));
+        // Sub-registers share the value of the whole register.
+        assert_eq!(ctx.set_reg_as_const("al", 2),
+                   Err(ContextError::RegisterAlreadySet("al".to_owned())));
+    }
+
+    #[test]
+    fn ctx_set_mem_errors() {
+        let mut ctx = RuneContext::builder().build().unwrap();
+        assert_eq!(ctx.set_mem_as_const(0x1000, 1, 12),
+                   Err(ContextError::InvalidAccessSize(12)));
+        assert_eq!(ctx.set_mem_as_sym(0x1000, 12), Err(ContextError::InvalidAccessSize(12)));
+        assert!(!ctx.syms.contains_key("mem_0x1000"));
+    }
+
+    #[test]
+    fn ctx_sub_reg_write() {
+        let mut ctx = RuneContext::builder().build().unwrap();
+        ctx.set_reg_as_const("rax", 0x1122334455667788).unwrap();
+        ctx.set_reg_as_const("rbx", 0x1122334455667788).unwrap();
+
+        // 8 and 16 bit writes preserve the rest of the register.
+        let const_ff = ctx.define_const(0xff, 8);
+        let const_ee = ctx.define_const(0xee, 8);
+        ctx.reg_write("al", const_ff);
+        ctx.reg_write("ah", const_ee);
+        // 32 bit writes clear the upper half.
+        let const_cafe = ctx.define_const(0xcafe, 32);
+        ctx.reg_write("ebx", const_cafe);
+
+        let rax = ctx.define_var("rax_", 64);
+        let rbx = ctx.define_var("rbx_", 64);
+        let ah = ctx.define_var("ah_", 8);
+        let rax_ = ctx.reg_read("rax");
+        let rbx_ = ctx.reg_read("rbx");
+        let ah_ = ctx.reg_read("ah");
+        ctx.eval(core::OpCodes::Cmp, &[rax, rax_]);
+        ctx.eval(core::OpCodes::Cmp, &[rbx, rbx_]);
+        ctx.eval(core::OpCodes::Cmp, &[ah, ah_]);
+
+        let result = ctx.solve(&mut solver());
+        assert_eq!(result[&rax], 0x112233445566eeff);
+        assert_eq!(result[&rbx], 0xcafe);
+        assert_eq!(result[&ah], 0xee);
+    }
+
     #[test]
     fn ctx_test_ip() {
-        let mut ctx = utils::new_ctx(None, None, None);
+        let mut ctx = RuneContext::builder().build().unwrap();
 
         ctx.set_ip(0xbadcafe);
         assert_eq!(ctx.ip(), 0xbadcafe);
@@ -506,27 +1235,38 @@ mod test {
 
     #[test]
     fn ctx_test_alias() {
-        let ctx = utils::new_ctx(None, None, None);
+        let ctx = RuneContext::builder().build().unwrap();
         assert_eq!(ctx.alias_of("rip".to_owned()), Some("PC".to_owned()));
     }
 
+    #[test]
+    fn ctx_general_registers() {
+        let ctx = RuneContext::builder().build().unwrap();
+        let regs = ctx.general_registers();
+        assert!(regs.contains(&"rax".to_owned()));
+        assert!(regs.contains(&"rflags".to_owned()));
+        assert!(!regs.contains(&"eax".to_owned()));
+        assert!(!regs.contains(&"cf".to_owned()));
+        assert!(!regs.contains(&"fs".to_owned()));
+    }
+
     #[test]
     #[should_panic]
     fn ctx_unset_access_esil_old() {
-        let ctx = utils::new_ctx(None, None, None);
+        let ctx = RuneContext::builder().build().unwrap();
         ctx.e_old();
     }
 
     #[test]
     #[should_panic]
     fn ctx_unset_access_esil_cur() {
-        let ctx = utils::new_ctx(None, None, None);
+        let ctx = RuneContext::builder().build().unwrap();
         ctx.e_cur();
     }
 
     #[test]
     fn ctx_access_esil_old_cur() {
-        let mut ctx = utils::new_ctx(None, None, None);
+        let mut ctx = RuneContext::builder().build().unwrap();
         let const_8 = ctx.define_const(8, 64);
         let const_32 = ctx.define_const(32, 64);
 
@@ -550,14 +1290,84 @@ mod test {
     #[test]
     #[should_panic]
     fn ctx_read_before_set() {
-        let mut ctx = utils::new_ctx(None, None, None);
+        let mut ctx = RuneContext::builder().build().unwrap();
         ctx.reg_read("zf");
     }
 
     #[test]
     #[should_panic]
     fn ctx_invalid_reg() {
-        let mut ctx = utils::new_ctx(None, None, None);
+        let mut ctx = RuneContext::builder().build().unwrap();
         ctx.reg_read("asassa");
     }
+
+    #[test]
+    fn ctx_uninit_policies() {
+        let mut ctx = RuneContext::builder().build().unwrap();
+        match ctx.try_reg_read("rbx") {
+            Err(ContextError::UnsetRegister(ref reg)) if reg == "rbx" => {}
+            r => panic!("Expected an unset register, got {:?}", r),
+        }
+
+        let mut ctx = RuneContext::builder().uninit(UninitPolicy::Zero).build().unwrap();
+        let ebx = ctx.reg_read("ebx");
+        let zero = ctx.define_const(0, 32);
+        let eq = ctx.eval(core::OpCodes::Cmp, vec![ebx, zero]);
+        ctx.eval(core::OpCodes::Not, vec![eq]);
+        assert!(!ctx.check_sat(&mut solver()));
+
+        let mut ctx = RuneContext::builder()
+                          .uninit(UninitPolicy::Symbolic)
+                          .const_mem(0x1001, 0x41, 8)
+                          .build()
+                          .unwrap();
+        let ebx = ctx.reg_read("ebx");
+        assert!(ctx.syms.contains_key("rbx"));
+        assert_eq!(ctx.reg_read("ebx"), ebx);
+
+        let addr = ctx.define_const(0x1000, 64);
+        let word = ctx.mem_read(addr, 16);
+        assert!(ctx.syms.contains_key("mem_0x1000"));
+        assert!(!ctx.syms.contains_key("mem_0x1001"));
+
+        // The lazily created byte is the low byte of the word that was read.
+        let byte = ctx.syms["mem_0x1000"];
+        let hi = ctx.define_const(0x41, 8);
+        let word_ = ctx.eval(bitvec::OpCodes::Concat, vec![hi, byte]);
+        let eq = ctx.eval(core::OpCodes::Cmp, vec![word, word_]);
+        ctx.eval(core::OpCodes::Not, vec![eq]);
+        assert!(!ctx.check_sat(&mut solver()));
+    }
+
+    /// Returns true if `val` can only be `expected` in `ctx`. `ctx` is left unchanged.
+    pub fn value_is(ctx: &RuneContext, val: NodeIndex, width: usize, expected: u64) -> bool {
+        let mut ctx = ctx.clone();
+        let expected_ = ctx.define_const(expected, width);
+        let eq = ctx.eval(core::OpCodes::Cmp, vec![val, expected_]);
+        ctx.eval(core::OpCodes::Not, vec![eq]);
+        !ctx.check_sat(&mut solver())
+    }
+
+    #[test]
+    fn ctx_lazy_image() {
+        let mut image = MemImage::new();
+        image.add_segment(0x1ffe, vec![0x11, 0x22, 0x33, 0x44]);
+        let mut ctx = RuneContext::builder().image(image).build().unwrap();
+
+        // Only the page that is read is loaded. Memory past the image is zero.
+        let addr = ctx.define_const(0x2000, 64);
+        let dword = ctx.mem_read(addr, 32);
+        assert!(!ctx.memory().is_loaded(1));
+        assert!(ctx.memory().is_loaded(2));
+        assert!(value_is(&ctx, dword, 32, 0x4433));
+
+        // Writes take precedence over the image.
+        let addr = ctx.define_const(0x1fff, 64);
+        let byte = ctx.define_const(0xff, 8);
+        ctx.mem_write(addr, byte, 8);
+        let addr = ctx.define_const(0x1ffe, 64);
+        let word = ctx.mem_read(addr, 16);
+        assert!(ctx.memory().is_loaded(1));
+        assert!(value_is(&ctx, word, 16, 0xff11));
+    }
 }
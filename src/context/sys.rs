@@ -0,0 +1,29 @@
+//! State of the operating system that system calls keep across a path.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// State of the operating system on a path.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SysState {
+    /// Current program break.
+    pub brk: u64,
+    /// Address of the next mapping made by `mmap`.
+    pub mmap_base: u64,
+    pub fds: BTreeSet<u64>,
+    /// Number of bytes read from each file descriptor.
+    pub offsets: BTreeMap<u64, u64>,
+    /// Number of bytes returned by `getrandom`.
+    pub random: u64,
+}
+
+impl Default for SysState {
+    fn default() -> SysState {
+        SysState {
+            brk: 0x0900_0000,
+            mmap_base: 0x4000_0000,
+            fds: (0..3).collect(),
+            offsets: BTreeMap::new(),
+            random: 0,
+        }
+    }
+}
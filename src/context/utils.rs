@@ -1,12 +1,5 @@
 //! Utilities and other miscellaneous functions for `RuneContext`
 
-use context::rune_ctx::{RuneContext, RuneMemory, RuneRegFile};
-use context::context::{ContextAPI};
-use libsmt::backends::smtlib2::SMTLib2;
-use libsmt::logics::qf_abv;
-
-use std::collections::HashMap;
-
 #[derive(Debug, Clone)]
 pub enum Key {
     Mem(usize),
@@ -16,54 +9,16 @@ pub enum Key {
 /// Hex/Decimal to Memory address, any other string maps to Registers
 ///
 /// Useful when input strings is to be interpretted either as a Memory Address or a register name.
-pub fn to_key<T: AsRef<str>>(s: T) -> Key {
+/// Returns `None` for empty strings and invalid numbers.
+pub fn to_key<T: AsRef<str>>(s: T) -> Option<Key> {
     let v = s.as_ref();
     if v.len() > 2 && &v[0..2] == "0x" {
-        Key::Mem(usize::from_str_radix(&v[2..], 16).expect("Invalid number!"))
-    } else if v.chars().nth(0).unwrap().is_digit(10) {
-        Key::Mem(usize::from_str_radix(&v, 10).expect("Invalid number!"))
+        usize::from_str_radix(&v[2..], 16).ok().map(Key::Mem)
+    } else if v.chars().nth(0).map(|c| c.is_digit(10)).unwrap_or(false) {
+        usize::from_str_radix(&v, 10).ok().map(Key::Mem)
+    } else if v.is_empty() {
+        None
     } else {
-        Key::Reg(v.to_owned())
-    }
-}
-
-pub fn new_ctx(ip: Option<u64>,
-               syms: Option<Vec<String>>,
-               consts: Option<HashMap<String, u64>>)
-               -> RuneContext {
-    let rregfile = {
-        use r2pipe::r2::R2;
-        let mut r2 = R2::new(Some("malloc://64".to_owned())).expect("Unable to spawn r2!");
-        r2.send("e asm.bits = 64");
-        r2.send("e asm.arch = x86");
-        r2.flush();
-        let mut lreginfo = r2.reg_info().expect("Unable to retrieve register information!");
-        r2.close();
-        RuneRegFile::new(&mut lreginfo)
-    };
-
-    let mut rmem = RuneMemory::new();
-    let mut smt = SMTLib2::new(Some(qf_abv::QF_ABV));
-    rmem.init_memory(&mut smt);
-    let mut ctx = RuneContext::new(ip, rmem, rregfile, smt);
-
-    if let Some(ref sym_vars) = syms {
-        for var in sym_vars {
-            let  _ = match to_key(var) {
-                Key::Mem(addr) => ctx.set_mem_as_sym(addr, 64),
-                Key::Reg(ref reg) => ctx.set_reg_as_sym(reg),
-            };
-        }
+        Some(Key::Reg(v.to_owned()))
     }
-
-    if let Some(ref const_var) = consts {
-        for (k, v) in const_var {
-            let _ = match to_key(k) {
-                Key::Mem(addr) => ctx.set_mem_as_const(addr, *v, 64),
-                Key::Reg(ref reg) => ctx.set_reg_as_const(reg, *v),
-            };
-        }
-    }
-
-    ctx
 }
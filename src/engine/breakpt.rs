@@ -0,0 +1,333 @@
+//! Breakpoint management for rune.
+//!
+//! Breakpoints are owned by `Rune` and checked before every instruction is executed. When a
+//! breakpoint fires, `Engine::run` returns `RuneControl::Break` to the caller, which can then
+//! inspect or modify the context before resuming execution with another call to `run`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use libsmt::backends::z3;
+
+use context::context::{Context, RegisterRead};
+
+pub type BreakpointId = usize;
+
+/// Condition attached to a breakpoint.
+///
+/// The closure builds a boolean term in the context it is handed. The term is built on a copy
+/// of the current context so that it does not end up as a constraint on the path being explored.
+pub type Condition<Ctx> = Box<Fn(&mut Ctx) -> <Ctx as RegisterRead>::VarRef>;
+
+pub struct Breakpoint<Ctx: Context> {
+    pub addr: u64,
+    pub enabled: bool,
+    /// Remove the breakpoint after it fires for the first time.
+    pub one_shot: bool,
+    /// Number of times the breakpoint has fired.
+    pub hits: u64,
+    /// Number of hits to ignore before the breakpoint fires.
+    pub ignore_count: u64,
+    condition: Option<Condition<Ctx>>,
+}
+
+impl<Ctx: Context> fmt::Debug for Breakpoint<Ctx> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Breakpoint")
+         .field("addr", &self.addr)
+         .field("enabled", &self.enabled)
+         .field("one_shot", &self.one_shot)
+         .field("hits", &self.hits)
+         .field("ignore_count", &self.ignore_count)
+         .field("conditional", &self.condition.is_some())
+         .finish()
+    }
+}
+
+impl<Ctx: Context> Breakpoint<Ctx> {
+    fn new(addr: u64, one_shot: bool, condition: Option<Condition<Ctx>>) -> Breakpoint<Ctx> {
+        Breakpoint {
+            addr: addr,
+            enabled: true,
+            one_shot: one_shot,
+            hits: 0,
+            ignore_count: 0,
+            condition: condition,
+        }
+    }
+
+    pub fn is_conditional(&self) -> bool {
+        self.condition.is_some()
+    }
+
+    // Returns true if the condition (if any) can be satisfied in `ctx`.
+    fn condition_holds(&self, ctx: &Ctx) -> bool {
+        if let Some(ref cond) = self.condition {
+            let mut ctx_ = ctx.clone();
+            // The condition is a root in the solver graph and hence is asserted.
+            cond(&mut ctx_);
+            let mut z3: z3::Z3 = Default::default();
+            ctx_.check_sat(&mut z3)
+        } else {
+            true
+        }
+    }
+}
+
+/// Collection of breakpoints, indexed by the id handed out when they are added.
+pub struct BreakpointManager<Ctx: Context> {
+    bps: BTreeMap<BreakpointId, Breakpoint<Ctx>>,
+    next_id: BreakpointId,
+}
+
+impl<Ctx: Context> Default for BreakpointManager<Ctx> {
+    fn default() -> BreakpointManager<Ctx> {
+        BreakpointManager::new()
+    }
+}
+
+impl<Ctx: Context> BreakpointManager<Ctx> {
+    pub fn new() -> BreakpointManager<Ctx> {
+        BreakpointManager {
+            bps: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    fn insert(&mut self, bp: Breakpoint<Ctx>) -> BreakpointId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.bps.insert(id, bp);
+        id
+    }
+
+    /// Break every time execution reaches `addr`.
+    pub fn add(&mut self, addr: u64) -> BreakpointId {
+        self.insert(Breakpoint::new(addr, false, None))
+    }
+
+    /// Break at `addr` only if the condition built by `cond` is satisfiable in the current
+    /// context.
+    pub fn add_conditional<F>(&mut self, addr: u64, cond: F) -> BreakpointId
+        where F: Fn(&mut Ctx) -> <Ctx as RegisterRead>::VarRef + 'static
+    {
+        self.insert(Breakpoint::new(addr, false, Some(Box::new(cond))))
+    }
+
+    /// Break the first time execution reaches `addr` and remove the breakpoint.
+    pub fn add_one_shot(&mut self, addr: u64) -> BreakpointId {
+        self.insert(Breakpoint::new(addr, true, None))
+    }
+
+    pub fn remove(&mut self, id: BreakpointId) -> Option<Breakpoint<Ctx>> {
+        self.bps.remove(&id)
+    }
+
+    pub fn enable(&mut self, id: BreakpointId) -> bool {
+        self.set_enabled(id, true)
+    }
+
+    pub fn disable(&mut self, id: BreakpointId) -> bool {
+        self.set_enabled(id, false)
+    }
+
+    fn set_enabled(&mut self, id: BreakpointId, enabled: bool) -> bool {
+        if let Some(bp) = self.bps.get_mut(&id) {
+            bp.enabled = enabled;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Ignore the next `count` hits of the breakpoint.
+    pub fn set_ignore_count(&mut self, id: BreakpointId, count: u64) -> bool {
+        if let Some(bp) = self.bps.get_mut(&id) {
+            bp.ignore_count = count;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn get(&self, id: BreakpointId) -> Option<&Breakpoint<Ctx>> {
+        self.bps.get(&id)
+    }
+
+    pub fn iter(&self) -> ::std::collections::btree_map::Iter<BreakpointId, Breakpoint<Ctx>> {
+        self.bps.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bps.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.bps.clear();
+    }
+
+    /// Check if any breakpoint fires at the current instruction pointer of `ctx`.
+    ///
+    /// Updates hit counts of all enabled breakpoints at this address whose condition holds and
+    /// returns the id of the first one that fires. One-shot breakpoints are removed once they
+    /// fire.
+    pub fn check(&mut self, ctx: &Ctx) -> Option<BreakpointId> {
+        let ip = ctx.ip();
+        let mut fired = Vec::new();
+        for (id, bp) in &mut self.bps {
+            if !bp.enabled || bp.addr != ip || !bp.condition_holds(ctx) {
+                continue;
+            }
+            if bp.ignore_count > 0 {
+                bp.ignore_count -= 1;
+                continue;
+            }
+            bp.hits += 1;
+            fired.push(*id);
+        }
+
+        for id in &fired {
+            if self.bps[id].one_shot {
+                self.bps.remove(id);
+            }
+        }
+        fired.first().cloned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use libsmt::theories::core;
+    use petgraph::graph::NodeIndex;
+    use r2pipe::structs::LOpInfo;
+
+    use context::context::Evaluate;
+    use context::rune_ctx::RuneContext;
+    use engine::engine::Engine;
+    use engine::rune::{Rune, RuneControl};
+    use explorer::dfs::DFSExplorer;
+    use explorer::explorer::PathExplorer;
+    use stream::{FileStream, InstructionStream};
+
+    const ESIL_STEP: &'static str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                            "/tests/fixtures/esil_step.json");
+
+    fn ctx_at(ip: u64) -> RuneContext {
+        RuneContext::builder().ip(ip).const_reg("rax", 2).sym_reg("rbx").build().unwrap()
+    }
+
+    // The condition that `reg` holds `value`.
+    fn reg_eq(ctx: &mut RuneContext, reg: &str, value: u64) -> NodeIndex {
+        let val = ctx.reg_read(reg);
+        let value = ctx.define_const(value, 64);
+        ctx.eval(core::OpCodes::Cmp, vec![val, value])
+    }
+
+    // True if `reg` can only hold `value` in `ctx`.
+    fn reg_is(ctx: &RuneContext, reg: &str, value: u64) -> bool {
+        let mut ctx = ctx.clone();
+        let eq = reg_eq(&mut ctx, reg, value);
+        ctx.eval(core::OpCodes::Not, vec![eq]);
+        let mut z3: z3::Z3 = Default::default();
+        !ctx.check_sat(&mut z3)
+    }
+
+    #[test]
+    fn breakpoint_add_remove() {
+        let mut bps = BreakpointManager::new();
+        let a = bps.add(0x1000);
+        let b = bps.add(0x1003);
+        assert!(a != b);
+        assert_eq!(bps.check(&ctx_at(0x1000)), Some(a));
+        assert_eq!(bps.check(&ctx_at(0x1003)), Some(b));
+        assert_eq!(bps.check(&ctx_at(0x1006)), None);
+        assert_eq!(bps.check(&ctx_at(0x1000)), Some(a));
+        assert_eq!(bps.get(a).map(|bp| bp.hits), Some(2));
+
+        assert!(bps.remove(a).is_some());
+        assert!(bps.remove(a).is_none());
+        assert_eq!(bps.check(&ctx_at(0x1000)), None);
+        bps.clear();
+        assert!(bps.is_empty());
+    }
+
+    #[test]
+    fn breakpoint_conditions() {
+        let mut bps = BreakpointManager::new();
+        let never = bps.add_conditional(0x1000, |ctx: &mut RuneContext| reg_eq(ctx, "rax", 3));
+        assert!(bps.get(never).unwrap().is_conditional());
+        assert_eq!(bps.check(&ctx_at(0x1000)), None);
+        assert_eq!(bps.get(never).map(|bp| bp.hits), Some(0));
+
+        let always = bps.add_conditional(0x1000, |ctx: &mut RuneContext| reg_eq(ctx, "rax", 2));
+        assert_eq!(bps.check(&ctx_at(0x1000)), Some(always));
+
+        // A symbolic register satisfies the condition if any of its values does. The condition
+        // does not constrain the context it is checked in.
+        bps.clear();
+        let sym = bps.add_conditional(0x1000, |ctx: &mut RuneContext| reg_eq(ctx, "rbx", 7));
+        let mut ctx = ctx_at(0x1000);
+        assert_eq!(bps.check(&ctx), Some(sym));
+        reg_eq(&mut ctx, "rbx", 8);
+        let mut z3: z3::Z3 = Default::default();
+        assert!(ctx.check_sat(&mut z3));
+        assert_eq!(bps.check(&ctx), None);
+    }
+
+    #[test]
+    fn breakpoint_one_shot() {
+        let mut bps = BreakpointManager::new();
+        let id = bps.add_one_shot(0x1000);
+        assert!(!bps.get(id).unwrap().is_conditional());
+        assert_eq!(bps.check(&ctx_at(0x1000)), Some(id));
+        assert!(bps.get(id).is_none());
+        assert_eq!(bps.check(&ctx_at(0x1000)), None);
+    }
+
+    #[test]
+    fn breakpoint_enable_disable() {
+        let mut bps = BreakpointManager::new();
+        let id = bps.add(0x1000);
+        assert!(bps.disable(id));
+        assert_eq!(bps.check(&ctx_at(0x1000)), None);
+        assert!(bps.enable(id));
+        assert_eq!(bps.check(&ctx_at(0x1000)), Some(id));
+        assert!(!bps.disable(id + 1));
+        assert!(!bps.enable(id + 1));
+    }
+
+    #[test]
+    fn breakpoint_ignore_count() {
+        let mut bps = BreakpointManager::new();
+        let id = bps.add(0x1000);
+        assert!(bps.set_ignore_count(id, 2));
+        assert_eq!(bps.check(&ctx_at(0x1000)), None);
+        assert_eq!(bps.check(&ctx_at(0x1000)), None);
+        assert_eq!(bps.check(&ctx_at(0x1000)), Some(id));
+        assert_eq!(bps.get(id).map(|bp| (bp.hits, bp.ignore_count)), Some((1, 0)));
+        assert!(!bps.set_ignore_count(id + 1, 1));
+    }
+
+    #[test]
+    fn breakpoint_resume() {
+        let ctx = RuneContext::builder().ip(0x1000).const_reg("rax", 0).build().unwrap();
+        let mut stream: FileStream<u64, LOpInfo> = FileStream::new();
+        stream.load(ESIL_STEP);
+        let mut rune = Rune::new(ctx, DFSExplorer::new(), stream);
+        let id = rune.breakpoints_mut().add(0x1006);
+
+        // Execution stops before the instruction at the breakpoint.
+        assert_eq!(rune.run(), Ok(RuneControl::Break));
+        let ctx = rune.with_explorer(|_, ctx| ctx.clone());
+        assert_eq!(ctx.ip(), 0x1006);
+        assert!(reg_is(&ctx, "rax", 2));
+
+        // Resuming executes the instruction at the breakpoint without breaking again.
+        assert_eq!(rune.run(), Ok(RuneControl::Halt));
+        let ctx = rune.with_explorer(|_, ctx| ctx.clone());
+        assert!(reg_is(&ctx, "rax", 4));
+        assert_eq!(rune.breakpoints().get(id).map(|bp| bp.hits), Some(1));
+    }
+}
@@ -1,15 +1,103 @@
 //! Defines traits and structs that perform the actual symbolic emulation.
 
-#[derive(Clone, Copy, Debug)]
+use std::fmt;
+
+use context::context::ContextError;
+use engine::rune::RuneControl;
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum EngineError {
+    /// The esil operator is not supported.
     Undefined,
     InCorrectOperand,
+    /// An operator is missing one of its operands.
+    MissingOperand,
+    /// The instruction at the address has no esil or no size.
+    InvalidInstruction(u64),
+    Context(ContextError),
+    /// The system call with the number is not known to the syscall handler.
+    UnknownSyscall(u64),
+    /// Error while executing the esil of the instruction at `addr`. `position` is the index of
+    /// `token` among the tokens executed for the instruction.
+    Esil {
+        addr: u64,
+        esil: String,
+        position: usize,
+        token: String,
+        cause: Box<EngineError>,
+    },
+}
+
+impl From<ContextError> for EngineError {
+    fn from(e: ContextError) -> EngineError {
+        EngineError::Context(e)
+    }
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EngineError::Undefined => write!(f, "Unsupported esil operator"),
+            EngineError::InCorrectOperand => write!(f, "Incorrect operand"),
+            EngineError::MissingOperand => write!(f, "Missing operand"),
+            EngineError::InvalidInstruction(addr) => {
+                write!(f, "No esil for the instruction at {:#x}", addr)
+            }
+            EngineError::Context(ref e) => write!(f, "{}", e),
+            EngineError::UnknownSyscall(number) => write!(f, "Unknown system call {}", number),
+            EngineError::Esil { addr, ref esil, position, ref token, ref cause } => {
+                write!(f,
+                       "{} at {:#x}, token {} ({}) of `{}`",
+                       cause,
+                       addr,
+                       position,
+                       token,
+                       esil)
+            }
+        }
+    }
 }
 
 pub type EngineResult<T> = Result<T, EngineError>;
 
 pub trait Engine: Sized {
-    fn run(&mut self) -> EngineResult<()>;
+    /// Execute a single instruction. Returns `RuneControl::Continue` if the current path
+    /// continues, `RuneControl::TerminatePath` if it ended and the next path has been loaded,
+    /// `RuneControl::Halt` if there are no paths left and `RuneControl::Break` if execution was
+    /// suspended before the instruction, for example by a breakpoint.
+    fn step(&mut self) -> EngineResult<RuneControl>;
+
+    /// Run until the instruction at `addr` is about to be executed, which returns
+    /// `RuneControl::Break`. Stops early like `run`.
+    ///
+    /// At least one instruction is executed first, so that calling `run_until` at `addr` runs on
+    /// until `addr` is reached again. The address is checked on whichever path is current after
+    /// each instruction: when a path ends, a path loaded next that starts at `addr` stops
+    /// execution as well.
+    fn run_until(&mut self, addr: u64) -> EngineResult<RuneControl>;
+
+    /// Run until all paths are explored (`RuneControl::Halt`) or until execution is suspended,
+    /// for example by a breakpoint (`RuneControl::Break`). Calling `run` again resumes execution.
+    fn run(&mut self) -> EngineResult<RuneControl> {
+        loop {
+            match try!(self.step()) {
+                RuneControl::Continue | RuneControl::TerminatePath => {}
+                c => return Ok(c),
+            }
+        }
+    }
+
+    /// Execute at most `n` instructions. Returns `RuneControl::Continue` if all of them were
+    /// executed, and stops early like `run`.
+    fn run_for(&mut self, n: usize) -> EngineResult<RuneControl> {
+        for _ in 0..n {
+            match try!(self.step()) {
+                RuneControl::Continue | RuneControl::TerminatePath => {}
+                c => return Ok(c),
+            }
+        }
+        Ok(RuneControl::Continue)
+    }
 }
 
 pub trait Configure {
@@ -0,0 +1,98 @@
+//! Signed esil operators.
+//!
+//! radare2 writes arithmetic shifts right as `>>>>`, and the signed variants of division and
+//! modulo with a `~` prefix (`~/`, `~%`). Signed comparisons follow the same convention (`~<`,
+//! `~>`). The esil lexer only knows the unsigned operators, so signed operators are rewritten into
+//! their unsigned counterparts before the expression is handed to the parser. Sign extension
+//! (`bits,value,~`) has no counterpart and is rewritten into a `<<` marked as a sign extension.
+//! The `Signedness` returned along with the rewritten expression then tells, for every operator
+//! token the parser produces, which variant of the operator it stands for. Tokens are matched
+//! with the words of the expression by the order in which they occur.
+
+use esil::lexer::Token;
+
+// Operators that have a signed variant, by the token the lexer produces for them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Shl,
+    Shr,
+    Div,
+    Mod,
+    Lt,
+    Gt,
+}
+
+/// The operator an operator token of a rewritten expression stands for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    Unsigned,
+    Signed,
+    /// `~`, which reaches the parser as `<<`.
+    SignExtend,
+}
+
+// (word, word it is rewritten to, kind of the operator, variant of the operator)
+const WORDS: &'static [(&'static str, &'static str, Kind, Variant)] = &[
+    ("~", "<<", Kind::Shl, Variant::SignExtend),
+    ("<<", "<<", Kind::Shl, Variant::Unsigned),
+    ("<<=", "<<=", Kind::Shl, Variant::Unsigned),
+    (">>>>", ">>", Kind::Shr, Variant::Signed),
+    (">>>>=", ">>=", Kind::Shr, Variant::Signed),
+    (">>", ">>", Kind::Shr, Variant::Unsigned),
+    (">>=", ">>=", Kind::Shr, Variant::Unsigned),
+    ("~/", "/", Kind::Div, Variant::Signed),
+    ("/", "/", Kind::Div, Variant::Unsigned),
+    ("/=", "/=", Kind::Div, Variant::Unsigned),
+    ("~%", "%", Kind::Mod, Variant::Signed),
+    ("%", "%", Kind::Mod, Variant::Unsigned),
+    ("%=", "%=", Kind::Mod, Variant::Unsigned),
+    ("~<", "<", Kind::Lt, Variant::Signed),
+    ("<", "<", Kind::Lt, Variant::Unsigned),
+    ("~>", ">", Kind::Gt, Variant::Signed),
+    (">", ">", Kind::Gt, Variant::Unsigned),
+];
+
+/// Variants of the operators of a rewritten expression, in the order they occur.
+#[derive(Clone, Debug, Default)]
+pub struct Signedness {
+    ops: Vec<(Kind, Variant)>,
+}
+
+impl Signedness {
+    /// The variant of the operator `token`, the next token of its kind produced by the parser,
+    /// stands for.
+    pub fn next(&mut self, token: &Token) -> Variant {
+        let kind = match *token {
+            Token::ELsl => Kind::Shl,
+            Token::ELsr => Kind::Shr,
+            Token::EDiv => Kind::Div,
+            Token::EMod => Kind::Mod,
+            Token::ELt => Kind::Lt,
+            Token::EGt => Kind::Gt,
+            _ => return Variant::Unsigned,
+        };
+        match self.ops.iter().position(|&(k, _)| k == kind) {
+            Some(i) => self.ops.remove(i).1,
+            None => Variant::Unsigned,
+        }
+    }
+}
+
+/// Rewrite the signed operators of an esil expression into unsigned ones. The number of words in
+/// the expression is unchanged.
+pub fn rewrite(esil: &str) -> (String, Signedness) {
+    let mut ops = Vec::new();
+    let words = esil.split(',')
+                    .map(|word| {
+                        match WORDS.iter().find(|w| w.0 == word.trim()) {
+                            Some(&(_, unsigned, kind, variant)) => {
+                                ops.push((kind, variant));
+                                unsigned.to_owned()
+                            }
+                            None => word.to_owned(),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+    (words.join(","), Signedness { ops: ops })
+}
+
@@ -0,0 +1,203 @@
+//! Esil internal variables (`$z`, `$c`, `$b`, `$p`, `$o`, `$s`, `$ds`, `$jt`, `$js`).
+//!
+//! radare2 computes flags through internal variables that are derived from the last operation:
+//! the value of the destination before (`old`) and after (`cur`) the operation and its width in
+//! bits (`lastsz`). These are tracked by the `Context` and turned into bit-precise SMT
+//! expressions here.
+//!
+//! Internal variables are rewritten into plain identifiers before the expression is handed to
+//! the esil parser, so that they reach rune as operands and are evaluated by `evaluate`.
+
+use libsmt::logics::qf_abv;
+use libsmt::theories::{bitvec, core};
+
+use context::context::{Context, ContextResult, RegisterRead};
+
+/// Prefix of the identifiers that internal variables are rewritten to.
+const PREFIX: &'static str = "__rune_";
+
+/// The kind of the last arithmetic operation, needed to compute the overflow flag.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arith {
+    Add,
+    Sub,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InternalVar {
+    /// `$z`: Result of the last operation is zero.
+    Zero,
+    /// `$c<n>`: Carry out of bit `n`.
+    Carry(u64),
+    /// `$b<n>`: Borrow from bit `n`.
+    Borrow(u64),
+    /// `$p`: Even number of bits set in the least significant byte of the result.
+    Parity,
+    /// `$o`: Signed overflow.
+    Overflow,
+    /// `$s`: Sign bit of the result.
+    Sign,
+    /// `$ds`: Delay slot. Delay slots are not modelled, hence always zero.
+    DelaySlot,
+    /// `$jt`: Jump target of a delayed jump. Always zero.
+    JumpTarget,
+    /// `$js`: Jump target is set. Always zero.
+    JumpSet,
+}
+
+impl InternalVar {
+    /// Parse an esil word such as `$c63`.
+    pub fn parse(word: &str) -> Option<InternalVar> {
+        if !word.starts_with('$') || word.len() < 2 {
+            return None;
+        }
+        let (name, arg) = word[1..].split_at(1);
+        let index = || arg.parse::<u64>().ok();
+        match (name, arg) {
+            ("z", "") => Some(InternalVar::Zero),
+            ("p", "") => Some(InternalVar::Parity),
+            ("o", "") => Some(InternalVar::Overflow),
+            ("s", "") => Some(InternalVar::Sign),
+            ("d", "s") => Some(InternalVar::DelaySlot),
+            ("j", "t") => Some(InternalVar::JumpTarget),
+            ("j", "s") => Some(InternalVar::JumpSet),
+            ("c", _) => index().map(InternalVar::Carry),
+            ("b", _) => index().map(InternalVar::Borrow),
+            _ => None,
+        }
+    }
+
+    /// Recover the internal variable from an identifier produced by `rewrite`.
+    pub fn from_identifier(name: &str) -> Option<InternalVar> {
+        if name.starts_with(PREFIX) {
+            InternalVar::parse(&format!("${}", &name[PREFIX.len()..]))
+        } else {
+            None
+        }
+    }
+}
+
+/// Rewrite internal variables of an esil expression into identifiers. The number of words in
+/// the expression is unchanged.
+pub fn rewrite(esil: &str) -> String {
+    esil.split(',')
+        .map(|word| {
+            if InternalVar::parse(word).is_some() {
+                format!("{}{}", PREFIX, &word[1..])
+            } else {
+                word.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+type VarRef<Ctx> = <Ctx as RegisterRead>::VarRef;
+
+// Keep only the lower `bits` bits of `v`.
+fn masked<Ctx>(ctx: &mut Ctx, v: VarRef<Ctx>, bits: u64) -> VarRef<Ctx>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    if bits >= 64 {
+        v
+    } else {
+        let mask = ctx.define_const((1 << bits) - 1, 64);
+        ctx.eval(bitvec::OpCodes::BvAnd, vec![v, mask])
+    }
+}
+
+// Bit `n` of `v`, as a 64 bit value.
+fn bit<Ctx>(ctx: &mut Ctx, v: VarRef<Ctx>, n: u64) -> VarRef<Ctx>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let n_ = ctx.define_const(n, 64);
+    let one = ctx.define_const(1, 64);
+    let shifted = ctx.eval(bitvec::OpCodes::BvLShr, vec![v, n_]);
+    ctx.eval(bitvec::OpCodes::BvAnd, vec![shifted, one])
+}
+
+// Turn a boolean into a 64 bit 0 / 1.
+fn to_bv<Ctx>(ctx: &mut Ctx, cond: VarRef<Ctx>) -> VarRef<Ctx>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let zero = ctx.define_const(0, 64);
+    let one = ctx.define_const(1, 64);
+    ctx.eval(core::OpCodes::ITE, vec![cond, one, zero])
+}
+
+/// Evaluate the internal variable `var` in `ctx`. `last` is the kind of the last arithmetic
+/// operation, if known.
+pub fn evaluate<Ctx>(ctx: &mut Ctx,
+                     var: InternalVar,
+                     last: Option<Arith>)
+                     -> ContextResult<VarRef<Ctx>>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let lastsz = ctx.e_lastsz() as u64;
+    Ok(match var {
+        InternalVar::Zero => {
+            let cur = try!(ctx.try_e_cur());
+            let cur = masked(ctx, cur, lastsz);
+            let zero = ctx.define_const(0, 64);
+            let is_zero = ctx.eval(core::OpCodes::Cmp, vec![cur, zero]);
+            to_bv(ctx, is_zero)
+        }
+        InternalVar::Carry(n) => {
+            // old + x = cur carries out of bit n iff the low n + 1 bits of cur are smaller than
+            // those of old.
+            let (old, cur) = (try!(ctx.try_e_old()), try!(ctx.try_e_cur()));
+            let old = masked(ctx, old, n + 1);
+            let cur = masked(ctx, cur, n + 1);
+            let lt = ctx.eval(bitvec::OpCodes::BvULt, vec![cur, old]);
+            to_bv(ctx, lt)
+        }
+        InternalVar::Borrow(n) => {
+            // old - x = cur borrows from bit n iff the low n bits of old are smaller than those
+            // of cur.
+            let (old, cur) = (try!(ctx.try_e_old()), try!(ctx.try_e_cur()));
+            let old = masked(ctx, old, n);
+            let cur = masked(ctx, cur, n);
+            let lt = ctx.eval(bitvec::OpCodes::BvULt, vec![old, cur]);
+            to_bv(ctx, lt)
+        }
+        InternalVar::Parity => {
+            // Fold the low byte onto bit 0. Parity is set if the number of set bits is even.
+            let cur = try!(ctx.try_e_cur());
+            let mut x = masked(ctx, cur, 8);
+            for shift in &[4, 2, 1] {
+                let shift_ = ctx.define_const(*shift, 64);
+                let shifted = ctx.eval(bitvec::OpCodes::BvLShr, vec![x.clone(), shift_]);
+                x = ctx.eval(bitvec::OpCodes::BvXor, vec![x, shifted]);
+            }
+            let low = bit(ctx, x, 0);
+            let zero = ctx.define_const(0, 64);
+            let even = ctx.eval(core::OpCodes::Cmp, vec![low, zero]);
+            to_bv(ctx, even)
+        }
+        InternalVar::Overflow => {
+            // For cur = old + x, overflow occurs if the sign of cur differs from the signs of
+            // both operands. For cur = old - x, if the operands differ in sign and the sign of
+            // cur differs from that of old.
+            let (old, cur) = (try!(ctx.try_e_old()), try!(ctx.try_e_cur()));
+            let t = if last == Some(Arith::Sub) {
+                let x = ctx.eval(bitvec::OpCodes::BvSub, vec![old.clone(), cur.clone()]);
+                let a = ctx.eval(bitvec::OpCodes::BvXor, vec![old.clone(), x]);
+                let b = ctx.eval(bitvec::OpCodes::BvXor, vec![old, cur]);
+                ctx.eval(bitvec::OpCodes::BvAnd, vec![a, b])
+            } else {
+                let x = ctx.eval(bitvec::OpCodes::BvSub, vec![cur.clone(), old.clone()]);
+                let a = ctx.eval(bitvec::OpCodes::BvXor, vec![old, cur.clone()]);
+                let b = ctx.eval(bitvec::OpCodes::BvXor, vec![x, cur]);
+                ctx.eval(bitvec::OpCodes::BvAnd, vec![a, b])
+            };
+            bit(ctx, t, lastsz - 1)
+        }
+        InternalVar::Sign => {
+            let cur = try!(ctx.try_e_cur());
+            bit(ctx, cur, lastsz - 1)
+        }
+        InternalVar::DelaySlot | InternalVar::JumpTarget | InternalVar::JumpSet => {
+            ctx.define_const(0, 64)
+        }
+    })
+}
@@ -0,0 +1,391 @@
+//! Hooks allow user supplied callbacks to observe and modify the context during execution.
+//!
+//! Hooks are registered on `Rune` and can be attached to a particular instruction address (before
+//! or after it is executed), to every instruction, or to memory and register accesses. Every hook
+//! returns a `RuneControl` which directs rune on how to proceed:
+//!  - `Continue`: Carry on as usual.
+//!  - `Skip`: Do not execute (the rest of) the current instruction. If the hook did not change
+//!    the instruction pointer, execution continues at the next instruction.
+//!  - `TerminatePath`: Stop exploring the current path and ask the explorer for the next one.
+//!  - `Halt`: Stop execution and return to the caller of `Engine::run`.
+//!
+//! Memory and register hooks fire before the access is performed, so that a hook can set up the
+//! value that is about to be read (for example, to model a peripheral).
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use context::context::{Context, RegisterRead};
+use engine::rune::RuneControl;
+
+pub type HookId = usize;
+
+type VarRef<Ctx> = <Ctx as RegisterRead>::VarRef;
+
+/// Callback invoked on instructions. Receives the context at the time of the hook.
+pub type InstructionHook<Ctx> = Box<FnMut(&mut Ctx) -> RuneControl>;
+/// Callback invoked on memory reads. Receives the address and size (in bits) of the read.
+pub type MemReadHook<Ctx> = Box<FnMut(&mut Ctx, VarRef<Ctx>, u64) -> RuneControl>;
+/// Callback invoked on memory writes. Receives the address, data and size (in bits) of the write.
+pub type MemWriteHook<Ctx> = Box<FnMut(&mut Ctx, VarRef<Ctx>, VarRef<Ctx>, u64) -> RuneControl>;
+/// Callback invoked on register writes. Receives the register name and the value being written.
+pub type RegWriteHook<Ctx> = Box<FnMut(&mut Ctx, &str, VarRef<Ctx>) -> RuneControl>;
+
+pub enum Hook<Ctx: Context> {
+    /// Before the instruction at the address is executed.
+    Before(u64, InstructionHook<Ctx>),
+    /// After the instruction at the address has been executed.
+    After(u64, InstructionHook<Ctx>),
+    /// Before every instruction.
+    Instruction(InstructionHook<Ctx>),
+    MemRead(MemReadHook<Ctx>),
+    MemWrite(MemWriteHook<Ctx>),
+    RegWrite(RegWriteHook<Ctx>),
+}
+
+impl<Ctx: Context> fmt::Debug for Hook<Ctx> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Hook::Before(addr, _) => write!(f, "Hook::Before({:#x})", addr),
+            Hook::After(addr, _) => write!(f, "Hook::After({:#x})", addr),
+            Hook::Instruction(_) => write!(f, "Hook::Instruction"),
+            Hook::MemRead(_) => write!(f, "Hook::MemRead"),
+            Hook::MemWrite(_) => write!(f, "Hook::MemWrite"),
+            Hook::RegWrite(_) => write!(f, "Hook::RegWrite"),
+        }
+    }
+}
+
+/// Combine the controls returned by two hooks. The more disruptive of the two wins.
+fn merge(a: RuneControl, b: RuneControl) -> RuneControl {
+    fn rank(c: RuneControl) -> u8 {
+        match c {
+            RuneControl::Halt => 3,
+            RuneControl::TerminatePath => 2,
+            RuneControl::Skip => 1,
+            _ => 0,
+        }
+    }
+    if rank(b) > rank(a) { b } else { a }
+}
+
+/// Collection of hooks, indexed by the id handed out when they are added.
+pub struct HookManager<Ctx: Context> {
+    hooks: BTreeMap<HookId, Hook<Ctx>>,
+    next_id: HookId,
+}
+
+impl<Ctx: Context> Default for HookManager<Ctx> {
+    fn default() -> HookManager<Ctx> {
+        HookManager::new()
+    }
+}
+
+impl<Ctx: Context> HookManager<Ctx> {
+    pub fn new() -> HookManager<Ctx> {
+        HookManager {
+            hooks: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn add(&mut self, hook: Hook<Ctx>) -> HookId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.hooks.insert(id, hook);
+        id
+    }
+
+    pub fn add_before<F>(&mut self, addr: u64, f: F) -> HookId
+        where F: FnMut(&mut Ctx) -> RuneControl + 'static
+    {
+        self.add(Hook::Before(addr, Box::new(f)))
+    }
+
+    pub fn add_after<F>(&mut self, addr: u64, f: F) -> HookId
+        where F: FnMut(&mut Ctx) -> RuneControl + 'static
+    {
+        self.add(Hook::After(addr, Box::new(f)))
+    }
+
+    pub fn add_instruction<F>(&mut self, f: F) -> HookId
+        where F: FnMut(&mut Ctx) -> RuneControl + 'static
+    {
+        self.add(Hook::Instruction(Box::new(f)))
+    }
+
+    pub fn add_mem_read<F>(&mut self, f: F) -> HookId
+        where F: FnMut(&mut Ctx, VarRef<Ctx>, u64) -> RuneControl + 'static
+    {
+        self.add(Hook::MemRead(Box::new(f)))
+    }
+
+    pub fn add_mem_write<F>(&mut self, f: F) -> HookId
+        where F: FnMut(&mut Ctx, VarRef<Ctx>, VarRef<Ctx>, u64) -> RuneControl + 'static
+    {
+        self.add(Hook::MemWrite(Box::new(f)))
+    }
+
+    pub fn add_reg_write<F>(&mut self, f: F) -> HookId
+        where F: FnMut(&mut Ctx, &str, VarRef<Ctx>) -> RuneControl + 'static
+    {
+        self.add(Hook::RegWrite(Box::new(f)))
+    }
+
+    pub fn remove(&mut self, id: HookId) -> Option<Hook<Ctx>> {
+        self.hooks.remove(&id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.hooks.clear();
+    }
+
+    /// Run hooks that fire before the instruction at the current instruction pointer.
+    pub fn before_instruction(&mut self, ctx: &mut Ctx) -> RuneControl {
+        let ip = ctx.ip();
+        let mut control = RuneControl::Continue;
+        for hook in self.hooks.values_mut() {
+            let c = match *hook {
+                Hook::Before(addr, ref mut f) if addr == ip => f(ctx),
+                Hook::Instruction(ref mut f) => f(ctx),
+                _ => continue,
+            };
+            control = merge(control, c);
+        }
+        control
+    }
+
+    /// Run hooks that fire after the instruction at `addr` has been executed.
+    pub fn after_instruction(&mut self, ctx: &mut Ctx, addr: u64) -> RuneControl {
+        let mut control = RuneControl::Continue;
+        for hook in self.hooks.values_mut() {
+            if let Hook::After(addr_, ref mut f) = *hook {
+                if addr_ == addr {
+                    control = merge(control, f(ctx));
+                }
+            }
+        }
+        control
+    }
+
+    pub fn mem_read(&mut self, ctx: &mut Ctx, addr: VarRef<Ctx>, size: u64) -> RuneControl {
+        let mut control = RuneControl::Continue;
+        for hook in self.hooks.values_mut() {
+            if let Hook::MemRead(ref mut f) = *hook {
+                control = merge(control, f(ctx, addr.clone(), size));
+            }
+        }
+        control
+    }
+
+    pub fn mem_write(&mut self,
+                     ctx: &mut Ctx,
+                     addr: VarRef<Ctx>,
+                     data: VarRef<Ctx>,
+                     size: u64)
+                     -> RuneControl {
+        let mut control = RuneControl::Continue;
+        for hook in self.hooks.values_mut() {
+            if let Hook::MemWrite(ref mut f) = *hook {
+                control = merge(control, f(ctx, addr.clone(), data.clone(), size));
+            }
+        }
+        control
+    }
+
+    pub fn reg_write(&mut self, ctx: &mut Ctx, reg: &str, value: VarRef<Ctx>) -> RuneControl {
+        let mut control = RuneControl::Continue;
+        for hook in self.hooks.values_mut() {
+            if let Hook::RegWrite(ref mut f) = *hook {
+                control = merge(control, f(ctx, reg, value.clone()));
+            }
+        }
+        control
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    use libsmt::backends::z3;
+    use libsmt::theories::core;
+    use petgraph::graph::NodeIndex;
+    use r2pipe::structs::LOpInfo;
+
+    use context::context::{Context, Evaluate, MemoryWrite, RegisterRead};
+    use context::rune_ctx::RuneContext;
+    use engine::engine::Engine;
+    use engine::rune::Rune;
+    use explorer::dfs::DFSExplorer;
+    use explorer::explorer::PathExplorer;
+    use stream::{FileStream, InstructionStream};
+
+    const ESIL_HOOKS: &'static str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                             "/tests/fixtures/esil_hooks.json");
+
+    // Run `ESIL_HOOKS` from 0x1000 with the hooks added by `f`. `rax` and `rbx` start at 0.
+    fn run_hooks<F>(f: F) -> (RuneControl, RuneContext)
+        where F: FnOnce(&mut HookManager<RuneContext>)
+    {
+        let ctx = RuneContext::builder()
+                      .ip(0x1000)
+                      .const_reg("rax", 0)
+                      .const_reg("rbx", 0)
+                      .const_mem(0x2000, 7, 64)
+                      .build()
+                      .unwrap();
+        let mut stream: FileStream<u64, LOpInfo> = FileStream::new();
+        stream.load(ESIL_HOOKS);
+        let mut rune = Rune::new(ctx, DFSExplorer::new(), stream);
+        f(rune.hooks_mut());
+        let control = rune.run().unwrap();
+        (control, rune.with_explorer(|_, ctx| ctx.clone()))
+    }
+
+    // True if `val` can only be `value` in `ctx`.
+    fn value_is(ctx: &RuneContext, val: NodeIndex, value: u64) -> bool {
+        let mut ctx = ctx.clone();
+        let value = ctx.define_const(value, 64);
+        let eq = ctx.eval(core::OpCodes::Cmp, vec![val, value]);
+        ctx.eval(core::OpCodes::Not, vec![eq]);
+        let mut z3: z3::Z3 = Default::default();
+        !ctx.check_sat(&mut z3)
+    }
+
+    fn reg_is(ctx: &RuneContext, reg: &str, value: u64) -> bool {
+        let mut ctx = ctx.clone();
+        let val = ctx.reg_read(reg);
+        value_is(&ctx, val, value)
+    }
+
+    #[test]
+    fn hooks_instruction() {
+        let all = Rc::new(RefCell::new(Vec::new()));
+        let before = Rc::new(Cell::new(0));
+        let after = Rc::new(Cell::new(0));
+        let (all_, before_, after_) = (all.clone(), before.clone(), after.clone());
+        let (control, _) = run_hooks(|hooks| {
+            hooks.add_instruction(move |ctx: &mut RuneContext| {
+                all_.borrow_mut().push(ctx.ip());
+                RuneControl::Continue
+            });
+            hooks.add_before(0x1004, move |ctx: &mut RuneContext| {
+                before_.set(ctx.ip());
+                RuneControl::Continue
+            });
+            // Hooks after an instruction see the address of the next one.
+            hooks.add_after(0x1004, move |ctx: &mut RuneContext| {
+                after_.set(ctx.ip());
+                RuneControl::Continue
+            });
+        });
+        assert_eq!(control, RuneControl::Halt);
+        assert_eq!(*all.borrow(), vec![0x1000, 0x1004, 0x1008, 0x100b]);
+        assert_eq!(before.get(), 0x1004);
+        assert_eq!(after.get(), 0x1008);
+    }
+
+    #[test]
+    fn hooks_accesses() {
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let regs = Rc::new(RefCell::new(Vec::new()));
+        let (writes_, regs_) = (writes.clone(), regs.clone());
+        let (_, ctx) = run_hooks(|hooks| {
+            // Memory hooks fire before the access, so that they can provide the value read.
+            hooks.add_mem_read(|ctx: &mut RuneContext, addr, size| {
+                let val = ctx.define_const(0x42, size as usize);
+                ctx.mem_write(addr, val, size);
+                RuneControl::Continue
+            });
+            hooks.add_mem_write(move |ctx: &mut RuneContext, addr, data, size| {
+                writes_.borrow_mut().push((value_is(ctx, addr, 0x2008),
+                                           value_is(ctx, data, 0x42),
+                                           size));
+                RuneControl::Continue
+            });
+            hooks.add_reg_write(move |_: &mut RuneContext, reg: &str, _| {
+                regs_.borrow_mut().push(reg.to_owned());
+                RuneControl::Continue
+            });
+        });
+        assert!(reg_is(&ctx, "rax", 0x42));
+        assert_eq!(*writes.borrow(), vec![(true, true, 64)]);
+        assert_eq!(*regs.borrow(), vec!["rax", "rbx", "rbx"]);
+    }
+
+    #[test]
+    fn hooks_skip() {
+        // Skipping an instruction continues at the next one.
+        let (_, ctx) = run_hooks(|hooks| {
+            hooks.add_before(0x100b, |_: &mut RuneContext| RuneControl::Skip);
+        });
+        assert!(reg_is(&ctx, "rbx", 1));
+
+        // Unless the hook redirected execution.
+        let (_, ctx) = run_hooks(|hooks| {
+            hooks.add_before(0x1000, |ctx: &mut RuneContext| {
+                ctx.set_ip(0x1008);
+                RuneControl::Skip
+            });
+        });
+        assert!(reg_is(&ctx, "rax", 0));
+        assert!(reg_is(&ctx, "rbx", 2));
+
+        // Skipping a register write drops the rest of the instruction.
+        let (_, ctx) = run_hooks(|hooks| {
+            hooks.add_reg_write(|_: &mut RuneContext, reg: &str, _| {
+                if reg == "rbx" { RuneControl::Skip } else { RuneControl::Continue }
+            });
+        });
+        assert!(reg_is(&ctx, "rax", 7));
+        assert!(reg_is(&ctx, "rbx", 0));
+    }
+
+    #[test]
+    fn hooks_halt() {
+        let (control, ctx) = run_hooks(|hooks| {
+            hooks.add_before(0x1008, |_: &mut RuneContext| RuneControl::Halt);
+        });
+        assert_eq!(control, RuneControl::Halt);
+        assert_eq!(ctx.ip(), 0x1008);
+        assert!(reg_is(&ctx, "rax", 7));
+        assert!(reg_is(&ctx, "rbx", 0));
+    }
+
+    #[test]
+    fn hooks_merge() {
+        let mut ctx = RuneContext::builder().ip(0x1000).build().unwrap();
+        let calls = Rc::new(Cell::new(0));
+        let mut hooks = HookManager::new();
+        for &c in &[RuneControl::Skip, RuneControl::TerminatePath, RuneControl::Continue] {
+            let calls = calls.clone();
+            hooks.add_before(0x1000, move |_: &mut RuneContext| {
+                calls.set(calls.get() + 1);
+                c
+            });
+        }
+        // Every hook runs, and the most disruptive control wins.
+        assert_eq!(hooks.before_instruction(&mut ctx), RuneControl::TerminatePath);
+        assert_eq!(calls.get(), 3);
+
+        let halt = hooks.add_instruction(|_: &mut RuneContext| RuneControl::Halt);
+        assert_eq!(hooks.before_instruction(&mut ctx), RuneControl::Halt);
+        assert!(hooks.remove(halt).is_some());
+        assert_eq!(hooks.before_instruction(&mut ctx), RuneControl::TerminatePath);
+
+        // Hooks at other addresses do not run.
+        ctx.set_ip(0x1004);
+        assert_eq!(hooks.before_instruction(&mut ctx), RuneControl::Continue);
+        assert_eq!(hooks.after_instruction(&mut ctx, 0x1000), RuneControl::Continue);
+        hooks.clear();
+        assert!(hooks.is_empty());
+    }
+}
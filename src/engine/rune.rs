@@ -1,17 +1,30 @@
 //! Trait and struct implementations for rune symbolic engine
 
+use std::mem;
+
 use r2pipe::structs::LOpInfo;
 
-use context::context::{Context, Evaluate, MemoryRead, MemoryWrite, RegisterRead, RegisterWrite};
+use context::context::{AddrStrategy, Context, ContextError, Evaluate, MemoryRead, MemoryWrite,
+                       RegisterRead, RegisterWrite};
+use context::event::{self, PathEvent};
+use context::heap::Allocation;
+use context::region::{Access, MemRegion};
 use context::rune_ctx::RuneContext;
 use explorer::explorer::PathExplorer;
 use stream::InstructionStream;
 use engine::engine::{Engine, EngineError, EngineResult};
+use engine::breakpt::BreakpointManager;
+use engine::hook::HookManager;
+use engine::esil_signed::{self, Variant};
+use engine::esil_vars::{self, Arith, InternalVar};
+use engine::summary::SummaryManager;
+use engine::syscall::{LinuxSyscalls, Syscall, SyscallAbi, SyscallHandler, SyscallPolicy, ENOSYS};
 use esil::lexer::{Token, Tokenizer};
 use esil::parser::{Parse, Parser};
 
 use libsmt::theories::{bitvec, core};
 use libsmt::logics::qf_abv;
+use libsmt::backends::z3;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum RuneControl {
@@ -24,6 +37,75 @@ pub enum RuneControl {
     Break,
 }
 
+/// Tunable parameters of the engine.
+#[derive(Clone, Debug)]
+pub struct RuneConfig {
+    /// Maximum number of branches that can go either way followed on a path through a single
+    /// instruction whose branches are decided in place, such as the iterations of an esil loop
+    /// (`GOTO`) with a symbolic condition. Paths that need more are terminated. Branches decided
+    /// by the current constraints are not counted, so loops over concrete counters run to
+    /// completion.
+    pub unroll_bound: usize,
+    /// Maximum number of feasible targets explored when the program counter is written with a
+    /// symbolic value. Jumps with more targets are reported as a `PathEvent::ControlFlowHijack`.
+    pub jump_target_limit: usize,
+    /// Return errors from `Engine::run` instead of terminating the path they occurred on and
+    /// recording them in `Rune::errors`.
+    pub abort_on_error: bool,
+    /// What to do with instructions that have no esil, or esil that rune does not support.
+    pub unsupported: UnsupportedPolicy,
+    /// What to do with system calls that the syscall handler does not know.
+    pub unknown_syscall: SyscallPolicy,
+}
+
+/// Handling of instructions that cannot be executed. Every such instruction is recorded as a
+/// `PathEvent::UnsupportedInstruction`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnsupportedPolicy {
+    /// Terminate the path with an error.
+    Stop,
+    /// Continue at the next instruction.
+    Skip,
+    /// Give every general purpose register and flag, except the program counter and the stack
+    /// pointer, a fresh symbolic value and continue at the next instruction. The registers an
+    /// instruction writes are not known without its esil, so all of them are assumed written.
+    Havoc,
+}
+
+impl Default for RuneConfig {
+    fn default() -> RuneConfig {
+        RuneConfig {
+            unroll_bound: 32,
+            jump_target_limit: 16,
+            abort_on_error: false,
+            unsupported: UnsupportedPolicy::Stop,
+            unknown_syscall: SyscallPolicy::Stop,
+        }
+    }
+}
+
+// Intra-instruction control flow requested by the esil `GOTO` and `BREAK` operators.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EsilFlow {
+    Goto(usize),
+    Break,
+}
+
+// Decisions made while executing an instruction that may be executed again from its initial
+// state: the address of its first symbolic memory access, for `AddrStrategy::Fork`, and the sides
+// taken at the branches decided in place that could go either way.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Replay {
+    addr: Option<u64>,
+    branches: Vec<bool>,
+}
+
+// What is executed at the current address: an instruction, or the summary of the function.
+enum Code {
+    Inst(LOpInfo),
+    Summary(String),
+}
+
 pub struct Rune<Ctx, Exp, S>
     where Ctx: Context,
           Exp: PathExplorer,
@@ -33,11 +115,47 @@ pub struct Rune<Ctx, Exp, S>
     ctx: Ctx,
     /// Path decision algorithm
     explorer: Exp,
-    /// Stores values that are intermediates during symbolic execution. These are not
-    /// a part of register or memory
-    intermediates: Vec<<Ctx as RegisterRead>::VarRef>,
+    /// Stores values that are intermediates during symbolic execution, along with their width
+    /// in bits. These are not a part of register or memory
+    intermediates: Vec<(<Ctx as RegisterRead>::VarRef, usize)>,
     stream: S,
     skip: bool,
+    /// Breakpoints checked before every instruction
+    breakpoints: BreakpointManager<Ctx>,
+    /// User callbacks on instructions, memory and register accesses
+    hooks: HookManager<Ctx>,
+    /// Set when execution was suspended by a breakpoint, so that resuming does not break again
+    /// on the same instruction.
+    resume: bool,
+    config: RuneConfig,
+    /// Pending `GOTO` / `BREAK` for the esil expression being executed.
+    flow: Option<EsilFlow>,
+    /// True if the branches of the current instruction are decided in place (see
+    /// `needs_replay`) instead of by the explorer.
+    in_place: bool,
+    /// Address of the instruction being executed.
+    inst_addr: u64,
+    /// Non-constant value written to the program counter by the current instruction. It is
+    /// resolved once the instruction has been executed completely.
+    pending_jump: Option<<Ctx as RegisterRead>::VarRef>,
+    /// Kind of the last arithmetic operation, needed to evaluate the overflow flag (`$o`).
+    last_arith: Option<Arith>,
+    /// Errors that terminated paths.
+    errors: Vec<EngineError>,
+    /// Number of accesses at symbolic addresses in the current instruction.
+    sym_accesses: usize,
+    /// Decisions to repeat when the current instruction is executed again for a fork.
+    replay: Replay,
+    /// Decisions made so far in the current instruction.
+    taken: Replay,
+    /// Other decisions that are feasible in the current instruction. Each is a fork, for which
+    /// the instruction is executed again.
+    forks: Vec<Replay>,
+    syscalls: Box<SyscallHandler<Ctx>>,
+    /// Summaries run in place of the functions they stand in for.
+    summaries: SummaryManager<Ctx>,
+    /// Final states of the paths on which the entry function returned.
+    returned: Vec<Ctx>,
 }
 
 
@@ -54,34 +172,213 @@ where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
             intermediates: Vec::new(),
             stream: stream,
             skip: false,
+            breakpoints: BreakpointManager::new(),
+            hooks: HookManager::new(),
+            resume: false,
+            config: RuneConfig::default(),
+            flow: None,
+            in_place: false,
+            inst_addr: 0,
+            pending_jump: None,
+            last_arith: None,
+            errors: Vec::new(),
+            sym_accesses: 0,
+            replay: Replay::default(),
+            taken: Replay::default(),
+            forks: Vec::new(),
+            syscalls: Box::new(LinuxSyscalls::new()),
+            summaries: SummaryManager::libc(),
+            returned: Vec::new(),
+        }
+    }
+
+    /// Errors that terminated paths, in the order they occurred.
+    pub fn errors(&self) -> &[EngineError] {
+        &self.errors
+    }
+
+    /// Final states of the paths on which the function execution started in returned to the
+    /// return address of its stack frame (see `ContextBuilder::frame`), in the order they
+    /// returned. Each records a `PathEvent::Returned`.
+    pub fn returned(&self) -> &[Ctx] {
+        &self.returned
+    }
+
+    pub fn config(&self) -> &RuneConfig {
+        &self.config
+    }
+
+    pub fn config_mut(&mut self) -> &mut RuneConfig {
+        &mut self.config
+    }
+
+    pub fn breakpoints(&self) -> &BreakpointManager<Ctx> {
+        &self.breakpoints
+    }
+
+    pub fn breakpoints_mut(&mut self) -> &mut BreakpointManager<Ctx> {
+        &mut self.breakpoints
+    }
+
+    /// Replace the handler of system calls, `LinuxSyscalls` by default.
+    pub fn set_syscalls<H>(&mut self, handler: H)
+        where H: SyscallHandler<Ctx> + 'static
+    {
+        self.syscalls = Box::new(handler);
+    }
+
+    /// Function summaries, the summaries of `Libc` by default. Summaries are only run for
+    /// functions whose address has been added with `SummaryManager::add_target`.
+    pub fn summaries(&self) -> &SummaryManager<Ctx> {
+        &self.summaries
+    }
+
+    pub fn summaries_mut(&mut self) -> &mut SummaryManager<Ctx> {
+        &mut self.summaries
+    }
+
+    pub fn hooks(&self) -> &HookManager<Ctx> {
+        &self.hooks
+    }
+
+    pub fn hooks_mut(&mut self) -> &mut HookManager<Ctx> {
+        &mut self.hooks
+    }
+
+    /// Context of the path being executed.
+    pub fn ctx(&self) -> &Ctx {
+        &self.ctx
+    }
+
+    pub fn ctx_mut(&mut self) -> &mut Ctx {
+        &mut self.ctx
+    }
+
+    pub fn explorer(&self) -> &Exp {
+        &self.explorer
+    }
+
+    pub fn explorer_mut(&mut self) -> &mut Exp {
+        &mut self.explorer
+    }
+
+    /// Hands the explorer and the current context to `f`. Front-ends use this to let an
+    /// (interactive) explorer work on the context while execution is suspended.
+    pub fn with_explorer<F, R>(&mut self, f: F) -> R
+        where F: FnOnce(&mut Exp, &mut Ctx) -> R
+    {
+        f(&mut self.explorer, &mut self.ctx)
+    }
+
+    // Width in bits of addresses and of the program counter.
+    fn word_size(&self) -> usize {
+        self.ctx.arch().addr_width()
+    }
+
+    // Bring `v` from `from` to `to` bits, by extracting the low bits or by zero (or sign)
+    // extension.
+    fn resize(&mut self,
+              v: <Ctx as RegisterRead>::VarRef,
+              from: usize,
+              to: usize,
+              signed: bool)
+              -> <Ctx as RegisterRead>::VarRef {
+        if from == to {
+            v
+        } else if let Some(value) = self.ctx.const_value(&v) {
+            let value = if signed && from < to && (value >> (from - 1)) & 1 == 1 {
+                value | (u64::max_value() << from)
+            } else {
+                value
+            };
+            let mask = if to >= 64 { u64::max_value() } else { (1 << to) - 1 };
+            self.ctx.define_const(value & mask, to)
+        } else if from > to {
+            self.ctx.eval(bitvec::OpCodes::Extract(to as u64 - 1, 0), vec![v])
+        } else if signed {
+            let msb = self.ctx.eval(bitvec::OpCodes::Extract(from as u64 - 1, from as u64 - 1),
+                                    vec![v.clone()]);
+            let one = self.ctx.define_const(1, 1);
+            let is_neg = self.ctx.eval(core::OpCodes::Cmp, vec![msb, one]);
+            let ones = self.ctx.define_const(u64::max_value() >> (64 - (to - from)), to - from);
+            let zeros = self.ctx.define_const(0, to - from);
+            let hi = self.ctx.eval(core::OpCodes::ITE, vec![is_neg, ones, zeros]);
+            self.ctx.eval(bitvec::OpCodes::Concat, vec![hi, v])
+        } else {
+            let zeros = self.ctx.define_const(0, to - from);
+            self.ctx.eval(bitvec::OpCodes::Concat, vec![zeros, v])
+        }
+    }
+
+    // Turn an esil condition (any non-zero value) into a 1 bit value.
+    fn to_cond(&mut self,
+               v: <Ctx as RegisterRead>::VarRef,
+               width: usize)
+               -> <Ctx as RegisterRead>::VarRef {
+        let zero = self.ctx.define_const(0, width);
+        let is_zero = self.ctx.eval(core::OpCodes::Cmp, vec![v, zero]);
+        self.bool_to_bit(is_zero, false)
+    }
+
+    // (ite cond (_ bv1 1) (_ bv0 1)), or the negation if `if_true` is false.
+    fn bool_to_bit(&mut self,
+                   cond: <Ctx as RegisterRead>::VarRef,
+                   if_true: bool)
+                   -> <Ctx as RegisterRead>::VarRef {
+        let const_0 = self.ctx.define_const(0, 1);
+        let const_1 = self.ctx.define_const(1, 1);
+        if if_true {
+            self.ctx.eval(core::OpCodes::ITE, vec![cond, const_1, const_0])
+        } else {
+            self.ctx.eval(core::OpCodes::ITE, vec![cond, const_0, const_1])
         }
     }
 
+    // Read an operand, along with its width in bits. Constants take the width `hint` (the width
+    // of the other operand) if they fit in it, and the word size otherwise.
     fn process_in(&mut self,
-                  t: Option<&Token>)
-                  -> EngineResult<Option<<Ctx as RegisterRead>::VarRef>> {
+                  t: Option<&Token>,
+                  hint: Option<usize>)
+                  -> EngineResult<Option<(<Ctx as RegisterRead>::VarRef, usize)>> {
         if t.is_none() {
             return Ok(None);
         }
+        let word_size = self.word_size();
         let read = match *t.unwrap() {
             Token::ERegister(ref name) | Token::EIdentifier(ref name) => {
-                if self.ctx.alias_of(name.clone()) == Some("PC".to_owned()) {
+                if let Some(var) = InternalVar::from_identifier(name) {
+                    (try!(esil_vars::evaluate(&mut self.ctx, var, self.last_arith)), 64)
+                } else if self.ctx.alias_of(name.clone()) == Some("PC".to_owned()) {
                     let ip = self.ctx.ip();
-                    self.ctx.define_const(ip, 64)
+                    (self.ctx.define_const(ip, word_size), word_size)
                 } else {
-                    self.ctx.reg_read(name)
+                    let unknown = || ContextError::UnknownRegister(name.clone());
+                    let width = try!(self.ctx.reg_width(name).ok_or_else(unknown));
+                    (try!(self.ctx.try_reg_read(name)), width)
                 }
             }
-            Token::EEntry(ref id, Some(64)) => self.intermediates[*id].clone(),
-            Token::EConstant(value) => self.ctx.define_const(value, 64),
+            Token::EEntry(id, _) => self.intermediates[id].clone(),
+            Token::EConstant(value) => {
+                let width = match hint {
+                    Some(width) if width >= 64 || value >> width == 0 => width,
+                    _ => word_size,
+                };
+                (self.ctx.define_const(value, width), width)
+            }
+            // `$$` is the address of the instruction being executed. The program counter has
+            // already been advanced to the next instruction, as radare2 does.
             Token::EAddress => {
-                let ip = self.ctx.ip();
-                self.ctx.define_const(ip, 64)
+                let addr = self.inst_addr;
+                (self.ctx.define_const(addr, word_size), word_size)
             }
-            Token::EOld => self.ctx.e_old(),
-            Token::ECur => self.ctx.e_cur(),
-            Token::ELastsz => self.ctx.define_const(64, 64),
-            _ => unreachable!(),
+            // Internal variables are kept 64 bits wide by the context.
+            Token::EOld => (try!(self.ctx.try_e_old()), 64),
+            Token::ECur => (try!(self.ctx.try_e_cur()), 64),
+            Token::ELastsz => {
+                let lastsz = self.ctx.e_lastsz() as u64;
+                (self.ctx.define_const(lastsz, 64), 64)
+            }
+            _ => return Err(EngineError::InCorrectOperand),
         };
         Ok(Some(read))
     }
@@ -90,10 +387,12 @@ where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
                   token: Token,
                   lhs: Option<Token>,
                   rhs: Option<Token>,
+                  variant: Variant,
                   control: &mut RuneControl)
-                  -> EngineResult<Option<<Ctx as RegisterRead>::VarRef>> {
+                  -> EngineResult<Option<(<Ctx as RegisterRead>::VarRef, usize)>> {
         // Reset previously set `control`
         *control = RuneControl::Continue;
+        let signed = variant == Variant::Signed;
 
         // If skip is active, do no further processing.
         if self.skip {
@@ -103,6 +402,22 @@ where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
             return Ok(None);
         }
 
+        if token == Token::EBreak {
+            self.flow = Some(EsilFlow::Break);
+            return Ok(None);
+        }
+
+        // The interrupt number tells `syscall` and `int 0x80` apart, and both from other
+        // interrupts.
+        if token == Token::EInterrupt {
+            let interrupt = match lhs {
+                Some(Token::EConstant(n)) => Some(n),
+                _ => None,
+            };
+            *control = try!(self.interrupt(interrupt));
+            return Ok(None);
+        }
+
         // asserts to check validity.
         if token.is_arity_zero() {
             return Ok(None);
@@ -110,26 +425,51 @@ where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
         // println!("****");
 
         // println!("OPERANDS TO {:?}: {:?} {:?}", token, lhs, rhs);
-        let l_op = self.process_in(lhs.as_ref()).expect("LHS is ERR");
-        let r_op = self.process_in(rhs.as_ref()).expect("RHS is ERR");
-        // Since the operator arity us _atleast_ one. assert! that lhs is some.
-        assert!(l_op.is_some());
-        if token.is_binary() {
-            assert!(r_op.is_some());
+        // The operand that is not a constant is read first, so that a constant operand can take
+        // its width.
+        let (l_op, r_op) = if let Some(Token::EConstant(_)) = lhs {
+            let r_op = try!(self.process_in(rhs.as_ref(), None));
+            let l_op = try!(self.process_in(lhs.as_ref(), r_op.as_ref().map(|op| op.1)));
+            (l_op, r_op)
+        } else {
+            let l_op = try!(self.process_in(lhs.as_ref(), None));
+            let r_op = try!(self.process_in(rhs.as_ref(), l_op.as_ref().map(|op| op.1)));
+            (l_op, r_op)
+        };
+        // The operator arity is _atleast_ one.
+        if token.is_binary() && r_op.is_none() {
+            return Err(EngineError::MissingOperand);
         }
+        let (l_op, l_width) = try!(l_op.ok_or(EngineError::MissingOperand));
 
         // Instructions that do not produce a result
         // Example: Mem Write / Eq / If / EndIf
         match token {
             Token::EEq => {
                 let res = if let Some(Token::EIdentifier(ref reg)) = lhs {
+                    let (r_op, r_width) = r_op.unwrap();
                     if self.ctx.alias_of(reg.clone()) == Some("PC".to_owned()) {
-                        if let Token::EConstant(const_) = rhs.unwrap() {
+                        let word_size = self.word_size();
+                        let r_op = self.resize(r_op, r_width, word_size, false);
+                        *control = self.hooks.reg_write(&mut self.ctx, reg, r_op.clone());
+                        if *control != RuneControl::Continue {
+                            return Ok(None);
+                        }
+                        if let Some(Token::EConstant(const_)) = rhs {
                             self.ctx.set_ip(const_);
+                            self.pending_jump = None;
+                        } else {
+                            self.pending_jump = Some(r_op);
                         }
                     } else {
+                        // Values are truncated or zero extended to the width of the register.
+                        let r_op = self.resize(r_op, r_width, l_width, false);
+                        *control = self.hooks.reg_write(&mut self.ctx, reg, r_op.clone());
+                        if *control != RuneControl::Continue {
+                            return Ok(None);
+                        }
                         // println!("REGISTER WRITE: {:?} = {:?}", reg, r_op);
-                        self.ctx.reg_write(reg, r_op.unwrap());
+                        try!(self.ctx.try_reg_write(reg, r_op));
                     }
                     Ok(None)
                 } else {
@@ -138,11 +478,43 @@ where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
                 return res;
             }
             Token::EIf => {
-                *control = self.explorer.register_branch(&mut self.ctx, l_op.unwrap());
+                let cond = self.to_cond(l_op, l_width);
+                *control = if self.in_place {
+                    self.branch_in_place(cond)
+                } else {
+                    self.explorer.register_branch(&mut self.ctx, cond)
+                };
                 return Ok(None);
             }
+            Token::EGoto => {
+                // The target is an index into the words of the esil expression and is always
+                // emitted as a constant by radare2.
+                return if let Some(Token::EConstant(n)) = lhs {
+                    self.flow = Some(EsilFlow::Goto(n as usize));
+                    Ok(None)
+                } else {
+                    Err(EngineError::InCorrectOperand)
+                };
+            }
+            // The operand has already been popped off the esil stack, discard it.
+            Token::EPop => return Ok(None),
             Token::EPoke(size) => {
-                self.ctx.mem_write(l_op.unwrap(), r_op.unwrap(), size as u64);
+                let word_size = self.word_size();
+                let addr = self.resize(l_op, l_width, word_size, false);
+                let addr = self.resolve_addr(addr, true);
+                *control = self.check_access(addr.clone(), size as u64, Access::Write);
+                if *control != RuneControl::Continue {
+                    return Ok(None);
+                }
+                let (data, d_width) = r_op.unwrap();
+                let data = self.resize(data, d_width, size as usize, false);
+                *control = self.hooks.mem_write(&mut self.ctx,
+                                                addr.clone(),
+                                                data.clone(),
+                                                size as u64);
+                if *control == RuneControl::Continue {
+                    try!(self.ctx.try_mem_write(addr, data, size as u64));
+                }
                 return Ok(None);
             }
             Token::ENop => return Ok(None),
@@ -151,36 +523,117 @@ where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
 
         let result = match token {
             Token::EPeek(size) => {
-                self.ctx.mem_read(l_op.unwrap(), size as u64)
+                let word_size = self.word_size();
+                let addr = self.resize(l_op, l_width, word_size, false);
+                let addr = self.resolve_addr(addr, false);
+                *control = self.check_access(addr.clone(), size as u64, Access::Read);
+                if *control != RuneControl::Continue {
+                    return Ok(None);
+                }
+                *control = self.hooks.mem_read(&mut self.ctx, addr.clone(), size as u64);
+                if *control != RuneControl::Continue {
+                    return Ok(None);
+                }
+                (try!(self.ctx.try_mem_read(addr, size as u64)), size as usize)
             }
             Token::ECmp | Token::ELt | Token::EGt => {
                 // This case is a bit different as we want the result to be a bitvector rather
                 // than
                 // a bool. Hence we adopt the following stratergy:
-                // (ite (= lhs rhs) (_ bv1 64) (_ bv0 64))
-                // FIXME: Set esil_old and esil_cur
-                let e_cur = self.ctx.eval(bitvec::OpCodes::BvSub,
-                                          vec![l_op.as_ref().unwrap().clone(),
-                                               r_op.as_ref().unwrap().clone()]);
+                // (ite (= lhs rhs) (_ bv1 1) (_ bv0 1))
+                // Flags after a comparison are those of lhs - rhs. Operands of signed
+                // comparisons are sign extended to the wider of the two.
+                let (r_op, r_width) = r_op.unwrap();
+                let width = ::std::cmp::max(l_width, r_width);
+                let l_op = self.resize(l_op, l_width, width, signed);
+                let r_op = self.resize(r_op, r_width, width, signed);
+                let e_cur = self.ctx.eval(bitvec::OpCodes::BvSub, vec![l_op.clone(), r_op.clone()]);
+                let e_cur = self.resize(e_cur, width, 64, false);
+                let e_old = self.resize(l_op.clone(), width, 64, false);
                 self.ctx.set_e_cur(e_cur);
-                self.ctx.set_e_old(l_op.as_ref().unwrap().clone());
-                let const_0 = self.ctx.define_const(0, 64);
-                let const_1 = self.ctx.define_const(1, 64);
-                let eq = self.ctx.eval(token.to_smt(), vec![l_op.unwrap(), r_op.unwrap()]);
-                self.ctx.eval(core::OpCodes::ITE, vec![eq, const_1, const_0])
-            }
-            Token::EPop => unimplemented!(),
-            Token::EGoto => unimplemented!(),
-            Token::EBreak => unimplemented!(),
+                self.ctx.set_e_old(e_old);
+                self.ctx.set_e_lastsz(width);
+                self.last_arith = Some(Arith::Sub);
+                let smt_fn = token.to_smt(signed).unwrap();
+                let eq = self.ctx.eval(smt_fn, vec![l_op, r_op]);
+                (self.bool_to_bit(eq, true), 1)
+            }
+            Token::ENeg => {
+                // esil `!` is a logical not: (ite (= lhs 0) (_ bv1 1) (_ bv0 1))
+                let const_0 = self.ctx.define_const(0, l_width);
+                let is_zero = self.ctx.eval(core::OpCodes::Cmp, vec![l_op, const_0]);
+                (self.bool_to_bit(is_zero, true), 1)
+            }
+            Token::ERol | Token::ERor => {
+                // SMT-LIB only rotates by constant amounts. Rotation by a (possibly symbolic)
+                // amount is expressed in terms of shifts, with n' = n mod width:
+                //   rol(x, n) = (x << n') | (x >> (width - n'))
+                //   ror(x, n) = (x >> n') | (x << (width - n'))
+                // Shifting by the full width yields zero, which covers n' = 0.
+                let x = l_op;
+                let (n, n_width) = r_op.unwrap();
+                let n = self.resize(n, n_width, l_width, false);
+                let width = self.ctx.define_const(l_width as u64, l_width);
+                let n = self.ctx.eval(bitvec::OpCodes::BvURem, vec![n, width.clone()]);
+                let n_ = self.ctx.eval(bitvec::OpCodes::BvSub, vec![width, n.clone()]);
+                let (fwd, back) = if token == Token::ERol {
+                    (bitvec::OpCodes::BvShl, bitvec::OpCodes::BvLShr)
+                } else {
+                    (bitvec::OpCodes::BvLShr, bitvec::OpCodes::BvShl)
+                };
+                let hi = self.ctx.eval(fwd, vec![x.clone(), n]);
+                let lo = self.ctx.eval(back, vec![x, n_]);
+                (self.ctx.eval(bitvec::OpCodes::BvOr, vec![hi, lo]), l_width)
+            }
+            Token::ELsl if variant == Variant::SignExtend => {
+                // `~`: sign extend the low `rhs` bits of lhs to the word size.
+                let (bits, _) = r_op.unwrap();
+                let bits = match self.ctx.const_value(&bits) {
+                    Some(bits) if bits > 0 && bits <= 64 => bits as usize,
+                    _ => return Err(EngineError::InCorrectOperand),
+                };
+                let word_size = self.word_size();
+                let v = self.resize(l_op, l_width, bits, false);
+                (self.resize(v, bits, word_size, true), word_size)
+            }
+            Token::ELsl | Token::ELsr => {
+                // The result has the width of the value being shifted.
+                let (n, n_width) = r_op.unwrap();
+                let n = self.resize(n, n_width, l_width, false);
+                let smt_fn = token.to_smt(signed).unwrap();
+                (self.ctx.eval(smt_fn, vec![l_op, n]), l_width)
+            }
             _ => {
-                let operands = {
-                    if token.is_unary() {
-                        vec![l_op.unwrap()]
+                match token {
+                    Token::EAdd => self.last_arith = Some(Arith::Add),
+                    Token::ESub => self.last_arith = Some(Arith::Sub),
+                    _ => {}
+                }
+                let smt_fn = if let Some(smt_fn) = token.to_smt(signed) {
+                    smt_fn
+                } else {
+                    return Err(EngineError::Undefined);
+                };
+                if token.is_unary() {
+                    (self.ctx.eval(smt_fn, vec![l_op]), l_width)
+                } else {
+                    // Operands of different widths are zero extended to the wider of the two, or
+                    // sign extended for signed operators.
+                    let (r_op, r_width) = r_op.unwrap();
+                    let width = ::std::cmp::max(l_width, r_width);
+                    let l_op = self.resize(l_op, l_width, width, signed);
+                    let r_op = self.resize(r_op, r_width, width, signed);
+                    let folded = match (self.ctx.const_value(&l_op),
+                                        self.ctx.const_value(&r_op)) {
+                        (Some(l), Some(r)) if !signed => fold(&token, l, r, width),
+                        _ => None,
+                    };
+                    if let Some(value) = folded {
+                        (self.ctx.define_const(value, width), width)
                     } else {
-                        vec![l_op.unwrap(), r_op.unwrap()]
+                        (self.ctx.eval(smt_fn, vec![l_op, r_op]), width)
                     }
-                };
-                self.ctx.eval(token.to_smt(), operands)
+                }
             }
         };
 
@@ -188,43 +641,311 @@ where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
     }
 
     // Write out to intermediates and return a token to it.
-    fn process_out(&mut self, res: &<Ctx as RegisterRead>::VarRef) -> Token {
+    fn process_out(&mut self, res: &(<Ctx as RegisterRead>::VarRef, usize)) -> Token {
         self.intermediates.push(res.clone());
-        Token::EEntry(self.intermediates.len() - 1, Some(64))
+        Token::EEntry(self.intermediates.len() - 1, None)
     }
-}
 
-impl<Ctx, Exp, S> Engine for Rune<Ctx, Exp, S>
-where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
-      Exp: PathExplorer<C = RuneControl, Ctx = Ctx>,
-      S: InstructionStream<Output = LOpInfo, Index = u64>
-{
-    fn run(&mut self) -> EngineResult<()> {
-        let mut p = Parser::init(None, Some(64));
+    // Branch of an instruction whose branches are decided in place. If the current constraints
+    // decide the branch, it is taken without further ado, so that loops over concrete counters
+    // run to completion. A branch that can go either way takes the side being replayed, or the
+    // true side, leaving the false side to a fork. At most `unroll_bound` such branches are taken
+    // in one execution of the instruction.
+    fn branch_in_place(&mut self, cond: <Ctx as RegisterRead>::VarRef) -> RuneControl {
+        let side = match self.feasible(cond.clone()) {
+            (true, false) => return RuneControl::ExploreTrue,
+            (false, true) => return RuneControl::ExploreFalse,
+            (false, false) => return RuneControl::TerminatePath,
+            (true, true) => {
+                let n = self.taken.branches.len();
+                if n >= self.config.unroll_bound {
+                    return RuneControl::TerminatePath;
+                }
+                match self.replay.branches.get(n) {
+                    Some(&side) => side,
+                    None => {
+                        let mut other = self.taken.clone();
+                        other.branches.push(false);
+                        self.forks.push(other);
+                        true
+                    }
+                }
+            }
+        };
+        self.taken.branches.push(side);
+        let side_ = self.ctx.define_const(side as u64, 1);
+        self.ctx.eval(core::OpCodes::Cmp, vec![cond, side_]);
+        if side {
+            RuneControl::ExploreTrue
+        } else {
+            RuneControl::ExploreFalse
+        }
+    }
+
+    // Check which sides of an esil conditional on the 1 bit `cond` are satisfiable in the current
+    // context.
+    fn feasible(&self, cond: <Ctx as RegisterRead>::VarRef) -> (bool, bool) {
+        let mut z3: z3::Z3 = Default::default();
+
+        let mut f_ctx = self.ctx.clone();
+        let zero = f_ctx.define_const(0, 1);
+        let is_zero = f_ctx.eval(core::OpCodes::Cmp, vec![cond.clone(), zero]);
+        let mut t_ctx = f_ctx.clone();
+        t_ctx.eval(core::OpCodes::Not, vec![is_zero]);
+
+        (t_ctx.check_sat(&mut z3), f_ctx.check_sat(&mut z3))
+    }
+
+    // Abandon the current path and load the next one from the explorer. Returns false if there
+    // are no more paths left to explore. Paths saved by the explorer in the middle of an
+    // instruction resume at the next instruction, which is why branches followed by more esil
+    // are decided in place.
+    fn next_path(&mut self) -> bool {
+        self.skip = false;
+        self.explorer.next_job(&mut self.ctx).is_some()
+    }
+
+    // Symbolically execute the esil of a single instruction. Returns `Continue` if the
+    // instruction was executed (or skipped) and execution may proceed to the next instruction.
+    fn execute(&mut self, opinfo: &LOpInfo) -> EngineResult<RuneControl> {
+        let in_place = opinfo.esil.as_ref().map_or(false, |esil| needs_replay(esil));
+        let snapshot = match self.ctx.addr_strategy() {
+            AddrStrategy::Fork(_) => Some(self.ctx.clone()),
+            _ if in_place => Some(self.ctx.clone()),
+            _ => None,
+        };
+        self.replay = Replay::default();
+        self.taken = Replay::default();
+        self.forks.clear();
+        let control = try!(self.execute_inst(opinfo));
+        let forks = mem::replace(&mut self.forks, Vec::new());
+        match snapshot {
+            Some(snapshot) if !forks.is_empty() => self.fork(opinfo, snapshot, forks, control),
+            _ => Ok(control),
+        }
+    }
+
+    // Execute the instruction again from `snapshot` for every fork, that is for every other
+    // address of its first symbolic memory access and every other side of its branches decided
+    // in place, and hand the resulting states to the explorer along with the current one.
+    fn fork(&mut self,
+            opinfo: &LOpInfo,
+            snapshot: Ctx,
+            mut forks: Vec<Replay>,
+            control: RuneControl)
+            -> EngineResult<RuneControl> {
+        let mut states = Vec::new();
+        if control == RuneControl::Continue {
+            states.push(self.ctx.clone());
+        }
+        let current = mem::replace(&mut self.ctx, snapshot.clone());
+        // Executions for a fork find forks of their own, with decisions beyond those replayed.
+        let mut i = 0;
+        while i < forks.len() {
+            self.ctx = snapshot.clone();
+            self.replay = forks[i].clone();
+            self.taken = Replay::default();
+            let result = self.execute_inst(opinfo);
+            forks.extend(self.forks.drain(..));
+            i += 1;
+            match result {
+                Ok(RuneControl::Continue) => states.push(self.ctx.clone()),
+                Ok(_) => {}
+                Err(e) => {
+                    if self.config.abort_on_error {
+                        self.ctx = current;
+                        return Err(e);
+                    }
+                    self.errors.push(e);
+                }
+            }
+        }
+        self.replay = Replay::default();
+        self.ctx = current;
+        Ok(self.explorer.register_targets(&mut self.ctx, states))
+    }
+
+    fn execute_inst(&mut self, opinfo: &LOpInfo) -> EngineResult<RuneControl> {
+        let width = try!(opinfo.size.ok_or(EngineError::InvalidInstruction(self.ctx.ip())));
+
+        // Increment ip by instruction width
+        self.inst_addr = self.ctx.ip();
+        self.ctx.increment_ip(width);
+
+        let addr = self.inst_addr;
+        if !self.ctx.regions().is_empty() &&
+           !self.ctx.regions().iter().any(|r| r.perms.exec && r.contains(addr, width)) {
+            let mut z3: z3::Z3 = Default::default();
+            let model = match self.ctx.try_solve(&mut z3) {
+                Ok(values) => event::named_values(&self.ctx, &values),
+                Err(_) => Vec::new(),
+            };
+            self.ctx.record_event(PathEvent::InvalidAccess {
+                addr: addr,
+                access: Access::Exec,
+                target: addr,
+                size: width,
+                model: model,
+            });
+            return Ok(RuneControl::TerminatePath);
+        }
+
+        let esil = match opinfo.esil {
+            Some(ref esil) if is_supported(esil) => esil,
+            _ => {
+                let addr = self.inst_addr;
+                return self.unsupported(opinfo, EngineError::InvalidInstruction(addr));
+            }
+        };
         let mut control = RuneControl::Continue;
+        match self.execute_esil(esil, &mut control) {
+            Ok(_) => {}
+            // Effects of the operators executed before the unsupported one are kept.
+            Err(EngineError::Esil { ref cause, .. }) if **cause == EngineError::Undefined => {
+                self.pending_jump = None;
+                return self.unsupported(opinfo, EngineError::Undefined);
+            }
+            Err(e) => return Err(e),
+        }
 
-        loop {
-            // println!("{}", self.ctx.ip());
-            let opinfo = if let Some(opinfo_) = self.stream.at(self.ctx.ip()) {
-                opinfo_
-            } else {
-                // Request for a new state from queue.
-                if let Some(_) = self.explorer.next_job(&mut self.ctx) {
-                    self.stream.at(self.ctx.ip()).unwrap()
+        match control {
+            RuneControl::TerminatePath | RuneControl::Halt => Ok(control),
+            _ => {
+                if let Some(target) = self.pending_jump.take() {
+                    Ok(self.jump(target))
                 } else {
-                    break;
+                    Ok(RuneControl::Continue)
                 }
-            };
+            }
+        }
+    }
 
-            let esil = opinfo.esil.as_ref().unwrap();
+    // Hand a system call to the syscall handler, and apply the configured `SyscallPolicy` if the
+    // handler does not know it. Interrupts other than system calls are not supported.
+    fn interrupt(&mut self, interrupt: Option<u64>) -> EngineResult<RuneControl> {
+        let abi = try!(SyscallAbi::linux(&self.ctx.arch(), interrupt)
+                           .ok_or(EngineError::Undefined));
+        let addr = self.inst_addr;
+        let number = match try!(self.syscalls.syscall(&mut self.ctx, &abi, addr)) {
+            Syscall::Done(control) => return Ok(control),
+            Syscall::Unknown(number) => number,
+        };
+        self.ctx.record_event(PathEvent::UnknownSyscall {
+            addr: addr,
+            number: number,
+        });
+        match self.config.unknown_syscall {
+            SyscallPolicy::Stop => return Err(EngineError::UnknownSyscall(number)),
+            SyscallPolicy::Enosys => try!(abi.set_return(&mut self.ctx, ENOSYS.wrapping_neg())),
+            SyscallPolicy::Havoc => {
+                let name = format!("havoc_{:x}_{}_{}", addr, abi.ret, self.ctx.events().len());
+                let width = try!(abi.ret_width(&self.ctx));
+                let var = self.ctx.define_var(name, width);
+                try!(abi.set_return_var(&mut self.ctx, var));
+            }
+        }
+        Ok(RuneControl::Continue)
+    }
 
-            // println!("{}", esil);
+    // Apply the configured `UnsupportedPolicy` to the instruction at `inst_addr`, which has no
+    // esil or esil that cannot be executed. `err` terminates the path if the policy is `Stop`.
+    fn unsupported(&mut self, opinfo: &LOpInfo, err: EngineError) -> EngineResult<RuneControl> {
+        let addr = self.inst_addr;
+        let havoc = if self.config.unsupported == UnsupportedPolicy::Havoc {
+            self.havoc_registers()
+        } else {
+            Vec::new()
+        };
+        for reg in &havoc {
+            // The number of events is unique on the path, and so is the name of the variable.
+            let name = format!("havoc_{:x}_{}_{}", addr, reg, self.ctx.events().len());
+            let width = try!(self.ctx
+                                 .reg_width(reg)
+                                 .ok_or_else(|| ContextError::UnknownRegister(reg.clone())));
+            let var = self.ctx.define_var(name, width);
+            try!(self.ctx.try_reg_write(reg, var));
+        }
+        self.ctx.record_event(PathEvent::UnsupportedInstruction {
+            addr: addr,
+            opcode: opinfo.opcode.clone(),
+            esil: opinfo.esil.clone(),
+            havoc: havoc,
+        });
+
+        match self.config.unsupported {
+            UnsupportedPolicy::Stop => Err(err),
+            UnsupportedPolicy::Skip | UnsupportedPolicy::Havoc => Ok(RuneControl::Continue),
+        }
+    }
 
-            // Increment ip by instruction width
-            let width = opinfo.size.as_ref().unwrap();
-            self.ctx.increment_ip(*width);
+    // Registers an unsupported instruction may have written. Which registers an instruction
+    // writes cannot be told reliably from its disassembly, so this is every general purpose
+    // register and flag, except the program counter and the stack pointer.
+    fn havoc_registers(&self) -> Vec<String> {
+        let kept = [Some("PC".to_owned()), Some("SP".to_owned())];
+        self.ctx
+            .general_registers()
+            .into_iter()
+            .filter(|reg| !kept.contains(&self.ctx.alias_of(reg.clone())))
+            .collect()
+    }
 
-            while let Some(ref token) = p.parse::<_, Tokenizer>(esil) {
+    /// Symbolically execute an esil expression on the current context, as if it were the esil
+    /// of the instruction at the current address. The instruction pointer is not advanced, but
+    /// the expression may write to it. Returns the results of the operations that are left on
+    /// the esil stack, along with their widths in bits. Branches of loops, and of conditionals
+    /// followed by more esil, only follow their true side if they can go either way.
+    pub fn eval_esil(&mut self,
+                     esil: &str)
+                     -> EngineResult<Vec<(<Ctx as RegisterRead>::VarRef, usize)>> {
+        self.inst_addr = self.ctx.ip();
+        self.replay = Replay::default();
+        self.taken = Replay::default();
+        let mut control = RuneControl::Continue;
+        let results = try!(self.execute_esil(esil, &mut control));
+        self.forks.clear();
+        if let Some(target) = self.pending_jump.take() {
+            self.jump(target);
+        }
+        Ok(results)
+    }
+
+    // Run the esil expression, leaving the control requested by the last operator in `control`.
+    // Returns the results that were not consumed by other operators.
+    fn execute_esil(&mut self,
+                    raw: &str,
+                    control: &mut RuneControl)
+                    -> EngineResult<Vec<(<Ctx as RegisterRead>::VarRef, usize)>> {
+        // Internal variables (`$z`, `$c64`, ...) are rewritten into identifiers that the parser
+        // accepts. This does not change the number of words.
+        let esil = esil_vars::rewrite(raw);
+        // Targets of `GOTO` are indices into the words of the expression.
+        let words = esil.split(',').collect::<Vec<_>>();
+        // Signed operators are rewritten into unsigned ones, likewise keeping the word count.
+        let (mut expr, mut signedness) = esil_signed::rewrite(&esil);
+
+        // println!("{}", esil);
+
+        self.skip = false;
+        self.pending_jump = None;
+        self.flow = None;
+        self.in_place = needs_replay(&esil);
+        self.last_arith = None;
+        self.sym_accesses = 0;
+        // Index of the token being executed, for error reports.
+        let mut position = 0;
+        // Intermediates pushed on the esil stack and not yet used as operands.
+        let mut stack = Vec::new();
+        // Values on the esil stack at a `GOTO`, bottom first.
+        let mut carried = Vec::new();
+
+        'esil: loop {
+            let mut p = Parser::init(None, Some(self.word_size() as _));
+            // Values pushed before a `GOTO` stay on the esil stack, as they do in radare2.
+            for value in carried.drain(..) {
+                p.push(value);
+            }
+            while let Some(ref token) = p.parse::<_, Tokenizer>(&expr) {
                 // println!("{:?}", token);
                 // If skip is active, we do not want to modify the esil stack
                 let (lhs, rhs) = if self.skip {
@@ -232,15 +953,61 @@ where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
                 } else {
                     p.fetch_operands(token)
                 };
+                for op in lhs.iter().chain(rhs.iter()) {
+                    if let Token::EEntry(id, _) = *op {
+                        stack.retain(|&e| e != id);
+                    }
+                }
 
-                if let Ok(Some(ref res)) = self.process_op(token.clone(), lhs, rhs, &mut control) {
-                    let rt = self.process_out(res);
-                    p.push(rt);
+                let variant = signedness.next(token);
+                match self.process_op(token.clone(), lhs, rhs, variant, control) {
+                    Ok(Some(ref res)) => {
+                        let rt = self.process_out(res);
+                        stack.push(self.intermediates.len() - 1);
+                        p.push(rt);
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        return Err(EngineError::Esil {
+                            addr: self.inst_addr,
+                            esil: raw.to_owned(),
+                            position: position,
+                            token: format!("{:?}", token),
+                            cause: Box::new(e),
+                        })
+                    }
+                }
+                position += 1;
+
+                match self.flow.take() {
+                    Some(EsilFlow::Break) => break 'esil,
+                    Some(EsilFlow::Goto(n)) => {
+                        if n >= words.len() {
+                            return Err(EngineError::Esil {
+                                addr: self.inst_addr,
+                                esil: raw.to_owned(),
+                                position: position - 1,
+                                token: format!("{:?}", token),
+                                cause: Box::new(EngineError::InCorrectOperand),
+                            });
+                        }
+                        // Restart parsing at the n-th word, with the esil stack as it is.
+                        while let (Some(value), _) = p.fetch_operands(&Token::EPop) {
+                            carried.push(value);
+                        }
+                        carried.reverse();
+                        let (expr_, signedness_) = esil_signed::rewrite(&words[n..].join(","));
+                        expr = expr_;
+                        signedness = signedness_;
+                        self.skip = false;
+                        continue 'esil;
+                    }
+                    None => {}
                 }
 
                 // `ExploreTrue` -> Don't skip the section inside the ?{,...,}
                 // `ExploreFalse` -> Skip the section inside the ?{,...,}
-                match control {
+                match *control {
                     RuneControl::ExploreTrue => {
                         self.skip = false;
                     }
@@ -248,29 +1015,528 @@ where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
                         self.skip = true;
                     }
                     RuneControl::Continue => continue,
-                    _ => break,
+                    _ => break 'esil,
+                }
+            }
+            break;
+        }
+
+        Ok(stack.into_iter().map(|id| self.intermediates[id].clone()).collect())
+    }
+
+    // Apply the address strategy of the context to a memory access at `addr`. Addresses that
+    // can only take a single value are replaced by that value.
+    fn resolve_addr(&mut self,
+                    addr: <Ctx as RegisterRead>::VarRef,
+                    write: bool)
+                    -> <Ctx as RegisterRead>::VarRef {
+        let strategy = self.ctx.addr_strategy();
+        let limit = match strategy {
+            AddrStrategy::Symbolic => return addr,
+            AddrStrategy::Fork(n) => ::std::cmp::max(n, 1),
+            AddrStrategy::Concretize | AddrStrategy::Region { .. } => 1,
+        };
+        let word_size = self.word_size();
+        let mut values = self.feasible_values(addr.clone(), limit + 1);
+        match values.len() {
+            // The path is infeasible.
+            0 => return addr,
+            1 => return self.ctx.define_const(values[0], word_size),
+            _ => {}
+        }
+
+        let first = self.sym_accesses == 0;
+        self.sym_accesses += 1;
+        let target = match strategy {
+            AddrStrategy::Region { start, end } => {
+                let start_ = self.ctx.define_const(start, word_size);
+                let end_ = self.ctx.define_const(end, word_size);
+                self.ctx.eval(bitvec::OpCodes::BvUGe, vec![addr.clone(), start_]);
+                self.ctx.eval(bitvec::OpCodes::BvULt, vec![addr.clone(), end_]);
+                None
+            }
+            AddrStrategy::Fork(_) if first => {
+                let target = match self.replay.addr {
+                    Some(pin) => pin,
+                    None => {
+                        values.truncate(limit);
+                        for &value in &values[1..] {
+                            self.forks.push(Replay {
+                                addr: Some(value),
+                                branches: self.taken.branches.clone(),
+                            });
+                        }
+                        values[0]
+                    }
+                };
+                self.taken.addr = Some(target);
+                Some(target)
+            }
+            _ => Some(values[0]),
+        };
+
+        let inst_addr = self.inst_addr;
+        self.ctx.record_event(PathEvent::SymbolicAccess {
+            addr: inst_addr,
+            write: write,
+            strategy: strategy,
+            targets: target.into_iter().collect(),
+        });
+        if let Some(target) = target {
+            let target_ = self.ctx.define_const(target, word_size);
+            self.ctx.eval(core::OpCodes::Cmp, vec![addr, target_.clone()]);
+            target_
+        } else {
+            addr
+        }
+    }
+
+    // Check an access of `size` bits at `addr` against the heap and the regions of the context.
+    // An access that may fall outside of the regions permitting it is recorded as a
+    // `PathEvent::InvalidAccess`. The path then continues with the valid accesses, if there are
+    // any.
+    fn check_access(&mut self,
+                    addr: <Ctx as RegisterRead>::VarRef,
+                    size: u64,
+                    access: Access)
+                    -> RuneControl {
+        let control = self.check_heap(addr.clone(), size, access == Access::Write);
+        if control != RuneControl::Continue || self.ctx.regions().is_empty() {
+            return control;
+        }
+        let n = size / 8;
+        let word_size = self.word_size();
+        let regions = self.ctx
+                          .regions()
+                          .iter()
+                          .filter(|r| r.perms.allows(access) && r.size >= n)
+                          .cloned()
+                          .collect::<Vec<_>>();
+
+        let mut invalid = self.ctx.clone();
+        if let Some(valid) = in_regions(&mut invalid, addr.clone(), n, &regions, word_size) {
+            invalid.eval(core::OpCodes::Not, vec![valid]);
+        }
+        let target = invalid.define_var("rune_value", word_size);
+        invalid.eval(core::OpCodes::Cmp, vec![target.clone(), addr.clone()]);
+        let mut z3: z3::Z3 = Default::default();
+        if !invalid.check_sat(&mut z3) {
+            return RuneControl::Continue;
+        }
+        let mut z3: z3::Z3 = Default::default();
+        let values = match invalid.try_solve(&mut z3) {
+            Ok(values) => values,
+            Err(_) => return RuneControl::Continue,
+        };
+
+        let inst_addr = self.inst_addr;
+        let event = PathEvent::InvalidAccess {
+            addr: inst_addr,
+            access: access,
+            target: values.get(&target).cloned().unwrap_or(0),
+            size: n,
+            model: event::named_values(&invalid, &values),
+        };
+        self.ctx.record_event(event);
+
+        if in_regions(&mut self.ctx, addr, n, &regions, word_size).is_none() {
+            return RuneControl::TerminatePath;
+        }
+        let mut z3: z3::Z3 = Default::default();
+        if self.ctx.check_sat(&mut z3) {
+            RuneControl::Continue
+        } else {
+            RuneControl::TerminatePath
+        }
+    }
+
+    // Check an access of `size` bits at `addr` against the allocations of the heap. An access
+    // that may start in a freed allocation, or in a live one but end beyond it, is recorded as a
+    // `PathEvent::UseAfterFree` or `PathEvent::HeapOutOfBounds`. The path then continues with the
+    // other accesses, if there are any.
+    fn check_heap(&mut self,
+                  addr: <Ctx as RegisterRead>::VarRef,
+                  size: u64,
+                  write: bool)
+                  -> RuneControl {
+        let allocs = match self.ctx.const_value(&addr) {
+            Some(addr) => self.ctx.heap().find(addr).into_iter().cloned().collect(),
+            None => self.ctx.heap().allocations().to_vec(),
+        };
+        let n = size / 8;
+        let word_size = self.word_size();
+        let mut reported = false;
+        for alloc in allocs {
+            let mut bad = self.ctx.clone();
+            heap_violation(&mut bad, addr.clone(), n, &alloc, word_size);
+            let target = bad.define_var("rune_value", word_size);
+            bad.eval(core::OpCodes::Cmp, vec![target.clone(), addr.clone()]);
+            let mut z3: z3::Z3 = Default::default();
+            if !bad.check_sat(&mut z3) {
+                continue;
+            }
+            let mut z3: z3::Z3 = Default::default();
+            let values = match bad.try_solve(&mut z3) {
+                Ok(values) => values,
+                Err(_) => continue,
+            };
+            let inst_addr = self.inst_addr;
+            let target = values.get(&target).cloned().unwrap_or(0);
+            let model = event::named_values(&bad, &values);
+            self.ctx.record_event(if alloc.live {
+                PathEvent::HeapOutOfBounds {
+                    addr: inst_addr,
+                    write: write,
+                    target: target,
+                    size: n,
+                    base: alloc.base,
+                    model: model,
+                }
+            } else {
+                PathEvent::UseAfterFree {
+                    addr: inst_addr,
+                    write: write,
+                    target: target,
+                    size: n,
+                    base: alloc.base,
+                    model: model,
+                }
+            });
+            let violation = heap_violation(&mut self.ctx, addr.clone(), n, &alloc, word_size);
+            self.ctx.eval(core::OpCodes::Not, vec![violation]);
+            reported = true;
+        }
+
+        let mut z3: z3::Z3 = Default::default();
+        if !reported || self.ctx.check_sat(&mut z3) {
+            RuneControl::Continue
+        } else {
+            RuneControl::TerminatePath
+        }
+    }
+
+    // End the current path, on which the entry function has returned.
+    fn entry_returned(&mut self) -> RuneControl {
+        let cc = self.ctx.call_conv();
+        let value = match cc.map(|cc| cc.return_value(&mut self.ctx)) {
+            Some(Ok(value)) => self.ctx.const_value(&value),
+            _ => None,
+        };
+        self.ctx.record_event(PathEvent::Returned { value: value });
+        self.returned.push(self.ctx.clone());
+        if self.next_path() {
+            RuneControl::TerminatePath
+        } else {
+            RuneControl::Halt
+        }
+    }
+
+    // Run the summary of the function `name` at the current address, and return to the caller.
+    fn summarize(&mut self, name: &str) -> EngineResult<RuneControl> {
+        self.inst_addr = self.ctx.ip();
+        let cc = try!(self.ctx.call_conv().ok_or(ContextError::NoCallConv));
+        let control = match self.summaries.get_mut(name) {
+            Some(summary) => try!(summary.call(&mut self.ctx, &cc)),
+            None => RuneControl::Continue,
+        };
+        if control != RuneControl::Continue {
+            return Ok(control);
+        }
+        self.return_to_caller(name)
+    }
+
+    // Return from the function `name` at the current address, which has not been entered yet.
+    fn return_to_caller(&mut self, name: &str) -> EngineResult<RuneControl> {
+        let cc = try!(self.ctx.call_conv().ok_or(ContextError::NoCallConv));
+        let nargs = self.summaries.nargs(name);
+        let target = try!(cc.pop_return(&mut self.ctx, nargs));
+        match self.ctx.const_value(&target) {
+            Some(target) => {
+                self.ctx.set_ip(target);
+                Ok(RuneControl::Continue)
+            }
+            None => Ok(self.jump(target)),
+        }
+    }
+
+    // Resolve a write of a non-constant `target` to the program counter. Every feasible target
+    // (up to `jump_target_limit`) becomes a separate state that is handed to the explorer.
+    fn jump(&mut self, target: <Ctx as RegisterRead>::VarRef) -> RuneControl {
+        let limit = self.config.jump_target_limit;
+        let word_size = self.word_size();
+        let mut values = self.feasible_values(target.clone(), limit + 1);
+
+        if values.len() > limit {
+            values.truncate(limit);
+            let addr = self.inst_addr;
+            self.ctx.record_event(PathEvent::ControlFlowHijack {
+                addr: addr,
+                targets: values.clone(),
+            });
+        }
+
+        match values.len() {
+            0 => RuneControl::TerminatePath,
+            // The only feasible value is already implied by the current constraints.
+            1 => {
+                self.ctx.set_ip(values[0]);
+                RuneControl::Continue
+            }
+            _ => {
+                let mut states = Vec::new();
+                for value in values {
+                    let mut state = self.ctx.clone();
+                    let value_ = state.define_const(value, word_size);
+                    state.eval(core::OpCodes::Cmp, vec![target.clone(), value_]);
+                    state.set_ip(value);
+                    states.push(state);
+                }
+                self.explorer.register_targets(&mut self.ctx, states)
+            }
+        }
+    }
+
+    // Ask the solver for up to `limit` distinct values that `expr` can take in the current
+    // context.
+    fn feasible_values(&self, expr: <Ctx as RegisterRead>::VarRef, limit: usize) -> Vec<u64> {
+        let mut ctx = self.ctx.clone();
+        let mut values = Vec::new();
+        let word_size = self.word_size();
+        // Only free variables are part of the model returned by the solver.
+        let var = ctx.define_var("rune_value", word_size);
+        ctx.eval(core::OpCodes::Cmp, vec![var.clone(), expr]);
+
+        while values.len() < limit {
+            let mut z3: z3::Z3 = Default::default();
+            if !ctx.check_sat(&mut z3) {
+                break;
+            }
+            let mut z3: z3::Z3 = Default::default();
+            let value = match ctx.try_solve(&mut z3) {
+                Ok(ref model) if model.contains_key(&var) => model[&var],
+                _ => break,
+            };
+            values.push(value);
+            // Block this value and look for the next one.
+            let value_ = ctx.define_const(value, word_size);
+            let eq = ctx.eval(core::OpCodes::Cmp, vec![var.clone(), value_]);
+            ctx.eval(core::OpCodes::Not, vec![eq]);
+        }
+        values
+    }
+}
+
+impl<Ctx, Exp, S> Engine for Rune<Ctx, Exp, S>
+where Ctx: Context<IFn=qf_abv::QF_ABV_Fn>,
+      Exp: PathExplorer<C = RuneControl, Ctx = Ctx>,
+      S: InstructionStream<Output = LOpInfo, Index = u64>
+{
+    fn step(&mut self) -> EngineResult<RuneControl> {
+        // Request for a new state from queue until there is an instruction, or a function with
+        // a summary, to execute.
+        let code = loop {
+            let ip = self.ctx.ip();
+            if self.ctx.entry_return() == Some(ip) {
+                return Ok(self.entry_returned());
+            }
+            if let Some(name) = self.summaries.target(ip) {
+                break Code::Summary(name.to_owned());
+            }
+            if let Some(opinfo) = self.stream.at(ip) {
+                break Code::Inst(opinfo);
+            }
+            if !self.next_path() {
+                return Ok(RuneControl::Halt);
+            }
+        };
+
+        // Breakpoints are checked before the instruction is executed. When resuming from a
+        // break, the instruction at the current address is executed without checking again.
+        if self.resume {
+            self.resume = false;
+        } else if self.breakpoints.check(&self.ctx).is_some() {
+            self.resume = true;
+            return Ok(RuneControl::Break);
+        }
+
+        let addr = self.ctx.ip();
+        let result = match self.hooks.before_instruction(&mut self.ctx) {
+            RuneControl::Continue => {
+                match code {
+                    Code::Inst(ref opinfo) => self.execute(opinfo),
+                    Code::Summary(ref name) => self.summarize(name),
+                }
+            }
+            // Hooks that skip an instruction without redirecting control flow continue at the
+            // next instruction. Skipping a function with a summary returns to the caller.
+            RuneControl::Skip if self.ctx.ip() == addr => {
+                match code {
+                    Code::Inst(ref opinfo) => {
+                        self.ctx.increment_ip(opinfo.size.unwrap_or(0));
+                        Ok(RuneControl::Continue)
+                    }
+                    Code::Summary(ref name) => self.return_to_caller(name),
+                }
+            }
+            RuneControl::Skip => Ok(RuneControl::Continue),
+            c => Ok(c),
+        };
+        let mut control = match result {
+            Ok(control) => control,
+            // Errors terminate the path they occurred on, unless configured otherwise.
+            Err(e) => {
+                if self.config.abort_on_error {
+                    return Err(e);
                 }
+                self.errors.push(e);
+                RuneControl::TerminatePath
             }
+        };
+
+        if control == RuneControl::Continue {
+            control = self.hooks.after_instruction(&mut self.ctx, addr);
+        }
 
-            match self.explorer.next(&mut self.ctx) {
-                RuneControl::Continue => {}
-                _ => unimplemented!(),
+        if control == RuneControl::Continue {
+            control = self.explorer.next(&mut self.ctx);
+        }
+
+        match control {
+            RuneControl::TerminatePath => {
+                if self.next_path() {
+                    Ok(RuneControl::TerminatePath)
+                } else {
+                    Ok(RuneControl::Halt)
+                }
             }
+            RuneControl::Halt | RuneControl::Break => Ok(control),
+            _ => Ok(RuneControl::Continue),
+        }
+    }
 
+    fn run_until(&mut self, addr: u64) -> EngineResult<RuneControl> {
+        loop {
+            match try!(self.step()) {
+                RuneControl::Continue | RuneControl::TerminatePath => {
+                    if self.ctx.ip() == addr {
+                        return Ok(RuneControl::Break);
+                    }
+                }
+                c => return Ok(c),
+            }
         }
+    }
+}
+
+// Build the condition that an access of `n` bytes at `addr` starts within the span of `alloc`,
+// and either ends beyond its size or `alloc` has been freed.
+fn heap_violation<Ctx>(ctx: &mut Ctx,
+                       addr: <Ctx as RegisterRead>::VarRef,
+                       n: u64,
+                       alloc: &Allocation<<Ctx as RegisterRead>::VarRef>,
+                       word_size: usize)
+                       -> <Ctx as RegisterRead>::VarRef
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let base = ctx.define_const(alloc.base, word_size);
+    let span = ctx.define_const(alloc.span, word_size);
+    let offset = ctx.eval(bitvec::OpCodes::BvSub, vec![addr, base]);
+    let in_span = ctx.eval(bitvec::OpCodes::BvULt, vec![offset.clone(), span]);
+    if !alloc.live {
+        return in_span;
+    }
+    let n = ctx.define_const(n, word_size);
+    let end = ctx.eval(bitvec::OpCodes::BvAdd, vec![offset, n]);
+    let beyond = ctx.eval(bitvec::OpCodes::BvUGt, vec![end, alloc.size.clone()]);
+    ctx.eval(core::OpCodes::And, vec![in_span, beyond])
+}
+
+// Build the condition that the `n` bytes at `addr` lie within one of `regions`. Returns `None` if
+// there are no regions.
+fn in_regions<Ctx>(ctx: &mut Ctx,
+                   addr: <Ctx as RegisterRead>::VarRef,
+                   n: u64,
+                   regions: &[MemRegion],
+                   word_size: usize)
+                   -> Option<<Ctx as RegisterRead>::VarRef>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let mut cond = None;
+    for region in regions {
+        let lo = ctx.define_const(region.base, word_size);
+        let hi = ctx.define_const(region.base.wrapping_add(region.size - n), word_size);
+        let above = ctx.eval(bitvec::OpCodes::BvUGe, vec![addr.clone(), lo]);
+        let below = ctx.eval(bitvec::OpCodes::BvULe, vec![addr.clone(), hi]);
+        let inside = ctx.eval(core::OpCodes::And, vec![above, below]);
+        cond = Some(match cond {
+            Some(cond) => ctx.eval(core::OpCodes::Or, vec![cond, inside]),
+            None => inside,
+        });
+    }
+    cond
+}
 
-        Ok(())
+// True if the branches of `esil` are decided in place instead of by the explorer: those of a loop
+// (`GOTO`), and those of a conditional followed by more esil. The explorer saves the other side
+// of a branch as a path that resumes at the next instruction, which would skip the rest of the
+// esil. Instead, the instruction is executed again for the other side (see `Rune::fork`).
+fn needs_replay(esil: &str) -> bool {
+    let words = esil.split(',').map(|w| w.trim()).filter(|w| !w.is_empty()).collect::<Vec<_>>();
+    if words.contains(&"GOTO") {
+        return true;
+    }
+    let mut depth = 0;
+    for (i, word) in words.iter().enumerate() {
+        match *word {
+            "?{" => depth += 1,
+            "}" if depth > 0 => {
+                depth -= 1;
+                if depth == 0 && i + 1 < words.len() {
+                    return true;
+                }
+            }
+            _ => {}
+        }
     }
+    false
+}
+
+// False for esil that radare2 emits for instructions it cannot lift.
+fn is_supported(esil: &str) -> bool {
+    !esil.trim().is_empty() && !esil.split(',').any(|w| w.trim() == "TODO")
+}
+
+// Evaluate operators on constants without the solver, so that addresses computed from constants
+// (`rip,0x10,+`) are known to the context. This is needed to load the pages of a memory image.
+fn fold(token: &Token, l: u64, r: u64, width: usize) -> Option<u64> {
+    let value = match *token {
+        Token::EAdd => l.wrapping_add(r),
+        Token::ESub => l.wrapping_sub(r),
+        Token::EMul => l.wrapping_mul(r),
+        Token::EAnd => l & r,
+        Token::EOr => l | r,
+        Token::EXor => l ^ r,
+        _ => return None,
+    };
+    Some(if width >= 64 { value } else { value & ((1 << width) - 1) })
 }
 
 trait ToSMTFn {
-    fn to_smt(&self) -> qf_abv::QF_ABV_Fn;
+    fn to_smt(&self, signed: bool) -> Option<qf_abv::QF_ABV_Fn>;
 }
 
-// Implement Into<Qf_Abv_Fn> for tokens in order to use them with RuneContext.
+// Map esil operators that correspond directly to a single SMT function. Operators that need more
+// than one SMT function (`!`, rotates, comparisons) are lowered in `Rune::process_op`. Compound
+// operators (`+=`, `--=`, `++`, ...) are expanded into these by the esil lexer. Signed operators
+// (`>>>>`, `~/`, `~<`, ...) reach rune as their unsigned tokens, see `esil_signed`.
 impl ToSMTFn for Token {
-    fn to_smt(&self) -> qf_abv::QF_ABV_Fn {
-        match *self {
+    fn to_smt(&self, signed: bool) -> Option<qf_abv::QF_ABV_Fn> {
+        let smt_fn = match *self {
+            Token::ELt if signed => bitvec::OpCodes::BvSLt.into(),
+            Token::EGt if signed => bitvec::OpCodes::BvSGt.into(),
+            Token::ELsr if signed => bitvec::OpCodes::BvAShr.into(),
+            Token::EDiv if signed => bitvec::OpCodes::BvSDiv.into(),
+            Token::EMod if signed => bitvec::OpCodes::BvSRem.into(),
             Token::ECmp => core::OpCodes::Cmp.into(),
             Token::ELt => bitvec::OpCodes::BvULt.into(),
             Token::EGt => bitvec::OpCodes::BvUGt.into(),
@@ -278,16 +1544,992 @@ impl ToSMTFn for Token {
             Token::ELsr => bitvec::OpCodes::BvLShr.into(),
             Token::EAnd => bitvec::OpCodes::BvAnd.into(),
             Token::EOr => bitvec::OpCodes::BvOr.into(),
-            Token::ENeg => bitvec::OpCodes::BvNeg.into(),
             Token::EMul => bitvec::OpCodes::BvMul.into(),
             Token::EXor => bitvec::OpCodes::BvXor.into(),
             Token::EAdd => bitvec::OpCodes::BvAdd.into(),
             Token::ESub => bitvec::OpCodes::BvSub.into(),
             Token::EDiv => bitvec::OpCodes::BvUDiv.into(),
             Token::EMod => bitvec::OpCodes::BvURem.into(),
-            Token::ERor => unimplemented!(),
-            Token::ERol => unimplemented!(),
-            _ => panic!("This opcode is either unimplemented or is not an opcode at all!"),
+            _ => return None,
+        };
+        Some(smt_fn)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use r2pipe::structs::LOpInfo;
+    use libsmt::backends::z3;
+    use libsmt::theories::core;
+
+    use context::arch::ArchInfo;
+    use context::callconv::CallConv;
+    use context::frame::{StackFrame, RETURN_SENTINEL};
+    use context::heap::HEAP_BASE;
+    use context::context::{Context, ContextAPI, Evaluate, RegisterRead};
+    use context::image::MemImage;
+    use context::rune_ctx::RuneContext;
+    use context::rune_ctx::test::value_is;
+    use context::sys::SysState;
+    use engine::engine::Engine;
+    use engine::summary::MAX_MEM_SIZE;
+    use explorer::dfs::DFSExplorer;
+    use explorer::explorer::PathExplorer;
+    use stream::{FileStream, InstructionStream};
+
+    const ESIL_OPS: &'static str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                           "/tests/fixtures/esil_ops.json");
+    const ESIL_ARCH: &'static str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                            "/tests/fixtures/esil_arch.json");
+    const ESIL_LOOPS: &'static str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                             "/tests/fixtures/esil_loops.json");
+    const ESIL_STEP: &'static str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                            "/tests/fixtures/esil_step.json");
+    const ESIL_UNSUPPORTED: &'static str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                                   "/tests/fixtures/esil_unsupported.json");
+    const ESIL_SYMADDR: &'static str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                               "/tests/fixtures/esil_symaddr.json");
+    const ESIL_JUMP: &'static str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                            "/tests/fixtures/esil_jump.json");
+    const ESIL_REGIONS: &'static str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                               "/tests/fixtures/esil_regions.json");
+    const ESIL_IMAGE: &'static str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                             "/tests/fixtures/esil_image.json");
+    const ESIL_SYSCALL: &'static str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                               "/tests/fixtures/esil_syscall.json");
+    const ESIL_ERRORS: &'static str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                              "/tests/fixtures/esil_errors.json");
+    const ESIL_SUMMARY: &'static str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                               "/tests/fixtures/esil_summary.json");
+    const ESIL_FRAME: &'static str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                             "/tests/fixtures/esil_frame.json");
+    const ESIL_HEAP: &'static str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                            "/tests/fixtures/esil_heap.json");
+
+    // Initial values of the operands.
+    const RAX: u64 = 0xf00000000000000f;
+    const RBX: u64 = 4;
+
+    // (operator, address of the instruction exercising it in `ESIL_OPS`, expected value of rcx)
+    const OPS: &'static [(&'static str, u64, u64)] = &[
+        ("+", 0x1000, 0xf000000000000013),
+        ("-", 0x1010, 0xf00000000000000b),
+        ("*", 0x1020, 0xc00000000000003c),
+        ("/", 0x1030, 0x3c00000000000003),
+        ("%", 0x1040, 0x3),
+        ("&", 0x1050, 0x4),
+        ("|", 0x1060, 0xf00000000000000f),
+        ("^", 0x1070, 0xf00000000000000b),
+        ("<<", 0x1080, 0xf0),
+        (">>", 0x1090, 0xf00000000000000),
+        ("<<<", 0x10a0, 0xff),
+        (">>>", 0x10b0, 0xff00000000000000),
+        ("<", 0x10c0, 0x0),
+        (">", 0x10d0, 0x1),
+        ("!", 0x10e0, 0x0),
+        ("+=", 0x10f0, 0xf000000000000013),
+        ("-=", 0x1100, 0xf00000000000000b),
+        ("*=", 0x1110, 0xc00000000000003c),
+        ("&=", 0x1120, 0x4),
+        ("|=", 0x1130, 0xf00000000000000f),
+        ("^=", 0x1140, 0xf00000000000000b),
+        ("<<=", 0x1150, 0xf0),
+        (">>=", 0x1160, 0xf00000000000000),
+        ("++=", 0x1170, 0xf000000000000010),
+        ("--=", 0x1180, 0xf00000000000000e),
+        ("++", 0x1190, 0xf000000000000010),
+        ("--", 0x11a0, 0xf00000000000000e),
+        ("=[8]", 0x11b0, 0xf00000000000000f),
+        (">>>>", 0x1280, 0xff00000000000000),
+        (">>>>=", 0x1290, 0xff00000000000000),
+        ("~/", 0x12a0, 0xfc00000000000004),
+        ("~%", 0x12b0, 0xffffffffffffffff),
+        ("~<", 0x12c0, 0x1),
+        ("~>", 0x12d0, 0x0),
+    ];
+
+    // (flag, address of the instruction reading it in `ESIL_OPS`, expected value)
+    const FLAGS: &'static [(&'static str, u64, u64)] = &[
+        ("$z", 0x11c0, 0),
+        ("$z", 0x11d0, 1),
+        ("$b64", 0x11e0, 1),
+        ("$c63", 0x11f0, 1),
+        ("$s", 0x1200, 1),
+        ("$o", 0x1210, 0),
+        ("$p", 0x1220, 0),
+    ];
+
+    // (description, address of the instruction in `ESIL_OPS`, expected value of rcx)
+    const WIDTHS: &'static [(&'static str, u64, u64)] = &[
+        ("8 bit add wraps around", 0x1230, 0x0),
+        ("zero flag of an 8 bit add", 0x1240, 0x1),
+        ("32 bit operands", 0x1250, 0x13),
+        ("32 bit write clears the upper half", 0x1260, 0xe),
+        ("64 bit value truncated to 16 bits", 0x1270, 0x000f),
+        ("8 bit value sign extended", 0x12e0, 0xfffffffffffffff0),
+        ("16 bit value sign extended", 0x12f0, 0xffffffffffff8000),
+        ("8 bit operand of a signed comparison", 0x1300, 0x1),
+    ];
+
+    // (architecture, address of the instruction in `ESIL_ARCH`, initial registers, register to
+    // check, expected value)
+    type ArchCase = (fn() -> ArchInfo, u64, &'static [(&'static str, u64)], &'static str, u64);
+
+    const ARCHS: &'static [ArchCase] = &[
+        (ArchInfo::x86, 0x1000, &[("eax", 0x11223344), ("esp", 0x2000)], "ecx", 0x11223344),
+        (ArchInfo::arm, 0x1010, &[], "r0", 0x1014),
+        (ArchInfo::aarch64,
+         0x1020,
+         &[("x0", 0xffffffff), ("x1", 1), ("x2", 0xffffffffffffffff)],
+         "x2",
+         0),
+        (ArchInfo::mips, 0x1030, &[("t0", 0x11223344)], "t1", 0x11),
+        (ArchInfo::thumb, 0x1040, &[], "r0", 0x1040),
+    ];
+
+    type TestRune = Rune<RuneContext, DFSExplorer<RuneContext>, FileStream<u64, LOpInfo>>;
+
+    // Rune exploring `fixture` from `ctx`.
+    fn rune_for(ctx: RuneContext, fixture: &str) -> TestRune {
+        let mut stream: FileStream<u64, LOpInfo> = FileStream::new();
+        stream.load(fixture);
+        Rune::new(ctx, DFSExplorer::new(), stream)
+    }
+
+    // Explore all paths through `fixture` from `ctx`, after `setup` has configured rune.
+    fn run_with<F>(ctx: RuneContext, fixture: &str, setup: F) -> TestRune
+        where F: FnOnce(&mut TestRune)
+    {
+        let mut rune = rune_for(ctx, fixture);
+        setup(&mut rune);
+        assert_eq!(rune.run(), Ok(RuneControl::Halt));
+        rune
+    }
+
+    // Execute the single instruction at `addr` in `fixture` and return the resulting context.
+    fn run_at(fixture: &str, addr: u64) -> RuneContext {
+        run_regs(ArchInfo::x86_64(), fixture, addr, &[("rax", RAX), ("rbx", RBX)])
+    }
+
+    fn run_regs(arch: ArchInfo, fixture: &str, addr: u64, regs: &[(&str, u64)]) -> RuneContext {
+        let mut builder = RuneContext::builder().arch(arch).ip(addr);
+        for &(reg, v) in regs {
+            builder = builder.const_reg(reg, v);
+        }
+        let ctx = builder.build().expect("Invalid context");
+        run_with(ctx, fixture, |_| {}).ctx().clone()
+    }
+
+    // Returns true if `reg` can only hold `expected` in `ctx`.
+    fn reg_is(ctx: &RuneContext, reg: &str, expected: u64) -> bool {
+        let val = ctx.clone().reg_read(reg);
+        let width = ctx.reg_width(reg).unwrap();
+        value_is(ctx, val, width, expected)
+    }
+
+    // A value `reg` can hold in `ctx`.
+    fn reg_value(ctx: &RuneContext, reg: &str) -> u64 {
+        let mut ctx = ctx.clone();
+        let val = ctx.reg_read(reg);
+        let width = ctx.reg_width(reg).unwrap();
+        let var = ctx.define_var("test_value", width);
+        ctx.eval(core::OpCodes::Cmp, vec![var, val]);
+        let mut z3: z3::Z3 = Default::default();
+        ctx.solve(&mut z3)[&var]
+    }
+
+    #[test]
+    fn esil_op_conformance() {
+        for &(op, addr, expected) in OPS {
+            let ctx = run_at(ESIL_OPS, addr);
+            assert!(reg_is(&ctx, "rcx", expected),
+                    "esil operator `{}` did not produce {:#x}",
+                    op,
+                    expected);
+        }
+    }
+
+    #[test]
+    fn esil_flag_conformance() {
+        for &(flag, addr, expected) in FLAGS {
+            let ctx = run_at(ESIL_OPS, addr);
+            assert!(reg_is(&ctx, "rcx", expected),
+                    "esil flag `{}` did not evaluate to {}",
+                    flag,
+                    expected);
+        }
+    }
+
+    #[test]
+    fn esil_width_inference() {
+        for &(desc, addr, expected) in WIDTHS {
+            let ctx = run_at(ESIL_OPS, addr);
+            assert!(reg_is(&ctx, "rcx", expected), "{}: expected {:#x}", desc, expected);
+        }
+    }
+
+    #[test]
+    fn esil_arch_support() {
+        for &(arch, addr, regs, reg, expected) in ARCHS {
+            let arch = arch();
+            let ctx = run_regs(arch, ESIL_ARCH, addr, regs);
+            assert!(reg_is(&ctx, reg, expected),
+                    "{:?}: expected {} to be {:#x}",
+                    arch,
+                    reg,
+                    expected);
+        }
+    }
+
+    // Final states of the paths through `ESIL_LOOPS` from `ctx`, all of which end at `end`.
+    fn run_loops(ctx: RuneContext, end: u64, unroll_bound: usize) -> Vec<RuneContext> {
+        let mut rune = rune_for(ctx, ESIL_LOOPS);
+        rune.config_mut().unroll_bound = unroll_bound;
+        let mut states = Vec::new();
+        while rune.step().unwrap() != RuneControl::Halt {
+            if rune.ctx().ip() == end {
+                states.push(rune.ctx().clone());
+            }
+        }
+        assert!(rune.errors().is_empty());
+        states
+    }
+
+    // Returns true if the bytes at `addr` can only be `expected` in `ctx`.
+    fn mem_is(ctx: &RuneContext, addr: u64, expected: &[u8]) -> bool {
+        let mut ctx = ctx.clone();
+        expected.iter().enumerate().all(|(i, &byte)| {
+            let addr = ctx.define_const(addr + i as u64, 64);
+            let val = ctx.mem_read(addr, 8);
+            value_is(&ctx, val, 8, byte as u64)
+        })
+    }
+
+    const QWORD: u64 = 0x1122334455667788;
+    const QWORD_BYTES: [u8; 8] = [0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11];
+
+    fn loop_ctx(ip: u64, rcx: Option<u64>) -> RuneContext {
+        let builder = RuneContext::builder()
+                          .ip(ip)
+                          .const_reg("rax", QWORD)
+                          .const_reg("rsi", 0x5000)
+                          .const_reg("rdi", 0x4000)
+                          .const_reg("df", 0)
+                          .mem_image(0x5000, b"hello");
+        let builder = match rcx {
+            Some(rcx) => builder.const_reg("rcx", rcx),
+            None => builder.sym_reg("rcx"),
+        };
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn esil_loops_concrete() {
+        // Loops over concrete counters run to completion, whatever the unroll bound.
+        let states = run_loops(loop_ctx(0x1000, Some(4)), 0x1005, 2);
+        assert_eq!(states.len(), 1);
+        assert!(mem_is(&states[0], 0x4000, &[0x88; 4]));
+        assert!(reg_is(&states[0], "rdi", 0x4004));
+        assert!(reg_is(&states[0], "rcx", 0));
+        assert!(reg_is(&states[0], "rdx", 1));
+
+        let states = run_loops(loop_ctx(0x2000, Some(5)), 0x2005, 2);
+        assert_eq!(states.len(), 1);
+        assert!(mem_is(&states[0], 0x4000, b"hello"));
+        assert!(reg_is(&states[0], "rsi", 0x5005));
+        assert!(reg_is(&states[0], "rdi", 0x4005));
+
+        let states = run_loops(loop_ctx(0x3000, Some(3)), 0x3006, 2);
+        assert_eq!(states.len(), 1);
+        for i in 0..3 {
+            assert!(mem_is(&states[0], 0x4000 + 8 * i, &QWORD_BYTES));
+        }
+        assert!(reg_is(&states[0], "rdi", 0x4018));
+
+        // A counter of zero does not execute the body.
+        let states = run_loops(loop_ctx(0x3000, Some(0)), 0x3006, 2);
+        assert_eq!(states.len(), 1);
+        assert!(reg_is(&states[0], "rdi", 0x4000));
+    }
+
+    #[test]
+    fn esil_goto_keeps_stack() {
+        // `7,rcx,1,+,7,GOTO,8,rbx,=,rdi,=` jumps over the 8, and assigns the values pushed
+        // before the jump.
+        let states = run_loops(loop_ctx(0x4000, Some(4)), 0x4002, 2);
+        assert_eq!(states.len(), 1);
+        assert!(reg_is(&states[0], "rbx", 5));
+        assert!(reg_is(&states[0], "rdi", 7));
+        assert!(reg_is(&states[0], "rdx", 1));
+    }
+
+    #[test]
+    fn esil_loops_symbolic() {
+        // Every number of iterations below the unroll bound is a path of its own, on which the
+        // rest of the esil has been executed.
+        for &bound in &[1, 3] {
+            let states = run_loops(loop_ctx(0x3000, None), 0x3006, bound);
+            assert_eq!(states.len(), bound);
+            let mut rdi = states.iter().map(|ctx| reg_value(ctx, "rdi")).collect::<Vec<_>>();
+            rdi.sort();
+            assert_eq!(rdi, (0..bound as u64).map(|i| 0x4000 + 8 * i).collect::<Vec<_>>());
+            for ctx in &states {
+                let n = (reg_value(ctx, "rdi") - 0x4000) / 8;
+                for i in 0..n {
+                    assert!(mem_is(ctx, 0x4000 + 8 * i, &QWORD_BYTES));
+                }
+                assert!(reg_is(ctx, "rcx", 0));
+                assert!(reg_is(ctx, "rdx", 1));
+            }
+        }
+
+        let states = run_loops(loop_ctx(0x2000, None), 0x2005, 4);
+        let mut copied = Vec::new();
+        for ctx in &states {
+            let n = reg_value(ctx, "rdi") - 0x4000;
+            assert!(reg_is(ctx, "rsi", 0x5000 + n));
+            assert!(mem_is(ctx, 0x4000, &b"hello"[..n as usize]));
+            copied.push(n);
+        }
+        copied.sort();
+        assert_eq!(copied, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn errors_terminate_path() {
+        let ctx = RuneContext::builder().ip(0x1000).const_reg("rax", 0).build().unwrap();
+        let mut stream: FileStream<u64, LOpInfo> = FileStream::new();
+        stream.load(ESIL_ERRORS);
+        let mut rune = Rune::new(ctx, DFSExplorer::new(), stream);
+
+        assert_eq!(rune.run(), Ok(RuneControl::Halt));
+        assert_eq!(rune.errors().len(), 1);
+        match rune.errors()[0] {
+            EngineError::Esil { addr: 0x1003, ref cause, .. } => {
+                assert_eq!(**cause,
+                           EngineError::Context(ContextError::UnknownRegister("foo".to_owned())));
+            }
+            ref e => panic!("Unexpected error: {:?}", e),
+        }
+        assert!(reg_is(rune.ctx(), "rax", 1));
+    }
+
+    #[test]
+    fn errors_abort() {
+        let ctx = RuneContext::builder().ip(0x1000).const_reg("rax", 0).build().unwrap();
+        let mut rune = rune_for(ctx, ESIL_ERRORS);
+        rune.config_mut().abort_on_error = true;
+
+        assert!(rune.run().is_err());
+    }
+
+    #[test]
+    fn step_and_run_until() {
+        let ctx = RuneContext::builder().ip(0x1000).const_reg("rax", 0).build().unwrap();
+        let mut rune = rune_for(ctx, ESIL_STEP);
+
+        assert_eq!(rune.step(), Ok(RuneControl::Continue));
+        assert_eq!(rune.ctx().ip(), 0x1003);
+        assert!(reg_is(rune.ctx(), "rax", 1));
+
+        assert_eq!(rune.run_until(0x1009), Ok(RuneControl::Break));
+        assert!(reg_is(rune.ctx(), "rax", 3));
+
+        assert_eq!(rune.run_for(1), Ok(RuneControl::Continue));
+        assert!(reg_is(rune.ctx(), "rax", 4));
+        assert_eq!(rune.run_for(8), Ok(RuneControl::Halt));
+    }
+
+    #[test]
+    fn run_until_current_addr() {
+        // The instruction at the current address is executed, and 0x1000 is not reached again.
+        let ctx = RuneContext::builder().ip(0x1000).const_reg("rax", 0).build().unwrap();
+        let mut rune = rune_for(ctx, ESIL_STEP);
+        assert_eq!(rune.run_until(0x1000), Ok(RuneControl::Halt));
+        assert!(reg_is(rune.ctx(), "rax", 4));
+    }
+
+    #[test]
+    fn run_until_across_paths() {
+        // Every path forked by `jmp rax` stops at 0x1300, including those loaded after the
+        // previous one ended.
+        let ctx = RuneContext::builder().ip(0x1000).sym_reg("rax").build().unwrap();
+        let mut rune = rune_for(ctx, ESIL_JUMP);
+        let mut values = Vec::new();
+        while rune.run_until(0x1300) == Ok(RuneControl::Break) {
+            assert_eq!(rune.ctx().ip(), 0x1300);
+            values.push(reg_value(rune.ctx(), "rdx"));
+        }
+        values.sort();
+        assert_eq!(values, vec![0x1100, 0x1110, 0x1120, 0x1130]);
+        assert!(rune.errors().is_empty());
+    }
+
+    #[test]
+    fn eval_esil_expression() {
+        let ctx = RuneContext::builder()
+                      .const_reg("rax", 2)
+                      .const_reg("rbx", 3)
+                      .build()
+                      .unwrap();
+        let stream: FileStream<u64, LOpInfo> = FileStream::new();
+        let mut rune = Rune::new(ctx, DFSExplorer::new(), stream);
+
+        assert!(rune.eval_esil("rax,rbx,+,rcx,=").unwrap().is_empty());
+        assert!(reg_is(rune.ctx(), "rcx", 5));
+
+        let results = rune.eval_esil("1,rcx,+").unwrap();
+        assert_eq!(results.len(), 1);
+        let (val, width) = results[0].clone();
+        assert_eq!(width, 64);
+        assert!(value_is(rune.ctx(), val, width, 6));
+
+        assert!(rune.eval_esil("rax,foo,+").is_err());
+    }
+
+    fn run_unsupported(policy: UnsupportedPolicy) -> TestRune {
+        let ctx = RuneContext::builder()
+                      .ip(0x1000)
+                      .const_reg("rax", 5)
+                      .const_reg("rbx", 0)
+                      .build()
+                      .unwrap();
+        run_with(ctx, ESIL_UNSUPPORTED, |rune| rune.config_mut().unsupported = policy)
+    }
+
+    #[test]
+    fn unsupported_policies() {
+        let event = |havoc: &[&str]| {
+            PathEvent::UnsupportedInstruction {
+                addr: 0x1000,
+                opcode: Some("rdrand rax".to_owned()),
+                esil: None,
+                havoc: havoc.iter().map(|r| r.to_string()).collect(),
+            }
+        };
+
+        let rune = run_unsupported(UnsupportedPolicy::Stop);
+        assert_eq!(rune.errors(), &[EngineError::InvalidInstruction(0x1000)]);
+        assert_eq!(rune.ctx().events(), &[event(&[])]);
+        assert!(reg_is(rune.ctx(), "rbx", 0));
+
+        let rune = run_unsupported(UnsupportedPolicy::Skip);
+        assert!(rune.errors().is_empty());
+        assert_eq!(rune.ctx().events(), &[event(&[])]);
+        assert!(reg_is(rune.ctx(), "rax", 5));
+        assert!(reg_is(rune.ctx(), "rbx", 1));
+
+        let rune = run_unsupported(UnsupportedPolicy::Havoc);
+        assert_eq!(rune.ctx().events().len(), 1);
+        let havoc = match rune.ctx().events()[0] {
+            PathEvent::UnsupportedInstruction { ref havoc, .. } => havoc.clone(),
+            ref e => panic!("Unexpected event: {:?}", e),
+        };
+        assert!(havoc.contains(&"rax".to_owned()));
+        assert!(havoc.contains(&"rcx".to_owned()));
+        assert!(!havoc.contains(&"rip".to_owned()));
+        assert!(!havoc.contains(&"rsp".to_owned()));
+        assert!(!havoc.contains(&"eax".to_owned()));
+        assert!(!reg_is(rune.ctx(), "rax", 5));
+        assert!(rune.ctx().is_reg_set("rcx"));
+        assert!(!rune.ctx().is_reg_set("rsp"));
+        assert!(reg_is(rune.ctx(), "rbx", 1));
+    }
+
+    // Values of rdx on every path through `ESIL_SYMADDR`, along with the events of the last
+    // path.
+    fn run_symaddr(strategy: AddrStrategy) -> (Vec<u64>, Vec<PathEvent>) {
+        let ctx = RuneContext::builder()
+                      .ip(0x1000)
+                      .sym_reg("rbx")
+                      .mem_image(0x2000, &[1, 2, 3, 4])
+                      .addr_strategy(strategy)
+                      .build()
+                      .unwrap();
+        let mut rune = rune_for(ctx, ESIL_SYMADDR);
+
+        let mut values = Vec::new();
+        while rune.step().unwrap() != RuneControl::Halt {
+            if rune.ctx().ip() == 0x1008 {
+                values.push(reg_value(rune.ctx(), "rdx"));
+            }
+        }
+        values.sort();
+        (values, rune.ctx().events().to_vec())
+    }
+
+    #[test]
+    fn symbolic_addresses() {
+        let (values, events) = run_symaddr(AddrStrategy::Symbolic);
+        assert_eq!(values.len(), 1);
+        assert!(events.is_empty());
+
+        let (values, events) = run_symaddr(AddrStrategy::Concretize);
+        assert_eq!(values.len(), 1);
+        assert_eq!(events.len(), 1);
+        match events[0] {
+            PathEvent::SymbolicAccess { addr: 0x1000, write: false, ref targets, .. } => {
+                assert_eq!(targets.len(), 1);
+                assert_eq!(values[0], targets[0] - 0x2000 + 1);
+            }
+            ref e => panic!("Unexpected event: {:?}", e),
+        }
+
+        let (values, _) = run_symaddr(AddrStrategy::Fork(4));
+        assert_eq!(values, vec![1, 2, 3, 4]);
+        let (values, _) = run_symaddr(AddrStrategy::Fork(2));
+        assert_eq!(values.len(), 2);
+
+        let (values, events) = run_symaddr(AddrStrategy::Region {
+            start: 0x2002,
+            end: 0x2004,
+        });
+        assert_eq!(values.len(), 1);
+        assert!(values[0] == 3 || values[0] == 4);
+        assert_eq!(events.len(), 1);
+    }
+
+    // Values of rdx, the target of the jump, on every path through `ESIL_JUMP` from `ip`, along
+    // with the events of the last path.
+    fn run_jump(ip: u64, jump_target_limit: usize) -> (Vec<u64>, Vec<PathEvent>) {
+        let ctx = RuneContext::builder()
+                      .ip(ip)
+                      .sym_reg("rax")
+                      .const_reg("rsp", 0x3000)
+                      .build()
+                      .unwrap();
+        let mut rune = rune_for(ctx, ESIL_JUMP);
+        rune.config_mut().jump_target_limit = jump_target_limit;
+
+        let mut values = Vec::new();
+        while rune.step().unwrap() != RuneControl::Halt {
+            if rune.ctx().ip() == 0x1300 {
+                values.push(reg_value(rune.ctx(), "rdx"));
+            }
+        }
+        assert!(rune.errors().is_empty());
+        values.sort();
+        (values, rune.ctx().events().to_vec())
+    }
+
+    #[test]
+    fn symbolic_jumps() {
+        // `jmp rax` and `ret` with four feasible targets.
+        for &(ip, jump) in &[(0x1000, 0x1007), (0x1200, 0x1207)] {
+            let (values, events) = run_jump(ip, 16);
+            assert_eq!(values, vec![0x1100, 0x1110, 0x1120, 0x1130]);
+            assert!(events.is_empty());
+
+            let (values, events) = run_jump(ip, 2);
+            assert_eq!(values.len(), 2);
+            assert_eq!(events.len(), 1);
+            match events[0] {
+                PathEvent::ControlFlowHijack { addr, ref targets } => {
+                    assert_eq!(addr, jump);
+                    let mut targets = targets.clone();
+                    targets.sort();
+                    assert_eq!(targets, values);
+                }
+                ref e => panic!("Unexpected event: {:?}", e),
+            }
+        }
+    }
+
+    fn run_regions(ip: u64) -> RuneContext {
+        let ctx = RuneContext::builder()
+                      .ip(ip)
+                      .sym_reg("rbx")
+                      .region(MemRegion::new(".text", 0x1000, 0x100, "r-x".parse().unwrap()))
+                      .region(MemRegion::new(".data", 0x2000, 0x10, "rw-".parse().unwrap()))
+                      .build()
+                      .unwrap();
+        run_with(ctx, ESIL_REGIONS, |_| {}).ctx().clone()
+    }
+
+    #[test]
+    fn invalid_accesses() {
+        let valid = |addr: u64| {
+            (addr >= 0x1000 && addr < 0x1100) || (addr >= 0x2000 && addr < 0x2010)
+        };
+
+        let ctx = run_regions(0x1000);
+        let events = ctx.events();
+        assert_eq!(events.len(), 2);
+        match events[0] {
+            PathEvent::InvalidAccess { addr, access, target, size, ref model } => {
+                assert_eq!((addr, access, size), (0x1000, Access::Read, 1));
+                assert!(!valid(target));
+                assert_eq!(model, &vec![("rbx".to_owned(), target)]);
+            }
+            ref e => panic!("Unexpected event: {:?}", e),
+        }
+        match events[1] {
+            PathEvent::InvalidAccess { addr, access, target, .. } => {
+                assert_eq!((addr, access, target), (0x1004, Access::Write, 0x1000));
+            }
+            ref e => panic!("Unexpected event: {:?}", e),
+        }
+        // The read was constrained to the valid addresses.
+        assert!(valid(reg_value(&ctx, "rbx")));
+
+        let ctx = run_regions(0x3000);
+        assert_eq!(ctx.events().len(), 1);
+        match ctx.events()[0] {
+            PathEvent::InvalidAccess { access: Access::Exec, target: 0x3000, .. } => {}
+            ref e => panic!("Unexpected event: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn image_reads() {
+        let mut image = MemImage::new();
+        image.add_segment(0x1100, (0..16).collect());
+        let ctx = RuneContext::builder().ip(0x1000).image(image).build().unwrap();
+        let rune = run_with(ctx, ESIL_IMAGE, |_| {});
+
+        // The address is relative to the program counter, and is folded to a constant.
+        assert!(rune.ctx().memory().is_loaded(1));
+        assert_eq!(reg_value(rune.ctx(), "rax"), 0x0b0a090807060504);
+    }
+
+    // Registers other than these are unset, and reading them is an error.
+    fn run_syscalls(ctx: RuneContext, policy: SyscallPolicy) -> TestRune {
+        run_with(ctx, ESIL_SYSCALL, |rune| rune.config_mut().unknown_syscall = policy)
+    }
+
+    // mmap(rdi, rsi, rdx, r10) at 0x6000, in a context with `regions`.
+    fn run_mmap(hint: u64, len: u64, flags: u64, regions: &[MemRegion]) -> TestRune {
+        let mut builder = RuneContext::builder()
+                              .ip(0x6000)
+                              .const_reg("rdi", hint)
+                              .const_reg("rsi", len)
+                              .const_reg("rdx", 3)
+                              .const_reg("r10", flags);
+        for region in regions {
+            builder = builder.region(region.clone());
+        }
+        run_syscalls(builder.build().unwrap(), SyscallPolicy::Stop)
+    }
+
+    #[test]
+    fn syscall_read_exit() {
+        // read(0, 0x3000, 4), then exit with the first byte that was read.
+        let ctx = RuneContext::builder()
+                      .ip(0x1000)
+                      .const_reg("rax", 0)
+                      .const_reg("rdi", 0)
+                      .const_reg("rsi", 0x3000)
+                      .const_reg("rdx", 4)
+                      .build()
+                      .unwrap();
+        let rune = run_syscalls(ctx, SyscallPolicy::Stop);
+        assert!(rune.errors().is_empty());
+        let mut ctx = rune.ctx().clone();
+        assert!(ctx.syms.contains_key("stdin[3]"));
+        assert!(!ctx.syms.contains_key("stdin[4]"));
+        assert_eq!(ctx.sys_state().offsets[&0], 4);
+        assert_eq!(ctx.events(), &[PathEvent::Exit { addr: 0x100d, status: None }]);
+        assert!(!ctx.is_reg_set("rcx"));
+
+        let rdi = ctx.reg_read("rdi");
+        let zeros = ctx.define_const(0, 56);
+        let byte = ctx.syms["stdin[0]"];
+        let byte = ctx.eval(bitvec::OpCodes::Concat, vec![zeros, byte]);
+        let eq = ctx.eval(core::OpCodes::Cmp, vec![rdi, byte]);
+        ctx.eval(core::OpCodes::Not, vec![eq]);
+        let mut z3: z3::Z3 = Default::default();
+        assert!(!ctx.check_sat(&mut z3));
+    }
+
+    #[test]
+    fn syscall_unknown_stop() {
+        let ctx = RuneContext::builder().ip(0x2000).build().unwrap();
+        let rune = run_syscalls(ctx, SyscallPolicy::Stop);
+        assert_eq!(rune.errors().len(), 1);
+        assert_eq!(rune.ctx().events(),
+                   &[PathEvent::UnknownSyscall {
+                         addr: 0x2004,
+                         number: 0x1234,
+                     }]);
+    }
+
+    #[test]
+    fn syscall_unknown_enosys() {
+        let ctx = RuneContext::builder().ip(0x2000).build().unwrap();
+        let rune = run_syscalls(ctx, SyscallPolicy::Enosys);
+        assert!(rune.errors().is_empty());
+        assert_eq!(reg_value(rune.ctx(), "rax"), ENOSYS.wrapping_neg());
+        assert_eq!(reg_value(rune.ctx(), "rcx"), 1);
+    }
+
+    #[test]
+    fn syscall_int3() {
+        // int3 is not a system call.
+        let ctx = RuneContext::builder().ip(0x3000).build().unwrap();
+        let rune = run_syscalls(ctx, SyscallPolicy::Stop);
+        assert_eq!(rune.errors().len(), 1);
+        assert!(rune.ctx().events().is_empty());
+        assert!(!rune.ctx().is_reg_set("rcx"));
+    }
+
+    #[test]
+    fn syscall_mmap() {
+        let rune = run_mmap(0, 0x1800, 0x22, &[]);
+        assert!(rune.errors().is_empty());
+        let base = SysState::default().mmap_base;
+        assert_eq!(reg_value(rune.ctx(), "rax"), base);
+        assert_eq!(rune.ctx().sys_state().mmap_base, base + 0x2000);
+    }
+
+    #[test]
+    fn syscall_mmap_overflow() {
+        // mmap(0, -1, ..) does not fit in the address space.
+        let ctx = RuneContext::builder()
+                      .ip(0x4000)
+                      .const_reg("rdi", 0)
+                      .const_reg("rdx", 3)
+                      .build()
+                      .unwrap();
+        let rune = run_syscalls(ctx, SyscallPolicy::Stop);
+        assert!(rune.errors().is_empty());
+        assert_eq!(reg_value(rune.ctx(), "rax"), 12u64.wrapping_neg());
+        assert_eq!(rune.ctx().sys_state().mmap_base, SysState::default().mmap_base);
+    }
+
+    #[test]
+    fn syscall_mmap_empty() {
+        let rune = run_mmap(0, 0, 0x22, &[]);
+        assert!(rune.errors().is_empty());
+        // EINVAL
+        assert_eq!(reg_value(rune.ctx(), "rax"), 22u64.wrapping_neg());
+        assert_eq!(rune.ctx().sys_state().mmap_base, SysState::default().mmap_base);
+    }
+
+    #[test]
+    fn syscall_mmap_fixed() {
+        let text = MemRegion::new(".text", 0x6000, 0x10, "r-x".parse().unwrap());
+        let data = MemRegion::new(".data", 0x8000, 0x1000, "rw-".parse().unwrap());
+        let rune = run_mmap(0x9000, 0x10, 0x12, &[text, data]);
+        assert!(rune.errors().is_empty());
+        assert!(rune.ctx().events().is_empty());
+        assert_eq!(reg_value(rune.ctx(), "rax"), 0x9000);
+        let mapped = MemRegion::new("[mmap]", 0x9000, 0x1000, "rw-".parse().unwrap());
+        assert_eq!(rune.ctx().regions().last(), Some(&mapped));
+        assert_eq!(rune.ctx().sys_state().mmap_base, SysState::default().mmap_base);
+    }
+
+    #[test]
+    fn syscall_mmap_fixed_overlap() {
+        let text = MemRegion::new(".text", 0x6000, 0x10, "r-x".parse().unwrap());
+        let data = MemRegion::new(".data", 0x8000, 0x1000, "rw-".parse().unwrap());
+        let rune = run_mmap(0x7000, 0x1001, 0x12, &[text, data]);
+        assert!(rune.errors().is_empty());
+        // EEXIST
+        assert_eq!(reg_value(rune.ctx(), "rax"), 17u64.wrapping_neg());
+        assert_eq!(rune.ctx().regions().len(), 2);
+    }
+
+    #[test]
+    fn syscall_brk() {
+        // brk(HEAP_BASE + 0x1000) fails and returns the current break, brk(brk + 0x1000) does
+        // not.
+        let ctx = RuneContext::builder().ip(0x5000).build().unwrap();
+        let rune = run_syscalls(ctx, SyscallPolicy::Stop);
+        assert!(rune.errors().is_empty());
+        let brk = SysState::default().brk;
+        assert_eq!(reg_value(rune.ctx(), "rbx"), brk);
+        assert_eq!(reg_value(rune.ctx(), "rax"), brk + 0x1000);
+        assert_eq!(rune.ctx().sys_state().brk, brk + 0x1000);
+    }
+
+    fn run_summaries<F>(f: F) -> TestRune
+        where F: FnOnce(&mut SummaryManager<RuneContext>)
+    {
+        let ctx = RuneContext::builder()
+                      .ip(0x1000)
+                      .const_reg("rsp", 0x8000)
+                      .const_reg("rdi", 0x2000)
+                      .mem_image(0x2000, b"hello\0")
+                      .build()
+                      .unwrap();
+        run_with(ctx, ESIL_SUMMARY, |rune| {
+            rune.summaries_mut().add_target(0x5000, "strlen");
+            rune.summaries_mut().add_target(0x5010, "memcpy");
+            f(rune.summaries_mut());
+        })
+    }
+
+    // memset(0x3000, 0x41, rdx)
+    fn run_memset(ctx: RuneContext) -> TestRune {
+        run_with(ctx, ESIL_SUMMARY, |rune| rune.summaries_mut().add_target(0x5020, "memset"))
+    }
+
+    #[test]
+    fn summaries() {
+        // strlen(0x2000), then memcpy(0x3000, 0x2000, 6).
+        let rune = run_summaries(|_| {});
+        assert!(rune.errors().is_empty());
+        assert_eq!(reg_value(rune.ctx(), "rbx"), 5);
+        assert_eq!(reg_value(rune.ctx(), "rax"), 0x3000);
+        assert_eq!(reg_value(rune.ctx(), "rsp"), 0x8000);
+        assert_eq!(reg_value(rune.ctx(), "rcx"), 1);
+        let mut ctx = rune.ctx().clone();
+        for (i, byte) in b"hello\0".iter().enumerate() {
+            let addr = ctx.define_const(0x3000 + i as u64, 64);
+            let val = ctx.mem_read(addr, 8);
+            assert!(value_is(&ctx, val, 8, *byte as u64));
+        }
+
+        // Summaries registered by users replace the built-in ones.
+        let rune = run_summaries(|summaries| {
+            summaries.add("strlen",
+                          |ctx: &mut RuneContext, cc: &CallConv| -> EngineResult<RuneControl> {
+                              let ret = ctx.define_const(42, 64);
+                              try!(cc.set_return(ctx, ret));
+                              Ok(RuneControl::Continue)
+                          });
+        });
+        assert!(rune.errors().is_empty());
+        assert_eq!(reg_value(rune.ctx(), "rbx"), 42);
+
+        // Sizes are capped.
+        let builder = || RuneContext::builder().ip(0x1200).const_reg("rsp", 0x8000);
+        let ctx = builder().const_reg("rdx", 0x1000_0000).build().unwrap();
+        let rune = run_memset(ctx);
+        assert!(rune.errors().is_empty());
+        assert!(mem_is(rune.ctx(), 0x3000 + MAX_MEM_SIZE - 1, &[0x41]));
+        assert!(mem_is(rune.ctx(), 0x3000 + MAX_MEM_SIZE, &[0]));
+
+        let ctx = builder().sym_reg("rdx").build().unwrap();
+        let rune = run_memset(ctx);
+        assert!(rune.errors().is_empty());
+        let mut ctx = rune.ctx().clone();
+        let rdx = ctx.reg_read("rdx");
+        let max = ctx.define_const(MAX_MEM_SIZE, 64);
+        ctx.eval(bitvec::OpCodes::BvUGt, vec![rdx, max]);
+        let mut z3: z3::Z3 = Default::default();
+        assert!(!ctx.check_sat(&mut z3));
+    }
+
+    fn run_frame(ctx: RuneContext) -> TestRune {
+        run_with(ctx, ESIL_FRAME, |_| {})
+    }
+
+    #[test]
+    fn entry_frame() {
+        // A function returning its first argument plus one.
+        let frame = StackFrame::for_arch(&ArchInfo::x86_64());
+        let ctx = RuneContext::builder().ip(0x1000).frame(frame).const_arg(0, 41).build().unwrap();
+        let rune = run_frame(ctx);
+        assert!(rune.errors().is_empty());
+        assert_eq!(rune.returned().len(), 1);
+        let ctx = &rune.returned()[0];
+        assert_eq!(ctx.ip(), RETURN_SENTINEL);
+        assert_eq!(ctx.events(), &[PathEvent::Returned { value: Some(42) }]);
+        assert_eq!(reg_value(ctx, "rsp"), frame.sp + 8);
+        assert_eq!(reg_value(ctx, "rbp"), 0);
+
+        let ctx = RuneContext::builder().ip(0x1000).frame(frame).sym_arg(0, 32).build().unwrap();
+        let rune = run_frame(ctx);
+        assert_eq!(rune.returned().len(), 1);
+        let mut ctx = rune.returned()[0].clone();
+        assert_eq!(ctx.events(), &[PathEvent::Returned { value: None }]);
+        let rax = ctx.return_value().unwrap();
+        let rdi = ctx.reg_read("rdi");
+        let diff = ctx.eval(bitvec::OpCodes::BvSub, vec![rax, rdi]);
+        assert!(value_is(&ctx, diff, 64, 1));
+    }
+
+    fn run_heap(ctx: RuneContext) -> TestRune {
+        let rune = run_with(ctx, ESIL_HEAP, |rune| {
+            rune.summaries_mut().add_target(0x5000, "malloc");
+            rune.summaries_mut().add_target(0x5010, "free");
+        });
+        assert!(rune.errors().is_empty());
+        rune
+    }
+
+    #[test]
+    fn heap_out_of_bounds() {
+        // p = malloc(16); p[16] = 1;
+        let ctx = RuneContext::builder().ip(0x1000).const_reg("rsp", 0x8000).build().unwrap();
+        let rune = run_heap(ctx);
+        assert_eq!(rune.ctx().events(),
+                   &[PathEvent::HeapOutOfBounds {
+                         addr: 0x100b,
+                         write: true,
+                         target: HEAP_BASE + 16,
+                         size: 1,
+                         base: HEAP_BASE,
+                         model: Vec::new(),
+                     }]);
+        assert!(!rune.ctx().is_reg_set("rdx"));
+    }
+
+    #[test]
+    fn heap_use_after_free() {
+        // p = malloc(16); free(p); p[0];
+        let ctx = RuneContext::builder().ip(0x2000).const_reg("rsp", 0x8000).build().unwrap();
+        let rune = run_heap(ctx);
+        assert_eq!(rune.ctx().events(),
+                   &[PathEvent::UseAfterFree {
+                         addr: 0x2013,
+                         write: false,
+                         target: HEAP_BASE,
+                         size: 1,
+                         base: HEAP_BASE,
+                         model: Vec::new(),
+                     }]);
+    }
+
+    #[test]
+    fn heap_double_free() {
+        // p = malloc(16); free(p); free(p);
+        let ctx = RuneContext::builder().ip(0x3000).const_reg("rsp", 0x8000).build().unwrap();
+        let rune = run_heap(ctx);
+        assert_eq!(rune.ctx().events(),
+                   &[PathEvent::DoubleFree {
+                         addr: 0x5010,
+                         ptr: HEAP_BASE,
+                         model: Vec::new(),
+                     }]);
+    }
+
+    #[test]
+    fn heap_invalid_free() {
+        // free(0x1234);
+        let ctx = RuneContext::builder().ip(0x4000).const_reg("rsp", 0x8000).build().unwrap();
+        let rune = run_heap(ctx);
+        assert_eq!(rune.ctx().events(),
+                   &[PathEvent::InvalidFree {
+                         addr: 0x5010,
+                         ptr: 0x1234,
+                         model: Vec::new(),
+                     }]);
+        assert!(!rune.ctx().is_reg_set("rdx"));
+    }
+
+    #[test]
+    fn heap_symbolic_size() {
+        // p = malloc(n); p[15] = 1; is out of bounds for n < 16 only.
+        let ctx = RuneContext::builder()
+                      .ip(0x6000)
+                      .const_reg("rsp", 0x8000)
+                      .sym_reg("rdi")
+                      .build()
+                      .unwrap();
+        let rune = run_heap(ctx);
+        assert_eq!(rune.ctx().events().len(), 1);
+        match rune.ctx().events()[0] {
+            PathEvent::HeapOutOfBounds { ref model, .. } => {
+                assert_eq!(model.len(), 1);
+                assert_eq!(model[0].0, "rdi");
+                assert!(model[0].1 < 16);
+            }
+            ref e => panic!("Expected an out of bounds access, got {:?}", e),
         }
+        assert_eq!(reg_value(rune.ctx(), "rdx"), 1);
     }
 }
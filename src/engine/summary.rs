@@ -0,0 +1,429 @@
+//! Function summaries.
+//!
+//! A summary stands in for the code of a function, usually a library function called through the
+//! PLT. When the program counter reaches the address of a function with a summary, rune runs the
+//! summary instead of the code at the address and returns to the caller. Summaries read their
+//! arguments and set their return value through the `CallConv` of the context.
+//!
+//! Summaries are registered by name with the `SummaryManager` of the engine, which starts out with
+//! the summaries of `Libc`. The addresses of the functions are added separately, for example from
+//! the imports radare2 reports for a binary (`imports_r2`).
+
+use std::collections::HashMap;
+
+use libsmt::backends::z3;
+use libsmt::logics::qf_abv;
+use libsmt::theories::{bitvec, core};
+use r2pipe::r2::R2;
+use rustc_serialize::json::Json;
+
+use context::callconv::{offset_addr, CallConv};
+use context::context::{Context, MemoryRead, MemoryWrite, RegisterRead};
+use context::event::{self, PathEvent};
+use context::region::Perms;
+use engine::engine::EngineResult;
+use engine::rune::RuneControl;
+use engine::syscall::{concretize, map};
+
+type VarRef<Ctx> = <Ctx as RegisterRead>::VarRef;
+
+/// Number of bytes of a string that summaries look at. Longer strings are cut short.
+pub const MAX_STRLEN: u64 = 64;
+
+/// Number of bytes that `memcpy`, `memmove` and `memset` handle at most.
+pub const MAX_MEM_SIZE: u64 = 0x1000;
+
+/// Largest size of an allocation whose size is symbolic.
+pub const MAX_SYMBOLIC_SIZE: u64 = 0x1000;
+
+/// Allocations larger than this fail.
+pub const MAX_ALLOC_SIZE: u64 = 0x1000_0000;
+
+pub trait Summary<Ctx: Context> {
+    /// Apply the effect of the function to `ctx`, which is at the entry of the function.
+    /// Returning `RuneControl::Continue` returns to the caller.
+    fn call(&mut self, ctx: &mut Ctx, cc: &CallConv) -> EngineResult<RuneControl>;
+
+    /// Number of arguments of the function, removed from the stack on return by conventions in
+    /// which the callee cleans up.
+    fn nargs(&self) -> usize {
+        0
+    }
+}
+
+impl<Ctx, F> Summary<Ctx> for F
+    where Ctx: Context,
+          F: FnMut(&mut Ctx, &CallConv) -> EngineResult<RuneControl>
+{
+    fn call(&mut self, ctx: &mut Ctx, cc: &CallConv) -> EngineResult<RuneControl> {
+        self(ctx, cc)
+    }
+}
+
+/// Summaries of libc functions.
+///
+/// - `strlen` and `strcmp` look at up to `MAX_STRLEN` bytes, which may be symbolic.
+/// - `memcpy`, `memmove` and `memset` handle as many bytes as one feasible value of their size.
+///   Symbolic sizes are constrained to at most `MAX_MEM_SIZE`, larger constant sizes are cut
+///   short.
+/// - `malloc` and `free` manage the allocations of the `Heap` of the context. Symbolic sizes are
+///   constrained to at most `MAX_SYMBOLIC_SIZE`, and allocations larger than `MAX_ALLOC_SIZE`
+///   fail. Freeing memory that is not allocated is recorded as a `PathEvent::DoubleFree` or
+///   `PathEvent::InvalidFree`, and the path continues with the pointers that can be freed, if
+///   there are any.
+/// - `puts` and `printf` discard their output and return a fresh symbol, named
+///   `<function>_<n>`.
+/// - `exit` terminates the path, recording a `PathEvent::Exit`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Libc {
+    Strlen,
+    Strcmp,
+    Memcpy,
+    Memset,
+    Malloc,
+    Free,
+    Puts,
+    Printf,
+    Exit,
+}
+
+impl Libc {
+    /// The summaries, along with the names of the functions they are registered for.
+    pub fn all() -> Vec<(&'static str, Libc)> {
+        vec![("strlen", Libc::Strlen),
+             ("strcmp", Libc::Strcmp),
+             ("memcpy", Libc::Memcpy),
+             // Bytes are all read before they are written.
+             ("memmove", Libc::Memcpy),
+             ("memset", Libc::Memset),
+             ("malloc", Libc::Malloc),
+             ("free", Libc::Free),
+             ("puts", Libc::Puts),
+             ("printf", Libc::Printf),
+             ("exit", Libc::Exit),
+             ("_exit", Libc::Exit)]
+    }
+}
+
+impl<Ctx> Summary<Ctx> for Libc
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    fn nargs(&self) -> usize {
+        match *self {
+            Libc::Strcmp => 2,
+            Libc::Memcpy | Libc::Memset => 3,
+            // The format arguments of printf are removed by the caller.
+            Libc::Strlen | Libc::Malloc | Libc::Free | Libc::Puts | Libc::Printf | Libc::Exit => 1,
+        }
+    }
+
+    fn call(&mut self, ctx: &mut Ctx, cc: &CallConv) -> EngineResult<RuneControl> {
+        let width = cc.word_size();
+        let ret = match *self {
+            Libc::Strlen => {
+                let s = try!(cc.arg(ctx, 0));
+                let mut len = ctx.define_const(MAX_STRLEN, width);
+                for i in (0..MAX_STRLEN).rev() {
+                    let byte = try!(read_byte(ctx, s.clone(), i, width));
+                    let is_nul = is_zero(ctx, byte);
+                    let i_ = ctx.define_const(i, width);
+                    len = ctx.eval(core::OpCodes::ITE, vec![is_nul, i_, len]);
+                }
+                len
+            }
+            Libc::Strcmp => {
+                let a = try!(cc.arg(ctx, 0));
+                let b = try!(cc.arg(ctx, 1));
+                // The difference of the first bytes that differ, or zero if there are none.
+                let mut diff = ctx.define_const(0, width);
+                for i in (0..MAX_STRLEN).rev() {
+                    let x = try!(read_byte(ctx, a.clone(), i, width));
+                    let y = try!(read_byte(ctx, b.clone(), i, width));
+                    let x_ = zero_extend(ctx, x.clone(), width);
+                    let y_ = zero_extend(ctx, y.clone(), width);
+                    let d = ctx.eval(bitvec::OpCodes::BvSub, vec![x_, y_]);
+                    let eq = ctx.eval(core::OpCodes::Cmp, vec![x.clone(), y]);
+                    let is_nul = is_zero(ctx, x);
+                    let zero = ctx.define_const(0, width);
+                    let rest = ctx.eval(core::OpCodes::ITE, vec![is_nul, zero, diff]);
+                    diff = ctx.eval(core::OpCodes::ITE, vec![eq, rest, d]);
+                }
+                diff
+            }
+            Libc::Memcpy => {
+                let dst = try!(cc.arg(ctx, 0));
+                let src = try!(cc.arg(ctx, 1));
+                let n = try!(cc.arg(ctx, 2));
+                let n = try!(mem_size(ctx, n, width));
+                let mut bytes = Vec::new();
+                for i in 0..n {
+                    bytes.push(try!(read_byte(ctx, src.clone(), i, width)));
+                }
+                for (i, byte) in bytes.into_iter().enumerate() {
+                    let addr = offset_addr(ctx, dst.clone(), i as u64, width);
+                    try!(ctx.try_mem_write(addr, byte, 8));
+                }
+                dst
+            }
+            Libc::Memset => {
+                let s = try!(cc.arg(ctx, 0));
+                let c = try!(cc.arg(ctx, 1));
+                let n = try!(cc.arg(ctx, 2));
+                let n = try!(mem_size(ctx, n, width));
+                let byte = ctx.eval(bitvec::OpCodes::Extract(7, 0), vec![c]);
+                for i in 0..n {
+                    let addr = offset_addr(ctx, s.clone(), i, width);
+                    try!(ctx.try_mem_write(addr, byte.clone(), 8));
+                }
+                s
+            }
+            Libc::Malloc => {
+                let size = try!(cc.arg(ctx, 0));
+                let max_size = match ctx.const_value(&size) {
+                    Some(size) => size,
+                    None => {
+                        let max = ctx.define_const(MAX_SYMBOLIC_SIZE, width);
+                        ctx.eval(bitvec::OpCodes::BvULe, vec![size.clone(), max]);
+                        MAX_SYMBOLIC_SIZE
+                    }
+                };
+                if max_size > MAX_ALLOC_SIZE {
+                    ctx.define_const(0, width)
+                } else {
+                    let ptr = ctx.heap_mut().alloc(size, max_size);
+                    let span = ctx.heap().find(ptr).map_or(0, |alloc| alloc.span);
+                    map(ctx, "[heap]", ptr, span, Perms::new(true, true, false));
+                    ctx.define_const(ptr, width)
+                }
+            }
+            Libc::Free => {
+                let ptr = try!(cc.arg(ctx, 0));
+                return free(ctx, ptr, width);
+            }
+            Libc::Puts | Libc::Printf => {
+                let name = if *self == Libc::Puts { "puts" } else { "printf" };
+                let n = ctx.symbols().len();
+                ctx.define_sym(format!("{}_{}", name, n), width)
+            }
+            Libc::Exit => {
+                let status = try!(cc.arg(ctx, 0));
+                let addr = ctx.ip();
+                let status = ctx.const_value(&status);
+                ctx.record_event(PathEvent::Exit {
+                    addr: addr,
+                    status: status,
+                });
+                return Ok(RuneControl::TerminatePath);
+            }
+        };
+        try!(cc.set_return(ctx, ret));
+        Ok(RuneControl::Continue)
+    }
+}
+
+// Number of bytes handled by `memcpy` and `memset` for the size `n`, at most `MAX_MEM_SIZE`.
+fn mem_size<Ctx>(ctx: &mut Ctx, n: VarRef<Ctx>, width: usize) -> EngineResult<u64>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    if let Some(n) = ctx.const_value(&n) {
+        return Ok(::std::cmp::min(n, MAX_MEM_SIZE));
+    }
+    let max = ctx.define_const(MAX_MEM_SIZE, width);
+    ctx.eval(bitvec::OpCodes::BvULe, vec![n.clone(), max]);
+    concretize(ctx, n, width)
+}
+
+// Free the allocation at `ptr`, reporting pointers that cannot be freed.
+fn free<Ctx>(ctx: &mut Ctx, ptr: VarRef<Ctx>, width: usize) -> EngineResult<RuneControl>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let addr = ctx.ip();
+    let (mut valid, dead) = {
+        let allocs = ctx.heap().allocations();
+        (allocs.iter().filter(|a| a.live).map(|a| a.base).collect::<Vec<_>>(),
+         allocs.iter().filter(|a| !a.live).map(|a| a.base).collect::<Vec<_>>())
+    };
+    // `free(NULL)` does nothing.
+    valid.push(0);
+
+    if !dead.is_empty() {
+        let mut double = ctx.clone();
+        is_one_of(&mut double, ptr.clone(), &dead, width);
+        if let Some((ptr, model)) = witness(&mut double, ptr.clone(), width) {
+            ctx.record_event(PathEvent::DoubleFree {
+                addr: addr,
+                ptr: ptr,
+                model: model,
+            });
+        }
+    }
+    let mut invalid = ctx.clone();
+    let known = valid.iter().chain(dead.iter()).cloned().collect::<Vec<_>>();
+    let is_known = is_one_of(&mut invalid, ptr.clone(), &known, width);
+    invalid.eval(core::OpCodes::Not, vec![is_known]);
+    if let Some((ptr, model)) = witness(&mut invalid, ptr.clone(), width) {
+        ctx.record_event(PathEvent::InvalidFree {
+            addr: addr,
+            ptr: ptr,
+            model: model,
+        });
+    }
+
+    is_one_of(ctx, ptr.clone(), &valid, width);
+    let mut z3: z3::Z3 = Default::default();
+    if !ctx.check_sat(&mut z3) {
+        return Ok(RuneControl::TerminatePath);
+    }
+    let ptr = try!(concretize(ctx, ptr, width));
+    if ptr != 0 {
+        ctx.heap_mut().free(ptr);
+    }
+    Ok(RuneControl::Continue)
+}
+
+// Build the condition that `v` is one of `values`. `values` must not be empty.
+fn is_one_of<Ctx>(ctx: &mut Ctx, v: VarRef<Ctx>, values: &[u64], width: usize) -> VarRef<Ctx>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let mut cond = None;
+    for value in values {
+        let value = ctx.define_const(*value, width);
+        let eq = ctx.eval(core::OpCodes::Cmp, vec![v.clone(), value]);
+        cond = Some(match cond {
+            Some(cond) => ctx.eval(core::OpCodes::Or, vec![cond, eq]),
+            None => eq,
+        });
+    }
+    cond.expect("No values to compare with")
+}
+
+// A value of `v` if `ctx` is satisfiable, along with the values of the named symbols.
+fn witness<Ctx>(ctx: &mut Ctx, v: VarRef<Ctx>, width: usize) -> Option<(u64, Vec<(String, u64)>)>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let var = ctx.define_var("rune_value", width);
+    ctx.eval(core::OpCodes::Cmp, vec![var.clone(), v]);
+    let mut z3: z3::Z3 = Default::default();
+    if !ctx.check_sat(&mut z3) {
+        return None;
+    }
+    let mut z3: z3::Z3 = Default::default();
+    match ctx.try_solve(&mut z3) {
+        Ok(model) => model.get(&var).map(|v| (*v, event::named_values(ctx, &model))),
+        Err(_) => None,
+    }
+}
+
+fn read_byte<Ctx>(ctx: &mut Ctx,
+                  s: VarRef<Ctx>,
+                  i: u64,
+                  width: usize)
+                  -> EngineResult<VarRef<Ctx>>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let addr = offset_addr(ctx, s, i, width);
+    Ok(try!(ctx.try_mem_read(addr, 8)))
+}
+
+fn is_zero<Ctx>(ctx: &mut Ctx, byte: VarRef<Ctx>) -> VarRef<Ctx>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let zero = ctx.define_const(0, 8);
+    ctx.eval(core::OpCodes::Cmp, vec![byte, zero])
+}
+
+fn zero_extend<Ctx>(ctx: &mut Ctx, byte: VarRef<Ctx>, width: usize) -> VarRef<Ctx>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    let zeros = ctx.define_const(0, width - 8);
+    ctx.eval(bitvec::OpCodes::Concat, vec![zeros, byte])
+}
+
+/// Summaries by the name of the function, and the addresses of the functions.
+pub struct SummaryManager<Ctx: Context> {
+    summaries: HashMap<String, Box<Summary<Ctx>>>,
+    targets: HashMap<u64, String>,
+}
+
+impl<Ctx: Context> Default for SummaryManager<Ctx> {
+    fn default() -> SummaryManager<Ctx> {
+        SummaryManager::new()
+    }
+}
+
+impl<Ctx: Context> SummaryManager<Ctx> {
+    pub fn new() -> SummaryManager<Ctx> {
+        SummaryManager {
+            summaries: HashMap::new(),
+            targets: HashMap::new(),
+        }
+    }
+
+    /// Register the summary of the function `name`, replacing any previous one.
+    pub fn add<T, S>(&mut self, name: T, summary: S)
+        where T: AsRef<str>,
+              S: Summary<Ctx> + 'static
+    {
+        self.summaries.insert(name.as_ref().to_owned(), Box::new(summary));
+    }
+
+    pub fn remove<T: AsRef<str>>(&mut self, name: T) -> bool {
+        self.summaries.remove(name.as_ref()).is_some()
+    }
+
+    /// Declare that the function `name` is at `addr`.
+    pub fn add_target<T: AsRef<str>>(&mut self, addr: u64, name: T) {
+        self.targets.insert(addr, name.as_ref().to_owned());
+    }
+
+    /// Name of the function at `addr`, if it has a summary.
+    pub fn target(&self, addr: u64) -> Option<&str> {
+        match self.targets.get(&addr) {
+            Some(name) if self.summaries.contains_key(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Number of arguments of the function `name`, 0 if it has no summary.
+    pub fn nargs(&self, name: &str) -> usize {
+        self.summaries.get(name).map_or(0, |summary| summary.nargs())
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Box<Summary<Ctx>>> {
+        self.summaries.get_mut(name)
+    }
+}
+
+impl<Ctx> SummaryManager<Ctx>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    /// A manager holding the summaries of `Libc`.
+    pub fn libc() -> SummaryManager<Ctx> {
+        let mut manager = SummaryManager::new();
+        for (name, summary) in Libc::all() {
+            manager.add(name, summary);
+        }
+        manager
+    }
+}
+
+/// Addresses (in the PLT) and names of the functions imported by the binary loaded in radare2
+/// (`iij`).
+pub fn imports_r2(r2: &mut R2) -> Result<Vec<(u64, String)>, String> {
+    r2.send("iij");
+    let imports = try!(Json::from_str(&r2.recv()).map_err(|e| e.to_string()));
+    let imports = match imports.find("imports").and_then(|i| i.as_array()) {
+        Some(imports) => imports.clone(),
+        None => try!(imports.as_array().cloned().ok_or("Unexpected import info")),
+    };
+    Ok(imports.iter()
+              .filter_map(|import| {
+                  let name = import.find("name").and_then(|v| v.as_string());
+                  let plt = import.find("plt").and_then(|v| v.as_u64());
+                  match (name, plt) {
+                      (Some(name), Some(plt)) if plt != 0 => Some((plt, name.to_owned())),
+                      _ => None,
+                  }
+              })
+              .collect())
+}
@@ -0,0 +1,380 @@
+//! System calls.
+//!
+//! The esil interrupt operator (`$`), emitted for `syscall` and `int 0x80`, is handed to the
+//! `SyscallHandler` of the engine. The default handler, `LinuxSyscalls`, models a few common
+//! Linux system calls on x86 and x86-64. Any other handler can be installed with
+//! `Rune::set_syscalls`. System calls that the handler does not know are dealt with according
+//! to `RuneConfig::unknown_syscall`.
+//!
+//! State that system calls keep across a path, such as the program break and the open file
+//! descriptors, is held in the `SysState` of the context (`context::sys`).
+
+use libsmt::backends::z3;
+use libsmt::logics::qf_abv;
+use libsmt::theories::core;
+
+use context::arch::{Arch, ArchInfo};
+use context::callconv::offset_addr;
+use context::context::{Context, ContextError, MemoryWrite, RegisterRead, RegisterWrite};
+use context::event::PathEvent;
+use context::heap::HEAP_BASE;
+use context::region::{MemRegion, Perms};
+use context::sys::SysState;
+use engine::engine::EngineResult;
+use engine::rune::RuneControl;
+
+type VarRef<Ctx> = <Ctx as RegisterRead>::VarRef;
+
+const EBADF: u64 = 9;
+const ENOMEM: u64 = 12;
+const EEXIST: u64 = 17;
+const EINVAL: u64 = 22;
+/// Returned for unknown system calls under `SyscallPolicy::Enosys`.
+pub const ENOSYS: u64 = 38;
+
+const PAGE_SIZE: u64 = 0x1000;
+
+/// Handling of system calls that the handler does not know. Every such system call is recorded
+/// as a `PathEvent::UnknownSyscall`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SyscallPolicy {
+    /// Terminate the path with an error.
+    Stop,
+    /// Fail the system call with `ENOSYS` and continue.
+    Enosys,
+    /// Give the return value a fresh symbolic value and continue.
+    Havoc,
+}
+
+/// System calls modelled by `LinuxSyscalls`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sys {
+    Read,
+    Write,
+    Open,
+    Close,
+    Mmap,
+    Brk,
+    Exit,
+    ExitGroup,
+    Getrandom,
+}
+
+const X86_64_TABLE: &'static [(u64, Sys)] = &[(0, Sys::Read),
+                                              (1, Sys::Write),
+                                              (2, Sys::Open),
+                                              (3, Sys::Close),
+                                              (9, Sys::Mmap),
+                                              (12, Sys::Brk),
+                                              (60, Sys::Exit),
+                                              (231, Sys::ExitGroup),
+                                              (318, Sys::Getrandom)];
+
+// `mmap2` (192) takes its offset in pages rather than bytes, which makes no difference here.
+const X86_TABLE: &'static [(u64, Sys)] = &[(1, Sys::Exit),
+                                           (3, Sys::Read),
+                                           (4, Sys::Write),
+                                           (5, Sys::Open),
+                                           (6, Sys::Close),
+                                           (45, Sys::Brk),
+                                           (192, Sys::Mmap),
+                                           (252, Sys::ExitGroup),
+                                           (355, Sys::Getrandom)];
+
+/// Registers and numbering of a system call interface.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SyscallAbi {
+    /// Register holding the number of the system call.
+    pub number: &'static str,
+    pub args: [&'static str; 6],
+    pub ret: &'static str,
+    table: &'static [(u64, Sys)],
+}
+
+impl SyscallAbi {
+    pub fn x86_64() -> SyscallAbi {
+        SyscallAbi {
+            number: "rax",
+            args: ["rdi", "rsi", "rdx", "r10", "r8", "r9"],
+            ret: "rax",
+            table: X86_64_TABLE,
+        }
+    }
+
+    /// `int 0x80`, which 64-bit code can use as well.
+    pub fn x86() -> SyscallAbi {
+        SyscallAbi {
+            number: "eax",
+            args: ["ebx", "ecx", "edx", "esi", "edi", "ebp"],
+            ret: "eax",
+            table: X86_TABLE,
+        }
+    }
+
+    /// Interface used by Linux for the interrupt `interrupt` (the operand of the esil `$`
+    /// operator, if known) on `arch`. radare2 emits `0,$` for `syscall` and `128,$` for
+    /// `int 0x80`. Other interrupts are not system calls.
+    pub fn linux(arch: &ArchInfo, interrupt: Option<u64>) -> Option<SyscallAbi> {
+        match (arch.arch, arch.bits, interrupt) {
+            (Arch::X86, 32, Some(0x80)) |
+            (Arch::X86, 64, Some(0x80)) => Some(SyscallAbi::x86()),
+            (Arch::X86, 64, Some(0)) |
+            (Arch::X86, 64, None) => Some(SyscallAbi::x86_64()),
+            _ => None,
+        }
+    }
+
+    /// The system call with number `number`, if it is modelled.
+    pub fn sys(&self, number: u64) -> Option<Sys> {
+        self.table.iter().find(|&&(n, _)| n == number).map(|&(_, sys)| sys)
+    }
+
+    /// Read the `n`th argument.
+    pub fn arg<Ctx: Context>(&self, ctx: &mut Ctx, n: usize) -> EngineResult<VarRef<Ctx>> {
+        Ok(try!(ctx.try_reg_read(self.args[n])))
+    }
+
+    /// Set the return value to `value`. Errors are returned as `-errno`.
+    pub fn set_return<Ctx: Context>(&self, ctx: &mut Ctx, value: u64) -> EngineResult<()> {
+        let width = try!(self.ret_width(ctx));
+        let mask = if width >= 64 { u64::max_value() } else { (1 << width) - 1 };
+        let value = ctx.define_const(value & mask, width);
+        Ok(try!(ctx.try_reg_write(self.ret, value)))
+    }
+
+    pub fn set_return_var<Ctx: Context>(&self,
+                                        ctx: &mut Ctx,
+                                        value: VarRef<Ctx>)
+                                        -> EngineResult<()> {
+        Ok(try!(ctx.try_reg_write(self.ret, value)))
+    }
+
+    pub fn ret_width<Ctx: Context>(&self, ctx: &Ctx) -> EngineResult<usize> {
+        Ok(try!(ctx.reg_width(self.ret)
+                   .ok_or_else(|| ContextError::UnknownRegister(self.ret.to_owned()))))
+    }
+}
+
+/// Outcome of a system call.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Syscall {
+    /// The system call was executed, and the path continues as directed.
+    Done(RuneControl),
+    /// The handler does not know the system call with this number.
+    Unknown(u64),
+}
+
+pub trait SyscallHandler<Ctx: Context> {
+    /// Execute the system call requested by `ctx`, through `abi`. `addr` is the address of the
+    /// instruction making the call.
+    fn syscall(&mut self, ctx: &mut Ctx, abi: &SyscallAbi, addr: u64) -> EngineResult<Syscall>;
+}
+
+/// Linux system calls.
+///
+/// - `read` fills the buffer with fresh symbols, named `stdin[<i>]` for standard input and
+///   `fd<n>[<i>]` for other files, where `i` is the offset in the file. At most `max_read`
+///   bytes are returned.
+/// - `write` discards its output.
+/// - `open` and `close` hand out and release file descriptors. The file that is opened is not
+///   looked at.
+/// - `mmap` and `brk` allocate memory at the addresses in `SysState`. If the context has memory
+///   regions, the allocated memory is added to them. The program break does not grow past
+///   `HEAP_BASE`, where the heap of the `malloc` summary starts. A `MAP_FIXED` mapping that
+///   overlaps a region of the context fails with `EEXIST`, as the memory it would replace
+///   cannot be unmapped.
+/// - `exit` and `exit_group` terminate the path, recording a `PathEvent::Exit`.
+/// - `getrandom` returns fresh symbols, named `random[<i>]`.
+#[derive(Clone, Debug)]
+pub struct LinuxSyscalls {
+    pub max_read: u64,
+}
+
+impl Default for LinuxSyscalls {
+    fn default() -> LinuxSyscalls {
+        LinuxSyscalls::new()
+    }
+}
+
+impl LinuxSyscalls {
+    pub fn new() -> LinuxSyscalls {
+        LinuxSyscalls { max_read: 256 }
+    }
+}
+
+impl<Ctx> SyscallHandler<Ctx> for LinuxSyscalls
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    fn syscall(&mut self, ctx: &mut Ctx, abi: &SyscallAbi, addr: u64) -> EngineResult<Syscall> {
+        let width = try!(abi.ret_width(ctx));
+        let number = try!(ctx.try_reg_read(abi.number));
+        let number = try!(concretize(ctx, number, width));
+        let sys = match abi.sys(number) {
+            Some(sys) => sys,
+            None => return Ok(Syscall::Unknown(number)),
+        };
+
+        // Only the arguments a system call takes are read, the other registers may be unset.
+        let ret = match sys {
+            Sys::Exit | Sys::ExitGroup => {
+                let status = try!(abi.arg(ctx, 0));
+                let status = ctx.const_value(&status);
+                ctx.record_event(PathEvent::Exit {
+                    addr: addr,
+                    status: status,
+                });
+                return Ok(Syscall::Done(RuneControl::TerminatePath));
+            }
+            Sys::Read | Sys::Getrandom => {
+                let (fd, buf, count) = if sys == Sys::Read {
+                    let fd = try!(abi.arg(ctx, 0));
+                    let fd = try!(concretize(ctx, fd, width));
+                    (Some(fd), try!(abi.arg(ctx, 1)), try!(abi.arg(ctx, 2)))
+                } else {
+                    (None, try!(abi.arg(ctx, 0)), try!(abi.arg(ctx, 1)))
+                };
+                if fd.map_or(false, |fd| !ctx.sys_state().fds.contains(&fd)) {
+                    EBADF.wrapping_neg()
+                } else {
+                    let count = try!(concretize(ctx, count, width));
+                    let count = ::std::cmp::min(count, self.max_read);
+                    let offset = match fd {
+                        Some(fd) => ctx.sys_state().offsets.get(&fd).cloned().unwrap_or(0),
+                        None => ctx.sys_state().random,
+                    };
+                    let name = match fd {
+                        Some(0) => "stdin".to_owned(),
+                        Some(fd) => format!("fd{}", fd),
+                        None => "random".to_owned(),
+                    };
+                    for i in 0..count {
+                        let byte = ctx.define_sym(format!("{}[{}]", name, offset + i), 8);
+                        let addr = offset_addr(ctx, buf.clone(), i, width);
+                        try!(ctx.try_mem_write(addr, byte, 8));
+                    }
+                    match fd {
+                        Some(fd) => {
+                            ctx.sys_state_mut().offsets.insert(fd, offset + count);
+                        }
+                        None => ctx.sys_state_mut().random += count,
+                    }
+                    count
+                }
+            }
+            Sys::Write => {
+                let fd = try!(abi.arg(ctx, 0));
+                let fd = try!(concretize(ctx, fd, width));
+                if ctx.sys_state().fds.contains(&fd) {
+                    let count = try!(abi.arg(ctx, 2));
+                    try!(concretize(ctx, count, width))
+                } else {
+                    EBADF.wrapping_neg()
+                }
+            }
+            Sys::Open => {
+                let fd = (3..).find(|fd| !ctx.sys_state().fds.contains(fd)).unwrap();
+                ctx.sys_state_mut().fds.insert(fd);
+                fd
+            }
+            Sys::Close => {
+                let fd = try!(abi.arg(ctx, 0));
+                let fd = try!(concretize(ctx, fd, width));
+                if ctx.sys_state_mut().fds.remove(&fd) {
+                    0
+                } else {
+                    EBADF.wrapping_neg()
+                }
+            }
+            Sys::Brk => {
+                let target = try!(abi.arg(ctx, 0));
+                let target = try!(concretize(ctx, target, width));
+                let current = ctx.sys_state().brk;
+                if target > current && target <= HEAP_BASE {
+                    map(ctx, "[heap]", current, target - current, Perms::new(true, true, false));
+                    ctx.sys_state_mut().brk = target;
+                }
+                ctx.sys_state().brk
+            }
+            Sys::Mmap => {
+                let mut values = Vec::new();
+                for i in 0..4 {
+                    let arg = try!(abi.arg(ctx, i));
+                    values.push(try!(concretize(ctx, arg, width)));
+                }
+                let (hint, len, prot, flags) = (values[0], values[1], values[2], values[3]);
+                match place_mapping(ctx, hint, len, flags) {
+                    Ok((base, len)) => {
+                        let perms = Perms::new(prot & 1 != 0, prot & 2 != 0, prot & 4 != 0);
+                        map(ctx, "[mmap]", base, len, perms);
+                        base
+                    }
+                    Err(errno) => errno.wrapping_neg(),
+                }
+            }
+        };
+        try!(abi.set_return(ctx, ret));
+        Ok(Syscall::Done(RuneControl::Continue))
+    }
+}
+
+// Address and length, rounded up to whole pages, of a mapping of `len` bytes made by `mmap`, or
+// the error number the call fails with.
+fn place_mapping<Ctx: Context>(ctx: &mut Ctx,
+                               hint: u64,
+                               len: u64,
+                               flags: u64)
+                               -> Result<(u64, u64), u64> {
+    if len == 0 {
+        return Err(EINVAL);
+    }
+    let len = match len.checked_add(PAGE_SIZE - 1) {
+        Some(len) => len & !(PAGE_SIZE - 1),
+        None => return Err(ENOMEM),
+    };
+    // MAP_FIXED
+    if flags & 0x10 != 0 {
+        if hint & (PAGE_SIZE - 1) != 0 {
+            return Err(EINVAL);
+        }
+        if hint.checked_add(len).is_none() {
+            return Err(ENOMEM);
+        }
+        if ctx.regions().iter().any(|r| r.overlaps(hint, len)) {
+            return Err(EEXIST);
+        }
+        return Ok((hint, len));
+    }
+    let base = ctx.sys_state().mmap_base;
+    match base.checked_add(len) {
+        Some(end) => {
+            ctx.sys_state_mut().mmap_base = end;
+            Ok((base, len))
+        }
+        None => Err(ENOMEM),
+    }
+}
+
+/// Add the region to the context, if it keeps track of regions.
+pub fn map<Ctx: Context>(ctx: &mut Ctx, name: &str, base: u64, size: u64, perms: Perms) {
+    if !ctx.regions().is_empty() {
+        ctx.add_region(MemRegion::new(name, base, size, perms));
+    }
+}
+
+/// The value of `v`, a value of `width` bits. If `v` is not a constant, it is constrained to
+/// one of its feasible values.
+pub fn concretize<Ctx>(ctx: &mut Ctx, v: VarRef<Ctx>, width: usize) -> EngineResult<u64>
+    where Ctx: Context<IFn = qf_abv::QF_ABV_Fn>
+{
+    if let Some(value) = ctx.const_value(&v) {
+        return Ok(value);
+    }
+    let mut model = ctx.clone();
+    let var = model.define_var("concrete_value", width);
+    model.eval(core::OpCodes::Cmp, vec![var.clone(), v.clone()]);
+    let mut z3: z3::Z3 = Default::default();
+    let value = try!(try!(model.try_solve(&mut z3)).get(&var).cloned().ok_or(ContextError::Unsat));
+    let value_ = ctx.define_const(value, width);
+    ctx.eval(core::OpCodes::Cmp, vec![v, value_]);
+    Ok(value)
+}
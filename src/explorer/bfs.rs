@@ -10,6 +10,8 @@ use context::context::{Context, RegisterRead};
 enum BranchType {
     True,
     False,
+    /// One of the targets of a symbolic jump.
+    Target,
 }
 
 #[derive(Clone, Debug)]
@@ -65,6 +67,7 @@ where Ctx: Context {
                 match state.branch {
                     BranchType::True => RuneControl::ExploreTrue,
                     BranchType::False => RuneControl::ExploreFalse,
+                    BranchType::Target => RuneControl::Continue,
                 })
         } else {
             None
@@ -86,4 +89,13 @@ where Ctx: Context {
         // to load a new job from the queue.
         RuneControl::TerminatePath
     }
+
+    fn register_targets(&mut self, _: &mut Self::Ctx, targets: Vec<Self::Ctx>) -> RuneControl {
+        // Every target is a new state at the current depth. Queue them all up and move on to the
+        // next state in the queue.
+        for target in targets {
+            self.queue.push_back(SavedState::new(target, BranchType::Target));
+        }
+        RuneControl::TerminatePath
+    }
 }
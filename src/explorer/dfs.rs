@@ -14,6 +14,8 @@ use context::rune_ctx::RuneContext;
 enum BranchType {
     True,
     False,
+    /// One of the targets of a symbolic jump.
+    Target,
 }
 
 #[derive(Clone, Debug)]
@@ -61,6 +63,7 @@ impl PathExplorer for DFSExplorer<RuneContext> {
             Some(match state.branch {
                 BranchType::True => RuneControl::ExploreTrue,
                 BranchType::False => RuneControl::ExploreFalse,
+                BranchType::Target => RuneControl::Continue,
             })
         } else {
             None
@@ -78,7 +81,7 @@ impl PathExplorer for DFSExplorer<RuneContext> {
         // other part without changing the nature of this explorer.
         let mut false_ctx = ctx.clone();
         {
-            let zero = ctx.define_const(0, 1);
+            let zero = false_ctx.define_const(0, 1);
             false_ctx.eval(core::OpCodes::Cmp, &[condition, zero]);
         }
         self.queue.push_back(SavedState::new(false_ctx, BranchType::False));
@@ -88,4 +91,21 @@ impl PathExplorer for DFSExplorer<RuneContext> {
         }
         RuneControl::ExploreTrue
     }
+
+    fn register_targets(&mut self,
+                        ctx: &mut Self::Ctx,
+                        mut targets: Vec<Self::Ctx>)
+                        -> RuneControl {
+        // Explore the first target and push the rest in reverse so that they are explored in
+        // the order in which they were found.
+        if targets.is_empty() {
+            return RuneControl::TerminatePath;
+        }
+        let first = targets.remove(0);
+        for target in targets.into_iter().rev() {
+            self.queue.push_back(SavedState::new(target, BranchType::Target));
+        }
+        *ctx = first;
+        RuneControl::Continue
+    }
 }
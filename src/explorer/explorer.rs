@@ -13,5 +13,12 @@ pub trait PathExplorer {
     fn next(&mut self, &mut Self::Ctx) -> Self::C;
     fn next_job(&mut self, &mut Self::Ctx) -> Option<Self::C>;
 
+    /// Called on a conditional in the esil. The condition is a 1 bit value, the true branch is
+    /// taken when it is 1.
     fn register_branch(&mut self, &mut Self::Ctx, <Self::Ctx as RegisterRead>::VarRef) -> Self::C;
+
+    /// Called when the program counter is written with a value that can take more than one
+    /// value. Every context in `targets` has its instruction pointer set to one of the feasible
+    /// targets and the constraint selecting that target added.
+    fn register_targets(&mut self, &mut Self::Ctx, Vec<Self::Ctx>) -> Self::C;
 }
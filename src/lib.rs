@@ -15,8 +15,18 @@ extern crate regex;
 #[macro_use] extern crate libsmt;
 
 pub mod context {
+    pub mod arch;
+    pub mod builder;
+    pub mod callconv;
     pub mod context;
+    pub mod event;
+    pub mod frame;
+    pub mod heap;
+    pub mod image;
+    pub mod reg_profile;
+    pub mod region;
     pub mod rune_ctx;
+    pub mod sys;
     pub mod utils;
 }
 
@@ -31,6 +41,10 @@ pub mod engine {
     pub mod rune;
     pub mod hook;
     pub mod breakpt;
+    pub mod esil_vars;
+    pub mod esil_signed;
+    pub mod syscall;
+    pub mod summary;
 }
 
 pub mod stream;